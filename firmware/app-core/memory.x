@@ -5,6 +5,7 @@ MEMORY
     /* We have 1024K flash, the first 256K are available single-cycle */
     FLASH           : ORIGIN = 0x00000000, LENGTH = 256K
     /* Put the 'shared flash' in the last 256K of application core flash */
+    /* Starts with the settings store, see `SETTINGS_START` in `main.rs` */
     SHARED_FLASH    : ORIGIN = 0x000C0000, LENGTH = 256K
     RAM             : ORIGIN = 0x20000000, LENGTH = 256K
     SHARED_RAM      : ORIGIN = 0x20040000, LENGTH = 256K
@@ -13,7 +14,11 @@ MEMORY
 SECTIONS {
 
     .shared_ram (NOLOAD) : {
+        /* Checked by both cores at boot, see `common::shared_ram` */
+        KEEP(*(.shared_ram.header))
         *(.shared_ram)
         *(.shared_ram.*)
     } > SHARED_RAM
 }
+
+ASSERT(SHARED_RAM_HEADER == ORIGIN(SHARED_RAM), "the shared RAM header has to be at the start of SHARED_RAM")
@@ -0,0 +1,195 @@
+//! Reads the samples of the front-end into blocks. While streaming they're handed to the network
+//! core, decimated first if the stream rate is lower than the sample rate. In the impedance mode
+//! the response of the electrodes to the test current is measured on them instead.
+
+use crate::afe::{self, Afe};
+use crate::signal::IpcSignal;
+use common::block_buffer::BlockWriter;
+use common::contact::{self, ContactMeter};
+use common::decimate::{self, BlockDecimator};
+use common::ipc::{Payload, SampleBlock, BLOCK_FRAMES, SAMPLE_BLOCK_COUNT};
+use common::proto::to_edge_capnp::Gain;
+use common::{CHANNEL_COUNT, SAMPLE_RATES};
+use core::sync::atomic::{AtomicBool, Ordering};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+
+const CHANNELS: usize = CHANNEL_COUNT as usize;
+
+pub type Blocks = BlockWriter<'static, SampleBlock, SAMPLE_BLOCK_COUNT, IpcSignal>;
+
+/// What the front-end should be doing, replaced as commands change it
+pub static CONFIG: Signal<CriticalSectionRawMutex, Config> = Signal::new();
+/// Whether the network core has a host to stream to, as last asked. Until it's known, blocks are
+/// handed over as if there was one.
+pub static HOST_CONNECTED: AtomicBool = AtomicBool::new(true);
+/// `ContactQuality` messages to send to the host, a measurement makes one per channel
+pub static CONTACTS: Channel<CriticalSectionRawMutex, Payload, CHANNELS> = Channel::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub sample_rate: u32,
+    pub gains: [Gain; CHANNELS],
+    /// Rate sample frames are streamed at (Hz), 0 for every sample
+    pub stream_rate: u32,
+    /// Sample frames are sent to the host
+    pub streaming: bool,
+    /// The test current is on and contact is measured, no sample frames are sent
+    pub impedance_mode: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATES[0],
+            gains: [Gain::X24; CHANNELS],
+            stream_rate: 0,
+            streaming: false,
+            impedance_mode: false,
+        }
+    }
+}
+
+impl Config {
+    /// The front-end only converts while something uses the samples
+    fn running(&self) -> bool {
+        self.streaming || self.impedance_mode
+    }
+
+    fn afe(&self) -> afe::Config {
+        afe::Config {
+            sample_rate: self.sample_rate,
+            gains: self.gains,
+            test_current: self.impedance_mode,
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn run(mut afe: Afe, mut blocks: Blocks) {
+    let mut meter = ContactMeter::new();
+    // Carried across reconfigurations, so that the host sees the samples counted on
+    let mut sample_counter = 0;
+    let mut config = CONFIG.wait().await;
+    loop {
+        if !config.running() {
+            if let Err(error) = afe.stop().await {
+                defmt::error!("Couldn't stop the front-end: {}", error);
+            }
+            config = CONFIG.wait().await;
+            continue;
+        }
+        meter.reset();
+        config = match acquire(
+            &mut afe,
+            &mut blocks,
+            &mut meter,
+            &config,
+            &mut sample_counter,
+        )
+        .await
+        {
+            Ok(changed) => changed,
+            Err(error) => {
+                defmt::error!("Front-end failed: {}", error);
+                // Nothing to do until told otherwise, e.g. with a supported sample rate
+                let _ = afe.stop().await;
+                CONFIG.wait().await
+            }
+        };
+    }
+}
+
+/// Converts with `config` until it changes, and returns the new one. Samples are counted on from
+/// `sample_counter`, which is left at the next one to be read, also if the front-end fails.
+async fn acquire(
+    afe: &mut Afe,
+    blocks: &mut Blocks,
+    meter: &mut ContactMeter,
+    config: &Config,
+    sample_counter: &mut u64,
+) -> Result<Config, afe::Error> {
+    afe.configure(&config.afe()).await?;
+    afe.start().await?;
+    defmt::info!(
+        "Front-end converting at {} Hz, impedance mode {}",
+        config.sample_rate,
+        config.impedance_mode
+    );
+
+    let mut block = SampleBlock {
+        sample_rate: config.sample_rate,
+        channel_count: CHANNEL_COUNT,
+        ..SampleBlock::default()
+    };
+    let mut decimator = match decimate::factor(config.sample_rate, config.stream_rate) {
+        1 => None,
+        factor => Some(BlockDecimator::new(factor)),
+    };
+    let mut frame = [0; CHANNELS];
+    loop {
+        let lead_off = afe.read_frame(&mut frame).await?;
+        if block.frames == 0 {
+            block.timestamp = crate::CLOCK.now();
+            block.sample_counter = *sample_counter;
+            block.lead_off = 0;
+        }
+        let start = usize::from(block.frames) * CHANNELS;
+        block.samples[start..start + CHANNELS].copy_from_slice(&frame);
+        block.lead_off |= u32::from(lead_off);
+        block.frames += 1;
+        *sample_counter += 1;
+        if usize::from(block.frames) == BLOCK_FRAMES {
+            if config.impedance_mode {
+                measure(meter, &block, &config.gains);
+            } else {
+                stream(blocks, &mut decimator, &block);
+            }
+            block.frames = 0;
+        }
+        // Only between frames, so that a transfer is never cut short
+        if let Some(changed) = CONFIG.try_take() {
+            // The partial block still goes out, the measurement starts over anyway
+            if block.frames > 0 && !config.impedance_mode {
+                stream(blocks, &mut decimator, &block);
+            }
+            return Ok(changed);
+        }
+    }
+}
+
+/// Hands `block` to the network core through `decimator`, if there is one
+fn stream(blocks: &mut Blocks, decimator: &mut Option<BlockDecimator>, block: &SampleBlock) {
+    match decimator {
+        Some(decimator) => decimator.push(block, |decimated| hand_over(blocks, decimated)),
+        None => hand_over(blocks, block),
+    }
+}
+
+/// Hands `block` to the network core, to be streamed. It's dropped if the network core is still
+/// busy with all of the others, which counts as an overrun, or has no host to stream to.
+fn hand_over(blocks: &mut Blocks, block: &SampleBlock) {
+    if !HOST_CONNECTED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(mut shared) = blocks.acquire() {
+        *shared = block.clone();
+        shared.commit();
+    }
+}
+
+/// Adds `block` to the measurement, and queues the contact of every channel once it's done
+fn measure(meter: &mut ContactMeter, block: &SampleBlock, gains: &[Gain; CHANNELS]) {
+    let Some(measurement) = meter.push(block) else {
+        return;
+    };
+    for (index, &gain) in gains.iter().enumerate() {
+        let contact = measurement.contact(index, index as u8, contact::microvolts_per_count(gain));
+        let payload = Payload::encode(|out| meter.encode(&contact, out).ok_or(()));
+        // Dropped if the main loop fell behind, the next measurement follows shortly
+        if let Ok(payload) = payload {
+            let _ = CONTACTS.try_send(payload);
+        }
+    }
+}
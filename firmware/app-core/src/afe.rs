@@ -0,0 +1,215 @@
+//! Driver of the ADS1299 analog front-end, on SPI with its data ready line.
+//!
+//! Registers are only written while the front-end isn't converting. Once started it converts
+//! continuously, and each frame of samples is read out as soon as data ready goes low.
+
+use common::proto::to_edge_capnp::Gain;
+use common::CHANNEL_COUNT;
+use embassy_nrf::gpio::{Input, Output};
+use embassy_nrf::spim::{self, Spim};
+use embassy_time::Timer;
+
+const CHANNELS: usize = CHANNEL_COUNT as usize;
+
+// Opcodes
+const RESET: u8 = 0x06;
+const START: u8 = 0x08;
+const STOP: u8 = 0x0A;
+const RDATAC: u8 = 0x10;
+const SDATAC: u8 = 0x11;
+const RREG: u8 = 0x20;
+const WREG: u8 = 0x40;
+
+// Registers
+const ID: u8 = 0x00;
+const CONFIG1: u8 = 0x01;
+const CONFIG2: u8 = 0x02;
+const CONFIG3: u8 = 0x03;
+const LOFF: u8 = 0x04;
+const CH1SET: u8 = 0x05;
+const LOFF_SENSP: u8 = 0x0F;
+const CONFIG4: u8 = 0x17;
+
+/// Bits of [`ID`] that identify an 8 channel ADS1299, the rest is the revision
+const ID_MASK: u8 = 0x1F;
+const ID_ADS1299_8: u8 = 0x1E;
+/// Reserved bits of [`CONFIG1`] that have to be set, ORed with the data rate
+const CONFIG1_RESERVED: u8 = 0x90;
+/// Test signals off
+const CONFIG2_DEFAULT: u8 = 0xC0;
+/// Internal reference buffer on
+const CONFIG3_REFERENCE: u8 = 0xE0;
+/// 6 nA AC lead-off current at a quarter of the data rate, see [`common::contact`]
+const LOFF_AC_6NA_QUARTER_RATE: u8 = 0x03;
+/// Lead-off comparators on
+const CONFIG4_LOFF_COMPARATORS: u8 = 0x02;
+
+/// Status word and 24-bit samples of every channel
+const FRAME_LEN: usize = 3 + 3 * CHANNELS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    Spi(spim::Error),
+    /// Something other than an 8 channel ADS1299 answered
+    UnexpectedId(u8),
+    /// Not one of [`common::SAMPLE_RATES`]
+    UnsupportedSampleRate(u32),
+}
+
+impl From<spim::Error> for Error {
+    fn from(error: spim::Error) -> Self {
+        Self::Spi(error)
+    }
+}
+
+/// How the front-end converts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub sample_rate: u32,
+    pub gains: [Gain; CHANNELS],
+    /// Drives the lead-off test current into every electrode, for the impedance mode
+    pub test_current: bool,
+}
+
+pub struct Afe {
+    spi: Spim<'static>,
+    /// Chip select, active low
+    cs: Output<'static>,
+    /// Data ready, goes low once a frame can be read
+    drdy: Input<'static>,
+}
+
+impl Afe {
+    /// Resets the front-end and checks that it's there. `spi` has to be in mode 1, at 4 MHz or
+    /// less.
+    pub async fn new(
+        spi: Spim<'static>,
+        cs: Output<'static>,
+        drdy: Input<'static>,
+    ) -> Result<Self, Error> {
+        let mut afe = Self { spi, cs, drdy };
+        // Until the supplies and the reference settled after power-up
+        Timer::after_millis(150).await;
+        afe.command(RESET).await?;
+        Timer::after_micros(20).await;
+        // It starts out converting continuously, registers can't be accessed until stopped
+        afe.command(SDATAC).await?;
+        let id = afe.read_register(ID).await?;
+        if id & ID_MASK != ID_ADS1299_8 {
+            return Err(Error::UnexpectedId(id));
+        }
+        Ok(afe)
+    }
+
+    /// Stops converting and applies `config`. Conversions have to be started again afterwards.
+    pub async fn configure(&mut self, config: &Config) -> Result<(), Error> {
+        let data_rate = data_rate(config.sample_rate)?;
+        self.stop().await?;
+        self.write_registers(
+            CONFIG1,
+            &[
+                CONFIG1_RESERVED | data_rate,
+                CONFIG2_DEFAULT,
+                CONFIG3_REFERENCE,
+            ],
+        )
+        .await?;
+        let mut channels = [0; CHANNELS];
+        for (setting, &gain) in channels.iter_mut().zip(&config.gains) {
+            // Powered up, normal electrode input
+            *setting = gain_bits(gain) << 4;
+        }
+        self.write_registers(CH1SET, &channels).await?;
+
+        let (sense, comparators) = if config.test_current {
+            (0xFF, CONFIG4_LOFF_COMPARATORS)
+        } else {
+            (0, 0)
+        };
+        self.write_registers(LOFF, &[LOFF_AC_6NA_QUARTER_RATE])
+            .await?;
+        self.write_registers(LOFF_SENSP, &[sense]).await?;
+        self.write_registers(CONFIG4, &[comparators]).await
+    }
+
+    /// Starts converting continuously, a frame is then ready at every sample
+    pub async fn start(&mut self) -> Result<(), Error> {
+        self.command(START).await?;
+        self.command(RDATAC).await
+    }
+
+    /// Stops converting
+    pub async fn stop(&mut self) -> Result<(), Error> {
+        self.command(SDATAC).await?;
+        self.command(STOP).await
+    }
+
+    /// Waits for the next frame and reads the sample of every channel into `samples`. Returns the
+    /// channels whose electrode lost contact, bit `n` for channel `n`.
+    pub async fn read_frame(&mut self, samples: &mut [i32; CHANNELS]) -> Result<u8, Error> {
+        self.drdy.wait_for_low().await;
+        let mut frame = [0; FRAME_LEN];
+        // Not a constant, SPI can only send from RAM
+        let zeros = [0; FRAME_LEN];
+        self.transfer(&mut frame, &zeros).await?;
+        for (sample, bytes) in samples.iter_mut().zip(frame[3..].chunks_exact(3)) {
+            // Sign extended from 24 bits
+            *sample = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], 0]) >> 8;
+        }
+        // 1100, then the positive inputs that lost contact
+        Ok(frame[0] << 4 | frame[1] >> 4)
+    }
+
+    async fn command(&mut self, opcode: u8) -> Result<(), Error> {
+        self.transfer(&mut [], &[opcode]).await
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, Error> {
+        let mut read = [0; 3];
+        self.transfer(&mut read, &[RREG | register, 0, 0]).await?;
+        Ok(read[2])
+    }
+
+    async fn write_registers(&mut self, first: u8, values: &[u8]) -> Result<(), Error> {
+        let mut write = [0; 2 + CHANNELS];
+        write[0] = WREG | first;
+        write[1] = values.len() as u8 - 1;
+        write[2..2 + values.len()].copy_from_slice(values);
+        self.transfer(&mut [], &write[..2 + values.len()]).await
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        self.cs.set_low();
+        let result = self.spi.transfer(read, write).await;
+        self.cs.set_high();
+        Ok(result?)
+    }
+}
+
+/// `DR` bits of [`CONFIG1`] for `sample_rate`
+fn data_rate(sample_rate: u32) -> Result<u8, Error> {
+    let data_rate = match sample_rate {
+        16_000 => 0,
+        8_000 => 1,
+        4_000 => 2,
+        2_000 => 3,
+        1_000 => 4,
+        500 => 5,
+        250 => 6,
+        _ => return Err(Error::UnsupportedSampleRate(sample_rate)),
+    };
+    Ok(data_rate)
+}
+
+/// `GAIN` bits of a channel setting register
+fn gain_bits(gain: Gain) -> u8 {
+    match gain {
+        Gain::X1 => 0,
+        Gain::X2 => 1,
+        Gain::X4 => 2,
+        Gain::X6 => 3,
+        Gain::X8 => 4,
+        Gain::X12 => 5,
+        Gain::X24 => 6,
+    }
+}
@@ -0,0 +1,139 @@
+use crate::acquisition::{self, Config};
+use common::config_store::{self, keys, ConfigStore, Key};
+use common::ipc::{build_ack, build_error};
+use common::proto::capnp;
+use common::proto::from_edge_capnp::{from_edge, ErrorCode};
+use common::proto::no_alloc::{self, ScratchBuffer};
+use common::proto::to_edge_capnp::{to_edge, Gain};
+use common::{CHANNEL_COUNT, SAMPLE_RATES};
+use embassy_nrf::nvmc::Nvmc;
+
+/// Settings kept across reboots, see [`crate::SETTINGS_START`]
+pub type Settings = ConfigStore<Nvmc<'static>>;
+
+/// Handles the commands forwarded by the network core
+pub struct CommandHandler {
+    /// Replies are built in here before being serialized
+    scratch: ScratchBuffer<64>,
+    /// `None` if the settings couldn't be read at boot
+    settings: Option<Settings>,
+    /// What the front-end was last told to do
+    config: Config,
+}
+
+impl CommandHandler {
+    /// Configures the front-end with the saved settings, it stays idle until told to stream
+    pub fn new(mut settings: Option<Settings>) -> Self {
+        let config = settings.as_mut().map_or_else(Config::default, load);
+        acquisition::CONFIG.signal(config);
+        Self {
+            scratch: ScratchBuffer::default(),
+            settings,
+            config,
+        }
+    }
+
+    /// Handles an encoded `ToEdge` command, and encodes the reply to send to the host into `out`
+    pub fn handle(&mut self, message: &[u8], out: &mut [u8]) -> capnp::Result<usize> {
+        let command = no_alloc::read(message)?;
+        let command = command.get_root::<to_edge::Reader>()?;
+        let request_id = command.get_request_id();
+        let mut config = self.config;
+        let applied = match command.which() {
+            Ok(to_edge::StartStreaming(())) => {
+                config.streaming = true;
+                Ok(())
+            }
+            Ok(to_edge::StopStreaming(())) => {
+                config.streaming = false;
+                Ok(())
+            }
+            Ok(to_edge::SetImpedanceMode(on)) => {
+                config.impedance_mode = on;
+                Ok(())
+            }
+            Ok(to_edge::SetSampleRate(rate)) => {
+                config.sample_rate = closest_sample_rate(rate);
+                let rate = config.sample_rate.to_le_bytes();
+                save(&mut self.settings, keys::SAMPLE_RATE, &rate)
+            }
+            Ok(to_edge::SetStreamRate(rate)) => {
+                config.stream_rate = rate;
+                save(&mut self.settings, keys::STREAM_RATE, &rate.to_le_bytes())
+            }
+            Ok(to_edge::SetChannelGain(channel_gain)) => {
+                let channel_gain = channel_gain?;
+                let channel = channel_gain.get_channel();
+                if channel >= CHANNEL_COUNT {
+                    Err((ErrorCode::InvalidArgument, "No such channel"))
+                } else if let Ok(gain) = channel_gain.get_gain() {
+                    config.gains[usize::from(channel)] = gain;
+                    let key = keys::CHANNEL_GAIN + Key::from(channel);
+                    save(&mut self.settings, key, &(gain as u16).to_le_bytes())
+                } else {
+                    Err((ErrorCode::InvalidArgument, "Unknown gain"))
+                }
+            }
+            Ok(_) => Err((ErrorCode::Unsupported, "Not implemented yet")),
+            Err(_) => Err((ErrorCode::Unsupported, "Unknown command")),
+        };
+        if applied.is_ok() && config != self.config {
+            self.config = config;
+            acquisition::CONFIG.signal(config);
+        }
+        self.scratch
+            .encode::<from_edge::Owned>(out, |reply| match applied {
+                Ok(()) => build_ack(reply, request_id),
+                Err((code, message)) => build_error(reply, request_id, code, message),
+            })
+    }
+}
+
+/// The supported sample rate closest to `rate`
+fn closest_sample_rate(rate: u32) -> u32 {
+    SAMPLE_RATES
+        .into_iter()
+        .min_by_key(|supported| supported.abs_diff(rate))
+        .unwrap_or(rate)
+}
+
+/// Front-end configuration from the saved settings, the defaults for those that aren't
+fn load(settings: &mut Settings) -> Config {
+    let mut config = Config::default();
+    let mut value = [0; 4];
+    if let Ok(Some(4)) = settings.get(keys::SAMPLE_RATE, &mut value) {
+        config.sample_rate = closest_sample_rate(u32::from_le_bytes(value));
+    }
+    if let Ok(Some(4)) = settings.get(keys::STREAM_RATE, &mut value) {
+        config.stream_rate = u32::from_le_bytes(value);
+    }
+    for (channel, gain) in config.gains.iter_mut().enumerate() {
+        let key = keys::CHANNEL_GAIN + channel as Key;
+        let mut value = [0; 2];
+        if let Ok(Some(2)) = settings.get(key, &mut value) {
+            if let Ok(saved) = Gain::try_from(u16::from_le_bytes(value)) {
+                *gain = saved;
+            }
+        }
+    }
+    config
+}
+
+/// Stores a setting, so that it's still applied after a reboot. Returns the error to reply with if
+/// it can't be.
+fn save(
+    settings: &mut Option<Settings>,
+    key: Key,
+    value: &[u8],
+) -> Result<(), (ErrorCode, &'static str)> {
+    let Some(settings) = settings else {
+        return Err((ErrorCode::Unknown, "The settings couldn't be read at boot"));
+    };
+    settings.set(key, value).map_err(|error| {
+        defmt::error!("Couldn't save setting {}: {}", key, error);
+        match error {
+            config_store::Error::Full => (ErrorCode::StorageFull, "No space left for settings"),
+            _ => (ErrorCode::Unknown, "Couldn't save the setting"),
+        }
+    })
+}
@@ -1,25 +1,57 @@
 #![no_std]
 #![no_main]
 
+use commands::CommandHandler;
+use common::clock::Clock;
+use common::config_store::ConfigStore;
+use common::ipc::{Message, Payload};
+use common::log::Core;
+use common::proto::ProtocolVersion;
+use common::ring_buffer::OverflowPolicy;
+use common::rpc::{Endpoint, QueueTransport, RemoteError, Reply, Request};
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::{panic::PanicInfo, sync::atomic::compiler_fence};
 use defmt_rtt as _;
-use embassy_executor::{task, Spawner, SpawnerTraceExt};
-use embassy_nrf::gpio::Output;
+use embassy_executor::Spawner;
+use embassy_futures::select::{select4, Either4};
+use embassy_nrf::gpio::{Input, Output};
 use embassy_nrf::ipc::{self, InterruptHandler as IpcInterruptHandler, Ipc, IpcChannel};
+use embassy_nrf::nvmc::Nvmc;
 use embassy_nrf::pac::SPU;
 use embassy_nrf::peripherals::IPC;
-use embassy_nrf::{bind_interrupts, reset};
-use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use embassy_sync::watch;
-use embassy_time::Timer;
+use embassy_nrf::{bind_interrupts, reset, spim};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use signal::IpcSignal;
+mod acquisition;
+mod afe;
 mod bsp;
+mod commands;
+mod signal;
 
-static BLE_WATCH: watch::Watch<CriticalSectionRawMutex, (), 1> = watch::Watch::new();
+/// Start of the settings store, at the start of `SHARED_FLASH` in `memory.x`
+const SETTINGS_START: u32 = 0x000C_0000;
+/// Erase pages of the settings store, the rest of `SHARED_FLASH` is free
+const SETTINGS_PAGES: u32 = 4;
+
+/// How often the device time and the state of the link are fetched from the network core
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the network core has to answer a request
+const RPC_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Set when the cores disagree on the layout of shared RAM, the status LED blinks faster
+static LAYOUT_MISMATCH: AtomicBool = AtomicBool::new(false);
+/// Device time, as kept by the network core
+static CLOCK: Clock = Clock::new();
 
 #[embassy_executor::task]
 async fn led_blinker(mut led: Output<'static>) {
     loop {
-        Timer::after_secs(1).await;
+        let period = if LAYOUT_MISMATCH.load(Ordering::Relaxed) {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_secs(1)
+        };
+        Timer::after(period).await;
         led.toggle();
     }
 }
@@ -50,18 +82,43 @@ async fn main(spawner: Spawner) {
     init_trustzone();
 
     reset::hold_network_core();
+    // Safety: The network core is held in reset, and nothing took an end of the queues yet
+    unsafe {
+        common::SHARED_RAM_HEADER.write();
+        common::IPC_CHANNELS.init();
+        common::SAMPLE_BLOCKS.init();
+    }
 
-    defmt::info!("Application core started");
+    defmt::info!(
+        "Application core started, protocol version {}.{}",
+        ProtocolVersion::CURRENT.major,
+        ProtocolVersion::CURRENT.minor
+    );
 
     let p = bsp::init();
+    let settings = match ConfigStore::mount(Nvmc::new(p.NVMC), SETTINGS_START, SETTINGS_PAGES) {
+        Ok(settings) => Some(settings),
+        Err(error) => {
+            defmt::error!("Couldn't read the settings: {}", error);
+            None
+        }
+    };
     let Ipc {
         event0: mut start_ipc,
-        event1: mut ble_queue_ipc,
+        event1: mut to_net_ipc,
+        event2: mut from_net_ipc,
+        event3: mut blocks_ipc,
+        event4: mut rpc_to_net_ipc,
+        event5: mut rpc_from_net_ipc,
         ..
     } = Ipc::new(p.IPC, Irqs);
 
     start_ipc.configure_wait([IpcChannel::Channel0]);
-    ble_queue_ipc.configure_trigger([IpcChannel::Channel1]);
+    to_net_ipc.configure_trigger([IpcChannel::Channel1]);
+    from_net_ipc.configure_wait([IpcChannel::Channel2]);
+    blocks_ipc.configure_trigger([IpcChannel::Channel3]);
+    rpc_to_net_ipc.configure_trigger([IpcChannel::Channel4]);
+    rpc_from_net_ipc.configure_wait([IpcChannel::Channel5]);
 
     reset::clear_reasons();
     reset::release_network_core();
@@ -85,23 +142,119 @@ async fn main(spawner: Spawner) {
 
     defmt::unwrap!(spawner.spawn(gpiote_blinker(led_4_net_status, start_ipc)));
 
-    defmt::unwrap!(spawner.spawn_named(
-        "ble-ipc",
-        ipc_handler_task(ble_queue_ipc, BLE_WATCH.sender())
-    ));
+    // The network core checked the header before signalling that it started
+    if let Err(error) = common::SHARED_RAM_HEADER.check_on_app_core() {
+        defmt::error!("Not using the queues to the network core: {}", error);
+        LAYOUT_MISMATCH.store(true, Ordering::Relaxed);
+        return;
+    }
 
-    // Safety: This is the only place where this is called
-    let mut ble_receiver =
-        unsafe { common::BLE_QUEUE.get_receiver_with_signal(defmt::unwrap!(BLE_WATCH.receiver())) };
+    let mut from_net = defmt::unwrap!(common::IPC_CHANNELS
+        .net_to_app
+        .take_receiver_with_signal(Core::App, IpcSignal(from_net_ipc)));
+    let mut to_net = defmt::unwrap!(common::IPC_CHANNELS
+        .app_to_net
+        .take_sender_with_signal(Core::App, IpcSignal(to_net_ipc)));
+    // Nothing is taken off the queue while the host isn't connected, what it missed is stale by
+    // the time it reconnects
+    to_net.set_policy(OverflowPolicy::DropOldest);
+    let transport = QueueTransport {
+        sender: defmt::unwrap!(common::IPC_CHANNELS
+            .rpc_app_to_net
+            .take_sender_with_signal(Core::App, IpcSignal(rpc_to_net_ipc))),
+        receiver: defmt::unwrap!(common::IPC_CHANNELS
+            .rpc_net_to_app
+            .take_receiver_with_signal(Core::App, IpcSignal(rpc_from_net_ipc))),
+    };
+    let mut rpc = Endpoint::new(transport, answer_rpc);
+    let blocks =
+        defmt::unwrap!(common::SAMPLE_BLOCKS.take_writer(Core::App, IpcSignal(blocks_ipc)));
+
+    // The front-end on the Arduino header of the development kit
+    let mut spi_config = spim::Config::default();
+    spi_config.frequency = spim::Frequency::M2;
+    spi_config.mode = spim::MODE_1;
+    let spi = spim::Spim::new(p.SERIAL2, Irqs, p.P1_15, p.P1_14, p.P1_13, spi_config);
+    let cs = Output::new(
+        p.P1_12,
+        embassy_nrf::gpio::Level::High,
+        embassy_nrf::gpio::OutputDrive::Standard,
+    );
+    let drdy = Input::new(p.P1_10, embassy_nrf::gpio::Pull::None);
+    match afe::Afe::new(spi, cs, drdy).await {
+        Ok(afe) => defmt::unwrap!(spawner.spawn(acquisition::run(afe, blocks))),
+        Err(error) => defmt::error!("No samples, couldn't set up the front-end: {}", error),
+    }
 
+    let mut commands = CommandHandler::new(settings);
+    let mut poll = Ticker::every(POLL_INTERVAL);
     loop {
-        defmt::info!("Got event! {:?}", ble_receiver.recv().await);
+        let message = match select4(
+            from_net.recv(),
+            acquisition::CONTACTS.receive(),
+            rpc.serve(),
+            poll.next(),
+        )
+        .await
+        {
+            Either4::First(message) => message,
+            Either4::Second(contact) => {
+                // Can't fail, the oldest message is dropped instead
+                let _ = to_net.send(Message::ToHost(contact)).await;
+                continue;
+            }
+            Either4::Third(()) => continue,
+            Either4::Fourth(()) => {
+                let asked_at = Instant::now();
+                match rpc.time(RPC_TIMEOUT).await {
+                    // The reply took about half of the round trip to get here
+                    Ok(time) => CLOCK.set(time + asked_at.elapsed().as_micros() / 2),
+                    Err(error) => defmt::warn!("Couldn't get the device time: {}", error),
+                }
+                match rpc.link(RPC_TIMEOUT).await {
+                    Ok(link) => {
+                        let was =
+                            acquisition::HOST_CONNECTED.swap(link.connected, Ordering::Relaxed);
+                        if link.connected != was {
+                            defmt::info!(
+                                "Host connected: {}, RSSI {} dBm",
+                                link.connected,
+                                link.rssi
+                            );
+                        }
+                    }
+                    Err(error) => defmt::warn!("Couldn't get the state of the link: {}", error),
+                }
+                continue;
+            }
+        };
+        let Message::Command(command) = message else {
+            defmt::warn!("Unexpected message from the network core");
+            continue;
+        };
+        let reply = Payload::encode(|out| commands.handle(command.as_bytes(), out));
+        let Ok(reply) = reply else {
+            defmt::warn!("Couldn't encode the reply to a command");
+            continue;
+        };
+        // Can't fail, the oldest message is dropped instead
+        let _ = to_net.send(Message::ToHost(reply)).await;
+    }
+}
+
+/// Answers the requests of the network core
+fn answer_rpc(request: Request) -> Result<Reply, RemoteError> {
+    match request {
+        // There is no fuel gauge on the development kit
+        Request::Battery => Err(RemoteError::Unavailable),
+        Request::Link | Request::Time => Err(RemoteError::Unsupported),
     }
 }
 
 bind_interrupts! {
     struct Irqs {
         IPC => IpcInterruptHandler<embassy_nrf::peripherals::IPC>;
+        SERIAL2 => spim::InterruptHandler<embassy_nrf::peripherals::SERIAL2>;
     }
 }
 
@@ -111,14 +264,3 @@ pub fn panic(_: &PanicInfo) -> ! {
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
     }
 }
-
-#[task]
-async fn ipc_handler_task(
-    mut event: ipc::Event<'static, IPC>,
-    sender: watch::Sender<'static, CriticalSectionRawMutex, (), 1>,
-) {
-    loop {
-        event.wait().await;
-        sender.send(());
-    }
-}
@@ -0,0 +1,20 @@
+//! IPC events as the signals of the queues in shared RAM, see [`common::signal`]
+
+use common::signal::{Notify, Wait};
+use embassy_nrf::ipc::Event;
+use embassy_nrf::peripherals::IPC;
+
+/// An IPC event, configured to either trigger or wait on a channel to the network core
+pub struct IpcSignal(pub Event<'static, IPC>);
+
+impl Notify for IpcSignal {
+    fn notify(&mut self) {
+        self.0.trigger();
+    }
+}
+
+impl Wait for IpcSignal {
+    async fn wait(&mut self) {
+        self.0.wait().await;
+    }
+}
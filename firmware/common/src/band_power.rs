@@ -0,0 +1,141 @@
+//! Band power estimated on the device, sent to the host instead of the samples when the link
+//! doesn't have the bandwidth for them.
+//!
+//! Segments are a second long at the lowest sample rate, so estimates are only useful for the EEG
+//! bands at the lower sample rates.
+
+use crate::ipc::SampleBlock;
+use crate::CHANNEL_COUNT;
+use dsp::welch::{Welch, WelchConfig, Window};
+use proto::capnp;
+use proto::from_edge_capnp::from_edge;
+use proto::no_alloc::ScratchBuffer;
+
+/// Samples of each channel in a segment
+pub const SEGMENT_LEN: usize = 256;
+/// Most bands an estimate can be split into
+pub const MAX_BANDS: usize = 8;
+/// Half overlapping Hann windows, an estimate every 512 samples covering 640
+pub const DEFAULT_CONFIG: WelchConfig = WelchConfig {
+    window: Window::Hann,
+    overlap: SEGMENT_LEN / 2,
+    segments: 4,
+};
+
+const CHANNELS: usize = CHANNEL_COUNT as usize;
+
+/// Turns consecutive sample blocks into `BandPower` messages
+pub struct BandPowerStream {
+    welch: Welch<SEGMENT_LEN, CHANNELS>,
+    edges: heapless::Vec<f32, { MAX_BANDS + 1 }>,
+    /// `sample_counter` the next block should start at, `None` before the first one
+    next_counter: Option<u64>,
+    /// Layout of the blocks the estimate is made of, a change starts it over
+    sample_rate: u32,
+    channel_count: u8,
+    /// Messages are built in here, a full estimate takes 46 words
+    scratch: ScratchBuffer<48>,
+}
+
+impl BandPowerStream {
+    /// Panics if there are fewer than two `edges` (Hz) or more than [`MAX_BANDS`] bands between
+    /// them, and for configurations [`Welch::new`] refuses
+    pub fn new(config: WelchConfig, edges: &[f32]) -> Self {
+        assert!(edges.len() >= 2, "bands need two edges");
+        let edges = heapless::Vec::from_slice(edges).expect("too many bands");
+        Self {
+            welch: Welch::new(config, crate::SAMPLE_RATES[0] as f32),
+            edges,
+            next_counter: None,
+            sample_rate: crate::SAMPLE_RATES[0],
+            channel_count: 0,
+            scratch: ScratchBuffer::new(),
+        }
+    }
+
+    /// Adds the samples of `block`, and encodes an estimate into `out` once there is one. Returns
+    /// the length of the message, only the latest estimate being sent if there were several.
+    ///
+    /// Estimates start over when blocks are missing, or their sample rate or channels change.
+    pub fn push(&mut self, block: &SampleBlock, out: &mut [u8]) -> Option<usize> {
+        let channels = usize::from(block.channel_count).min(CHANNELS);
+        if channels == 0 || block.sample_rate == 0 {
+            return None;
+        }
+        if block.sample_rate != self.sample_rate {
+            self.sample_rate = block.sample_rate;
+            self.welch.set_sample_rate(block.sample_rate as f32);
+        } else if self.next_counter != Some(block.sample_counter)
+            || block.channel_count != self.channel_count
+        {
+            self.welch.reset();
+        }
+        self.next_counter = Some(block.sample_counter + u64::from(block.frames));
+        self.channel_count = block.channel_count;
+
+        let span = self.welch.span() as u64;
+        let mut encoded = None;
+        for (index, samples) in block.samples().chunks_exact(channels).enumerate() {
+            let mut frame = [0.0; CHANNELS];
+            for (x, &sample) in frame.iter_mut().zip(samples) {
+                *x = sample as f32;
+            }
+            let Some(estimate) = self.welch.push(&frame) else {
+                continue;
+            };
+            let mut power = [0.0; CHANNELS * MAX_BANDS];
+            let bands = self.edges.len() - 1;
+            for (channel, power) in power.chunks_exact_mut(bands).take(channels).enumerate() {
+                estimate.band_power(channel, &self.edges, power);
+            }
+
+            // From the sample just pushed back to the first one the estimate covers
+            let offset =
+                (index as i64 - (span as i64 - 1)) * 1_000_000 / i64::from(block.sample_rate);
+            let first = Covered {
+                sample_counter: (block.sample_counter + index as u64).saturating_sub(span - 1),
+                timestamp: block.timestamp.saturating_add_signed(offset),
+                sample_count: span as u32,
+            };
+            let power = &power[..channels * bands];
+            encoded = self
+                .scratch
+                .encode::<from_edge::Owned>(out, |message| {
+                    build_band_power(message, block, &first, &self.edges, power)
+                })
+                .ok();
+        }
+        encoded
+    }
+
+    /// Starts over, e.g. when streaming stops
+    pub fn reset(&mut self) {
+        self.welch.reset();
+        self.next_counter = None;
+    }
+}
+
+/// Samples an estimate covers
+struct Covered {
+    sample_counter: u64,
+    timestamp: u64,
+    sample_count: u32,
+}
+
+fn build_band_power(
+    message: from_edge::Builder,
+    block: &SampleBlock,
+    covered: &Covered,
+    edges: &[f32],
+    power: &[f32],
+) -> capnp::Result<()> {
+    let mut band_power = message.init_band_power();
+    band_power.set_sample_counter(covered.sample_counter);
+    band_power.set_timestamp(covered.timestamp);
+    band_power.set_sample_count(covered.sample_count);
+    band_power.set_sample_rate(block.sample_rate);
+    band_power.set_channel_count(block.channel_count);
+    band_power.set_band_edges(edges)?;
+    band_power.set_power(power)?;
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//! Blocks of data handed from one core to the other without copying them.
+//!
+//! Each block of a [`BlockBuffer`] is owned by one side at a time. The writer fills a free block in
+//! place and commits it, which signals the reader. The reader reads the block in place and frees
+//! it once done. Both sides go through the blocks in the same order, so with two blocks the writer
+//! fills one while the reader works on the other, and a third lets the writer carry on while the
+//! reader is a block behind. When the reader falls further behind, the writer finds no free block
+//! and the overrun is counted rather than waited for.
+//!
+//! Like the ends of a queue, the writer and the reader each belong to a fixed core, see
+//! [`crate::ring_buffer`]. The state of a block is written by both, so each state is left by one
+//! side only: the writer moves a block from free to writing and on to ready, the reader from ready
+//! to reading and back to free. A side only writes the state of a block it found in one of its own
+//! states, which the other side never changes, so a plain load followed by a store can't lose an
+//! update of the other core.
+
+use crate::log::Core;
+use crate::ring_buffer::Taken;
+use crate::signal::{Notify, Wait};
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+const FREE: u8 = 0;
+const WRITING: u8 = 1;
+const READY: u8 = 2;
+const READING: u8 = 3;
+
+/// Who owns a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum BlockState {
+    /// Waiting for the writer
+    Free,
+    /// Being filled by the writer
+    Writing,
+    /// Filled, waiting for the reader
+    Ready,
+    /// Being read by the reader
+    Reading,
+}
+
+/// `N` blocks of `T` going round between a writer and a reader
+pub struct BlockBuffer<T, const N: usize> {
+    states: [AtomicU8; N],
+    blocks: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Times the writer wanted a block while the reader still had all of them
+    overruns: AtomicU32,
+    /// Set once the writer was handed out, see [`Taken`]
+    writer_taken: Taken,
+    /// Set once the reader was handed out
+    reader_taken: Taken,
+}
+
+// Safety: A block is only ever accessed by the side that owns it, see `BlockState`
+unsafe impl<T: Send, const N: usize> Sync for BlockBuffer<T, N> {}
+
+impl<T, const N: usize> Default for BlockBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Default, const N: usize> BlockBuffer<T, N> {
+    /// Frees every block, clears them and makes both ends available again. Shared RAM isn't
+    /// initialized at boot, so this has to be called once before either core takes an end.
+    ///
+    /// # Safety
+    ///
+    /// Neither end may be in use, on either core
+    pub unsafe fn init(&self) {
+        for (state, block) in self.states.iter().zip(&self.blocks) {
+            // Safety: Nobody has a reference to the block
+            unsafe { (*block.get()).write(T::default()) };
+            state.store(FREE, Ordering::Release);
+        }
+        self.overruns.store(0, Ordering::Relaxed);
+        self.writer_taken.reset();
+        self.reader_taken.reset();
+    }
+}
+
+impl<T, const N: usize> BlockBuffer<T, N> {
+    /// Creates the buffer, which has to be initialized with [`Self::init`] before either end is
+    /// taken
+    pub const fn new() -> Self {
+        Self {
+            states: [const { AtomicU8::new(FREE) }; N],
+            blocks: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            overruns: AtomicU32::new(0),
+            writer_taken: Taken::new(),
+            reader_taken: Taken::new(),
+        }
+    }
+
+    /// Who owns the block at `index`
+    pub fn state(&self, index: usize) -> BlockState {
+        match self.states[index].load(Ordering::Acquire) {
+            FREE => BlockState::Free,
+            WRITING => BlockState::Writing,
+            READY => BlockState::Ready,
+            _ => BlockState::Reading,
+        }
+    }
+
+    /// Times the writer found no free block
+    pub fn overruns(&self) -> u32 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    /// Gets the writing end on `core`, which signals `signal` after every block, or `None` if it
+    /// was already taken by either core
+    pub fn take_writer<S: Notify>(
+        &self,
+        core: Core,
+        signal: S,
+    ) -> Option<BlockWriter<'_, T, N, S>> {
+        if !self.writer_taken.take(core) {
+            return None;
+        }
+        Some(BlockWriter {
+            buffer: self,
+            next: 0,
+            signal,
+        })
+    }
+
+    /// Gets the reading end on `core`, woken up by `signal`, or `None` if it was already taken by
+    /// either core
+    pub fn take_reader<W: Wait>(&self, core: Core, signal: W) -> Option<BlockReader<'_, T, N, W>> {
+        if !self.reader_taken.take(core) {
+            return None;
+        }
+        Some(BlockReader {
+            buffer: self,
+            next: 0,
+            signal,
+        })
+    }
+
+    /// Only to be dereferenced by the side that owns the block at `index`
+    fn block(&self, index: usize) -> *mut T {
+        self.blocks[index].get().cast()
+    }
+}
+
+/// Writing end of a [`BlockBuffer`]
+pub struct BlockWriter<'a, T, const N: usize, S> {
+    buffer: &'a BlockBuffer<T, N>,
+    /// Block to fill next
+    next: usize,
+    signal: S,
+}
+
+impl<'a, T, const N: usize, S: Notify> BlockWriter<'a, T, N, S> {
+    /// Takes the next block to fill, or `None` if the reader hasn't freed it yet. It holds what was
+    /// last written to it.
+    pub fn acquire(&mut self) -> Option<WriteGuard<'_, 'a, T, N, S>> {
+        let state = &self.buffer.states[self.next];
+        // Only the writer leaves `FREE`
+        if state.load(Ordering::Acquire) != FREE {
+            self.buffer.overruns.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        state.store(WRITING, Ordering::Relaxed);
+        Some(WriteGuard {
+            writer: self,
+            committed: false,
+        })
+    }
+}
+
+/// A block being filled. It's handed to the reader by [`WriteGuard::commit`], and freed again if
+/// dropped without committing.
+pub struct WriteGuard<'w, 'a, T, const N: usize, S: Notify> {
+    writer: &'w mut BlockWriter<'a, T, N, S>,
+    committed: bool,
+}
+
+impl<T, const N: usize, S: Notify> WriteGuard<'_, '_, T, N, S> {
+    /// Hands the block to the reader
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl<T, const N: usize, S: Notify> Deref for WriteGuard<'_, '_, T, N, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The writer owns the block until the guard is dropped
+        unsafe { &*self.writer.buffer.block(self.writer.next) }
+    }
+}
+
+impl<T, const N: usize, S: Notify> DerefMut for WriteGuard<'_, '_, T, N, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: See `deref`
+        unsafe { &mut *self.writer.buffer.block(self.writer.next) }
+    }
+}
+
+impl<T, const N: usize, S: Notify> Drop for WriteGuard<'_, '_, T, N, S> {
+    fn drop(&mut self) {
+        let writer = &mut *self.writer;
+        let state = &writer.buffer.states[writer.next];
+        if self.committed {
+            state.store(READY, Ordering::Release);
+            writer.next = (writer.next + 1) % N;
+            writer.signal.notify();
+        } else {
+            state.store(FREE, Ordering::Release);
+        }
+    }
+}
+
+/// Reading end of a [`BlockBuffer`]
+pub struct BlockReader<'a, T, const N: usize, W> {
+    buffer: &'a BlockBuffer<T, N>,
+    /// Block to read next
+    next: usize,
+    signal: W,
+}
+
+impl<'a, T, const N: usize, W: Wait> BlockReader<'a, T, N, W> {
+    /// Waits for the next block to be committed. Returns straight away if it already was.
+    pub async fn ready(&mut self) {
+        while self.buffer.states[self.next].load(Ordering::Acquire) != READY {
+            // Only returns for signals sent after the last one we waited for, so a block committed
+            // since it was checked still wakes us up
+            self.signal.wait().await;
+        }
+    }
+
+    /// Takes the next block if it was committed
+    pub fn try_acquire(&mut self) -> Option<ReadGuard<'_, 'a, T, N, W>> {
+        let state = &self.buffer.states[self.next];
+        // Only the reader leaves `READY`
+        if state.load(Ordering::Acquire) != READY {
+            return None;
+        }
+        state.store(READING, Ordering::Relaxed);
+        Some(ReadGuard { reader: self })
+    }
+
+    /// Waits for the next block and takes it
+    pub async fn acquire(&mut self) -> ReadGuard<'_, 'a, T, N, W> {
+        self.ready().await;
+        match self.try_acquire() {
+            Some(guard) => guard,
+            None => unreachable!("only the reader takes ready blocks"),
+        }
+    }
+}
+
+/// A block being read, freed for the writer once dropped
+pub struct ReadGuard<'r, 'a, T, const N: usize, W> {
+    reader: &'r mut BlockReader<'a, T, N, W>,
+}
+
+impl<T, const N: usize, W> Deref for ReadGuard<'_, '_, T, N, W> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The reader owns the block until the guard is dropped
+        unsafe { &*self.reader.buffer.block(self.reader.next) }
+    }
+}
+
+impl<T, const N: usize, W> Drop for ReadGuard<'_, '_, T, N, W> {
+    fn drop(&mut self) {
+        let reader = &mut *self.reader;
+        reader.buffer.states[reader.next].store(FREE, Ordering::Release);
+        reader.next = (reader.next + 1) % N;
+    }
+}
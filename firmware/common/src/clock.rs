@@ -0,0 +1,47 @@
+//! Device time, as sent in every timestamp to the host.
+//!
+//! It counts microseconds from boot until the host sets it with `ToEdge.setTime`, and follows the
+//! host's wall-clock (as a unix timestamp) from then on. The network core keeps it, and the
+//! application core follows it with [`Request::Time`](crate::rpc::Request::Time) to stamp samples.
+
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Instant;
+
+pub struct Clock {
+    /// Added to the time since boot, wrapping. 64-bit atomics aren't available on the M33.
+    offset: Mutex<CriticalSectionRawMutex, Cell<u64>>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    pub const fn new() -> Self {
+        Self {
+            offset: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Current device time (µs)
+    pub fn now(&self) -> u64 {
+        self.at(Instant::now())
+    }
+
+    /// Device time (µs) at `instant`
+    pub fn at(&self, instant: Instant) -> u64 {
+        instant
+            .as_micros()
+            .wrapping_add(self.offset.lock(Cell::get))
+    }
+
+    /// Makes the current device time `time` (µs)
+    pub fn set(&self, time: u64) {
+        let offset = time.wrapping_sub(Instant::now().as_micros());
+        self.offset.lock(|cell| cell.set(offset));
+    }
+}
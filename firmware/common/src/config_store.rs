@@ -0,0 +1,405 @@
+//! Key/value settings kept in flash across reboots.
+//!
+//! Records are appended to the active page of the store, so changing a setting doesn't erase
+//! anything. Once the active page is full, the latest value of every key is copied to the next
+//! page, which then becomes the active one. Pages are used in turn, which spreads the erases evenly
+//! over the store.
+//!
+//! A power cut can't lose a setting that was written:
+//! - every record has a CRC, so one that was cut short is ignored, and the page is compacted at
+//!   the next boot rather than written after it;
+//! - a page only becomes the active one once its header is written, after everything was copied
+//!   to it. Until then, the previous page still holds every setting.
+
+use embedded_storage::nor_flash::NorFlash;
+use proto::framing::crc32;
+
+/// Identifies a setting, see [`keys`]
+pub type Key = u16;
+
+/// Keys of the settings stored by the firmware
+pub mod keys {
+    use super::Key;
+
+    /// Sample rate of the front-end (Hz), as a little-endian `u32`
+    pub const SAMPLE_RATE: Key = 1;
+    /// Rate sample frames are streamed at (Hz), as a little-endian `u32`, 0 for every sample
+    pub const STREAM_RATE: Key = 2;
+    /// The gain of channel `n` is stored under `CHANNEL_GAIN + n`, as a little-endian `u16` of
+    /// the `Gain` of the protocol
+    pub const CHANNEL_GAIN: Key = 0x100;
+}
+
+/// Longest value that can be stored
+pub const MAX_VALUE_LEN: usize = 128;
+
+const PAGE_MAGIC: u32 = u32::from_le_bytes(*b"CFG1");
+/// Magic, sequence number, CRC of both and padding
+const PAGE_HEADER_LEN: u32 = 16;
+/// CRC of the rest of the record, key and length
+const RECORD_HEADER_LEN: u32 = 8;
+/// Records start on a multiple of this, so that they can be written in one go
+const ALIGN: u32 = 8;
+const MAX_RECORD_LEN: usize = RECORD_HEADER_LEN as usize + MAX_VALUE_LEN;
+/// Key of erased flash, never used for a record
+const ERASED_KEY: Key = 0xFFFF;
+/// Length of a record marking its key as removed
+const REMOVED: u16 = 0xFFFE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error<E> {
+    Flash(E),
+    /// The value is longer than [`MAX_VALUE_LEN`]
+    TooLong,
+    /// The key is reserved
+    InvalidKey,
+    /// The latest value of every key doesn't fit in a page
+    Full,
+    /// The buffer is too small for the value, which is `len` bytes long
+    BufferTooSmall {
+        len: usize,
+    },
+}
+
+/// A record read back from flash
+#[derive(Clone, Copy)]
+struct Record {
+    key: Key,
+    /// Length of the value, or [`REMOVED`]
+    len: u16,
+}
+
+impl Record {
+    fn is_removed(&self) -> bool {
+        self.len == REMOVED
+    }
+
+    fn value_len(&self) -> usize {
+        if self.is_removed() {
+            0
+        } else {
+            self.len as usize
+        }
+    }
+
+    fn size(&self) -> u32 {
+        record_size(self.value_len())
+    }
+}
+
+/// What is at some offset of a page
+enum Slot {
+    Record(Record),
+    /// Erased flash, where the next record goes
+    End,
+    /// A record that was cut short
+    Torn,
+}
+
+const fn align(len: u32) -> u32 {
+    len.div_ceil(ALIGN) * ALIGN
+}
+
+const fn record_size(value_len: usize) -> u32 {
+    RECORD_HEADER_LEN + align(value_len as u32)
+}
+
+/// Settings stored in `pages` erase pages of `flash`, starting at `base`
+pub struct ConfigStore<F> {
+    flash: F,
+    base: u32,
+    pages: u32,
+    /// Page holding the settings
+    active: u32,
+    /// Incremented every time another page becomes the active one
+    sequence: u32,
+    /// Where the next record goes in the active page
+    write_offset: u32,
+}
+
+impl<F: NorFlash> ConfigStore<F> {
+    /// Finds the settings in flash, or starts empty if there are none. Finishes what a power cut
+    /// interrupted.
+    pub fn mount(flash: F, base: u32, pages: u32) -> Result<Self, Error<F::Error>> {
+        const {
+            let align = ALIGN as usize;
+            assert!(align.is_multiple_of(F::READ_SIZE) && align.is_multiple_of(F::WRITE_SIZE));
+            assert!(F::ERASE_SIZE >= PAGE_HEADER_LEN as usize + MAX_RECORD_LEN);
+        };
+        assert!(pages >= 2, "the store needs a page to compact into");
+        assert!(base.is_multiple_of(F::ERASE_SIZE as u32));
+
+        let mut store = Self {
+            flash,
+            base,
+            pages,
+            active: 0,
+            sequence: 0,
+            write_offset: PAGE_HEADER_LEN,
+        };
+        let mut newest = None;
+        for page in 0..pages {
+            let Some(sequence) = store.read_header(page)? else {
+                continue;
+            };
+            if newest.is_none_or(|(_, newest)| sequence > newest) {
+                newest = Some((page, sequence));
+            }
+        }
+        let Some((page, sequence)) = newest else {
+            store.erase(0)?;
+            store.write_header(0, 1)?;
+            store.sequence = 1;
+            return Ok(store);
+        };
+        store.active = page;
+        store.sequence = sequence;
+        if !store.scan()? {
+            store.compact(None)?;
+        }
+        Ok(store)
+    }
+
+    /// Copies the value of `key` to the start of `buf`. Returns its length, or `None` if `key`
+    /// isn't set.
+    pub fn get(&mut self, key: Key, buf: &mut [u8]) -> Result<Option<usize>, Error<F::Error>> {
+        let Some((offset, record)) = self.find(key)? else {
+            return Ok(None);
+        };
+        if record.is_removed() {
+            return Ok(None);
+        }
+        let len = record.value_len();
+        let Some(buf) = buf.get_mut(..len) else {
+            return Err(Error::BufferTooSmall { len });
+        };
+        let mut bytes = [0; MAX_RECORD_LEN];
+        let bytes = &mut bytes[..record.size() as usize];
+        self.read(self.active, offset, bytes)?;
+        buf.copy_from_slice(&bytes[RECORD_HEADER_LEN as usize..][..len]);
+        Ok(Some(len))
+    }
+
+    /// Sets `key` to `value`. Nothing is written if it already has that value.
+    pub fn set(&mut self, key: Key, value: &[u8]) -> Result<(), Error<F::Error>> {
+        if key == ERASED_KEY {
+            return Err(Error::InvalidKey);
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::TooLong);
+        }
+        let mut current = [0; MAX_VALUE_LEN];
+        if self.get(key, &mut current)? == Some(value.len()) && current[..value.len()] == *value {
+            return Ok(());
+        }
+        self.append(key, Some(value))
+    }
+
+    /// Removes the value of `key`, if it has one
+    pub fn remove(&mut self, key: Key) -> Result<(), Error<F::Error>> {
+        match self.find(key)? {
+            Some((_, record)) if !record.is_removed() => self.append(key, None),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes a record setting `key` to `value`, or removing it if `None`
+    fn append(&mut self, key: Key, value: Option<&[u8]>) -> Result<(), Error<F::Error>> {
+        let size = record_size(value.map_or(0, <[u8]>::len));
+        if self.write_offset + size > self.page_size() {
+            return self.compact(Some((key, value)));
+        }
+        let written = self.write_record(self.active, self.write_offset, key, value);
+        match written {
+            Ok(()) => self.write_offset += size,
+            // What was written of the record can't be written over, the next change compacts
+            Err(_) => self.write_offset = self.page_size(),
+        }
+        written
+    }
+
+    /// Moves the latest value of every key to the next page, replacing or removing the value of
+    /// one key on the way, and makes it the active page
+    fn compact(&mut self, replace: Option<(Key, Option<&[u8]>)>) -> Result<(), Error<F::Error>> {
+        let target = (self.active + 1) % self.pages;
+        self.erase(target)?;
+
+        let mut from = PAGE_HEADER_LEN;
+        let mut to = PAGE_HEADER_LEN;
+        while let Slot::Record(record) = self.read_record(self.active, from)? {
+            let next = from + record.size();
+            let replaced = replace.is_some_and(|(key, _)| key == record.key);
+            if !record.is_removed() && !replaced && !self.superseded(record.key, next)? {
+                let mut bytes = [0; MAX_RECORD_LEN];
+                let bytes = &mut bytes[..record.size() as usize];
+                self.read(self.active, from, bytes)?;
+                self.write(target, to, bytes)?;
+                to += record.size();
+            }
+            from = next;
+        }
+        if let Some((key, Some(value))) = replace {
+            let size = record_size(value.len());
+            if to + size > self.page_size() {
+                return Err(Error::Full);
+            }
+            self.write_record(target, to, key, Some(value))?;
+            to += size;
+        }
+
+        self.write_header(target, self.sequence.wrapping_add(1))?;
+        self.active = target;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.write_offset = to;
+        Ok(())
+    }
+
+    /// Finds where the records of the active page end. Returns whether the rest of the page is
+    /// erased, and can be written to.
+    fn scan(&mut self) -> Result<bool, Error<F::Error>> {
+        let mut offset = PAGE_HEADER_LEN;
+        loop {
+            match self.read_record(self.active, offset)? {
+                Slot::Record(record) => offset += record.size(),
+                Slot::End => break,
+                Slot::Torn => {
+                    self.write_offset = offset;
+                    return Ok(false);
+                }
+            }
+        }
+        self.write_offset = offset;
+        // A record cut short before its header was written
+        let mut chunk = [0; 64];
+        while offset < self.page_size() {
+            let len = chunk.len().min((self.page_size() - offset) as usize);
+            self.read(self.active, offset, &mut chunk[..len])?;
+            if chunk[..len].iter().any(|&byte| byte != 0xFF) {
+                return Ok(false);
+            }
+            offset += len as u32;
+        }
+        Ok(true)
+    }
+
+    /// Finds the latest record of `key` in the active page, and where it is
+    fn find(&mut self, key: Key) -> Result<Option<(u32, Record)>, Error<F::Error>> {
+        let mut found = None;
+        let mut offset = PAGE_HEADER_LEN;
+        while offset < self.write_offset {
+            let Slot::Record(record) = self.read_record(self.active, offset)? else {
+                break;
+            };
+            if record.key == key {
+                found = Some((offset, record));
+            }
+            offset += record.size();
+        }
+        Ok(found)
+    }
+
+    /// Whether a record of `key` is at or after `offset` in the active page
+    fn superseded(&mut self, key: Key, mut offset: u32) -> Result<bool, Error<F::Error>> {
+        while let Slot::Record(record) = self.read_record(self.active, offset)? {
+            if record.key == key {
+                return Ok(true);
+            }
+            offset += record.size();
+        }
+        Ok(false)
+    }
+
+    fn read_record(&mut self, page: u32, offset: u32) -> Result<Slot, Error<F::Error>> {
+        if offset + RECORD_HEADER_LEN > self.page_size() {
+            return Ok(Slot::End);
+        }
+        let mut bytes = [0; MAX_RECORD_LEN];
+        let header = &mut bytes[..RECORD_HEADER_LEN as usize];
+        self.read(page, offset, header)?;
+        if header.iter().all(|&byte| byte == 0xFF) {
+            return Ok(Slot::End);
+        }
+        let crc = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let record = Record {
+            key: u16::from_le_bytes([header[4], header[5]]),
+            len: u16::from_le_bytes([header[6], header[7]]),
+        };
+        let fits = offset + record.size() <= self.page_size();
+        if record.key == ERASED_KEY || record.value_len() > MAX_VALUE_LEN || !fits {
+            return Ok(Slot::Torn);
+        }
+        let bytes = &mut bytes[..record.size() as usize];
+        let value = RECORD_HEADER_LEN as usize..;
+        self.read(page, offset + RECORD_HEADER_LEN, &mut bytes[value.clone()])?;
+        let checked = &bytes[4..RECORD_HEADER_LEN as usize + record.value_len()];
+        if crc32(checked) != crc {
+            return Ok(Slot::Torn);
+        }
+        Ok(Slot::Record(record))
+    }
+
+    fn write_record(
+        &mut self,
+        page: u32,
+        offset: u32,
+        key: Key,
+        value: Option<&[u8]>,
+    ) -> Result<(), Error<F::Error>> {
+        let len = value.map_or(REMOVED, |value| value.len() as u16);
+        let value = value.unwrap_or(&[]);
+        let mut bytes = [0xFF; MAX_RECORD_LEN];
+        let bytes = &mut bytes[..record_size(value.len()) as usize];
+        bytes[4..6].copy_from_slice(&key.to_le_bytes());
+        bytes[6..8].copy_from_slice(&len.to_le_bytes());
+        bytes[RECORD_HEADER_LEN as usize..][..value.len()].copy_from_slice(value);
+        let crc = crc32(&bytes[4..RECORD_HEADER_LEN as usize + value.len()]);
+        bytes[..4].copy_from_slice(&crc.to_le_bytes());
+        self.write(page, offset, bytes)
+    }
+
+    /// Reads the sequence number of `page`, or `None` if it was never made the active page
+    fn read_header(&mut self, page: u32) -> Result<Option<u32>, Error<F::Error>> {
+        let mut header = [0; PAGE_HEADER_LEN as usize];
+        self.read(page, 0, &mut header)?;
+        let word =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        if word(0) != PAGE_MAGIC || word(8) != crc32(&header[..8]) {
+            return Ok(None);
+        }
+        Ok(Some(word(4)))
+    }
+
+    fn write_header(&mut self, page: u32, sequence: u32) -> Result<(), Error<F::Error>> {
+        let mut header = [0xFF; PAGE_HEADER_LEN as usize];
+        header[..4].copy_from_slice(&PAGE_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        let crc = crc32(&header[..8]);
+        header[8..12].copy_from_slice(&crc.to_le_bytes());
+        self.write(page, 0, &header)
+    }
+
+    fn page_size(&self) -> u32 {
+        F::ERASE_SIZE as u32
+    }
+
+    fn page_start(&self, page: u32) -> u32 {
+        self.base + page * self.page_size()
+    }
+
+    fn read(&mut self, page: u32, offset: u32, bytes: &mut [u8]) -> Result<(), Error<F::Error>> {
+        let start = self.page_start(page) + offset;
+        self.flash.read(start, bytes).map_err(Error::Flash)
+    }
+
+    fn write(&mut self, page: u32, offset: u32, bytes: &[u8]) -> Result<(), Error<F::Error>> {
+        let start = self.page_start(page) + offset;
+        self.flash.write(start, bytes).map_err(Error::Flash)
+    }
+
+    fn erase(&mut self, page: u32) -> Result<(), Error<F::Error>> {
+        let start = self.page_start(page);
+        self.flash
+            .erase(start, start + self.page_size())
+            .map_err(Error::Flash)
+    }
+}
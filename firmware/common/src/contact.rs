@@ -0,0 +1,179 @@
+//! Impedance mode: the front-end drives a test current into every enabled electrode, and the
+//! amplitude of the response at the frequency of that current is measured on each channel. The host
+//! works out the impedance between the electrode and the skin from it.
+
+use crate::ipc::SampleBlock;
+use crate::CHANNEL_COUNT;
+use dsp::lock_in::LockIn;
+use proto::capnp;
+use proto::from_edge_capnp::from_edge;
+use proto::no_alloc::ScratchBuffer;
+use proto::to_edge_capnp::Gain;
+
+/// Amplitude of the test current of the front-end's AC lead-off detection (nA)
+pub const TEST_CURRENT: f32 = 6.0;
+/// Periods of the test current each measurement is made over, two seconds at the lowest sample
+/// rate
+pub const PERIODS: u32 = 125;
+
+const CHANNELS: usize = CHANNEL_COUNT as usize;
+
+/// The front-end drives the test current at a quarter of the sample rate (Hz)
+pub fn test_frequency(sample_rate: u32) -> f32 {
+    sample_rate as f32 / 4.0
+}
+
+/// Input referred voltage of an ADC count at `gain`, for the 4.5 V reference (µV)
+pub fn microvolts_per_count(gain: Gain) -> f32 {
+    let gain = match gain {
+        Gain::X1 => 1.0,
+        Gain::X2 => 2.0,
+        Gain::X4 => 4.0,
+        Gain::X6 => 6.0,
+        Gain::X8 => 8.0,
+        Gain::X12 => 12.0,
+        Gain::X24 => 24.0,
+    };
+    4.5e6 / gain / (1 << 23) as f32
+}
+
+/// Response of every channel over a measurement
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    /// Device time at which the last sample of the measurement was taken (microseconds)
+    pub timestamp: u64,
+    pub test_frequency: f32,
+    pub channel_count: u8,
+    /// Amplitude of the response of each channel interleaved in the blocks (ADC counts), the
+    /// first `channel_count` are valid
+    pub amplitudes: [f32; CHANNELS],
+}
+
+impl Measurement {
+    /// Response of the electrode of `channel`, interleaved at `index` in the blocks and sampled
+    /// with `microvolts_per_count`
+    pub fn contact(&self, index: usize, channel: u8, microvolts_per_count: f32) -> Contact {
+        Contact {
+            channel,
+            timestamp: self.timestamp,
+            test_frequency: self.test_frequency,
+            amplitude: self.amplitudes[index] * microvolts_per_count,
+        }
+    }
+}
+
+/// Response of a single electrode, as sent to the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    pub channel: u8,
+    /// Device time at which the measurement ended (microseconds)
+    pub timestamp: u64,
+    pub test_frequency: f32,
+    /// Referred to the input of the front-end (µV)
+    pub amplitude: f32,
+}
+
+/// Measures the response of the electrodes in consecutive sample blocks, taken with the test
+/// current on
+pub struct ContactMeter {
+    lock_ins: [LockIn; CHANNELS],
+    /// `sample_counter` the next block should start at, `None` before the first one
+    next_counter: Option<u64>,
+    /// Layout of the blocks the measurement is made of, a change starts it over
+    sample_rate: u32,
+    channel_count: u8,
+    /// Messages are built in here, a `ContactQuality` takes 7 words
+    scratch: ScratchBuffer<8>,
+}
+
+impl Default for ContactMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContactMeter {
+    pub fn new() -> Self {
+        Self {
+            lock_ins: Self::lock_ins(crate::SAMPLE_RATES[0]),
+            next_counter: None,
+            sample_rate: crate::SAMPLE_RATES[0],
+            channel_count: 0,
+            scratch: ScratchBuffer::new(),
+        }
+    }
+
+    /// Adds the samples of `block`. Returns the response of every channel at the end of each
+    /// measurement.
+    ///
+    /// Measurements start over when blocks are missing, or their sample rate or channels change.
+    pub fn push(&mut self, block: &SampleBlock) -> Option<Measurement> {
+        let channels = usize::from(block.channel_count).min(CHANNELS);
+        if channels == 0 || block.sample_rate == 0 {
+            return None;
+        }
+        if block.sample_rate != self.sample_rate {
+            self.sample_rate = block.sample_rate;
+            self.lock_ins = Self::lock_ins(block.sample_rate);
+        } else if self.next_counter != Some(block.sample_counter)
+            || block.channel_count != self.channel_count
+        {
+            self.lock_ins.iter_mut().for_each(LockIn::reset);
+        }
+        self.next_counter = Some(block.sample_counter + u64::from(block.frames));
+        self.channel_count = block.channel_count;
+
+        let mut measurement = None;
+        for (index, samples) in block.samples().chunks_exact(channels).enumerate() {
+            let mut amplitudes = [0.0; CHANNELS];
+            let mut done = false;
+            for ((lock_in, &sample), amplitude) in
+                self.lock_ins.iter_mut().zip(samples).zip(&mut amplitudes)
+            {
+                if let Some(measured) = lock_in.push(sample as f32) {
+                    *amplitude = measured;
+                    done = true;
+                }
+            }
+            if done {
+                let elapsed = index as u64 * 1_000_000 / u64::from(block.sample_rate);
+                measurement = Some(Measurement {
+                    timestamp: block.timestamp + elapsed,
+                    test_frequency: test_frequency(block.sample_rate),
+                    channel_count: block.channel_count,
+                    amplitudes,
+                });
+            }
+        }
+        measurement
+    }
+
+    /// Encodes `contact` into `out`, returning the length of the message
+    pub fn encode(&mut self, contact: &Contact, out: &mut [u8]) -> Option<usize> {
+        let encoded = self
+            .scratch
+            .encode::<from_edge::Owned>(out, |message| build_contact_quality(message, contact));
+        encoded.ok()
+    }
+
+    /// Starts over, e.g. when the impedance mode is left
+    pub fn reset(&mut self) {
+        self.lock_ins.iter_mut().for_each(LockIn::reset);
+        self.next_counter = None;
+    }
+
+    fn lock_ins(sample_rate: u32) -> [LockIn; CHANNELS] {
+        let frequency = test_frequency(sample_rate);
+        core::array::from_fn(|_| LockIn::new(frequency, sample_rate as f32, PERIODS))
+    }
+}
+
+fn build_contact_quality(message: from_edge::Builder, contact: &Contact) -> capnp::Result<()> {
+    let mut quality = message.init_contact_quality();
+    quality.set_channel(contact.channel);
+    quality.set_timestamp(contact.timestamp);
+    quality.set_test_current(TEST_CURRENT);
+    quality.set_test_frequency(contact.test_frequency);
+    quality.set_amplitude(contact.amplitude);
+    Ok(())
+}
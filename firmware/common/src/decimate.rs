@@ -0,0 +1,159 @@
+//! Streaming at a fraction of the sample rate, for when the BLE link can't keep up with all of
+//! the samples. Blocks are low-pass filtered before samples are dropped, so that what is above the
+//! reduced Nyquist frequency doesn't alias into the band.
+
+use crate::ipc::{SampleBlock, BLOCK_FRAMES};
+use crate::CHANNEL_COUNT;
+use dsp::Resampler;
+
+/// Taps of the anti-alias filter, long enough to keep aliases out of the pass band up to
+/// [`MAX_FACTOR`]
+pub const TAPS: usize = 64;
+/// Largest factor the sample rate can be reduced by
+pub const MAX_FACTOR: u32 = 4;
+
+const CHANNELS: usize = CHANNEL_COUNT as usize;
+
+/// Factor that brings `sample_rate` closest to `stream_rate`, among those that divide it, so that
+/// the blocks really go out at the reduced rate. 1 if `stream_rate` is 0, for every sample.
+pub fn factor(sample_rate: u32, stream_rate: u32) -> u32 {
+    if stream_rate == 0 {
+        return 1;
+    }
+    (1..=MAX_FACTOR)
+        .filter(|&factor| sample_rate.is_multiple_of(factor))
+        .min_by_key(|&factor| (sample_rate / factor).abs_diff(stream_rate))
+        .unwrap_or(1)
+}
+
+/// Turns sample blocks into full blocks at a fraction of their sample rate
+pub struct BlockDecimator {
+    factor: u32,
+    resampler: Resampler<TAPS, CHANNELS>,
+    /// Being filled with the decimated samples
+    block: SampleBlock,
+    /// `sample_counter` the next block should start at, `None` before the first one
+    next_counter: Option<u64>,
+    /// Set once the filter got its first sample since starting over
+    started: bool,
+}
+
+impl BlockDecimator {
+    /// Keeps one sample in `factor`. Panics if that isn't between 2 and [`MAX_FACTOR`].
+    pub fn new(factor: u32) -> Self {
+        assert!(
+            (2..=MAX_FACTOR).contains(&factor),
+            "can only reduce the sample rate by 2 to {MAX_FACTOR}"
+        );
+        Self {
+            factor,
+            resampler: Resampler::decimator(factor as usize),
+            block: SampleBlock::default(),
+            next_counter: None,
+            started: false,
+        }
+    }
+
+    pub fn factor(&self) -> u32 {
+        self.factor
+    }
+
+    /// Adds the samples of `block`, and hands every block of decimated samples this fills to
+    /// `emit`. Decimated blocks count samples at the reduced rate, and lag
+    /// [`Resampler::delay`] samples of the full rate behind the samples they are made of.
+    ///
+    /// The filter starts over when blocks are missing, or their sample rate or channels change,
+    /// emitting the decimated samples so far first. Blocks at a sample rate that can't be divided
+    /// by the factor are passed through as they are.
+    pub fn push(&mut self, block: &SampleBlock, mut emit: impl FnMut(&SampleBlock)) {
+        let channels = usize::from(block.channel_count).min(CHANNELS);
+        if channels == 0 || block.sample_rate == 0 {
+            return;
+        }
+        if !block.sample_rate.is_multiple_of(self.factor) {
+            self.flush(&mut emit);
+            self.next_counter = None;
+            emit(block);
+            return;
+        }
+        let sample_rate = block.sample_rate / self.factor;
+        if self.next_counter != Some(block.sample_counter)
+            || sample_rate != self.block.sample_rate
+            || block.channel_count != self.block.channel_count
+        {
+            self.flush(&mut emit);
+            self.resampler.reset();
+            self.started = false;
+            self.block.sample_rate = sample_rate;
+            self.block.channel_count = block.channel_count;
+        }
+        self.next_counter = Some(block.sample_counter + u64::from(block.frames));
+        self.block.lead_off = block.lead_off;
+
+        let factor = u64::from(self.factor);
+        for (index, samples) in block.samples().chunks_exact(channels).enumerate() {
+            let counter = block.sample_counter + index as u64;
+            // Until a whole multiple of the factor after starting over, so that the decimated
+            // samples are counted in step with the full rate ones
+            if !self.started && !counter.is_multiple_of(factor) {
+                continue;
+            }
+            self.started = true;
+            let mut frame = [0.0; CHANNELS];
+            for (x, &sample) in frame.iter_mut().zip(samples) {
+                *x = sample as f32;
+            }
+
+            let Self {
+                resampler,
+                block: decimated,
+                ..
+            } = self;
+            resampler.push(&frame, |output| {
+                if decimated.frames == 0 {
+                    let elapsed = index as u64 * 1_000_000 / u64::from(block.sample_rate);
+                    decimated.sample_counter = counter / factor;
+                    decimated.timestamp = block.timestamp + elapsed;
+                }
+                let start = usize::from(decimated.frames) * channels;
+                for (sample, &y) in decimated.samples[start..start + channels]
+                    .iter_mut()
+                    .zip(output)
+                {
+                    *sample = round(y);
+                }
+                decimated.frames += 1;
+                if usize::from(decimated.frames) == BLOCK_FRAMES {
+                    emit(decimated);
+                    decimated.frames = 0;
+                }
+            });
+        }
+    }
+
+    /// Starts over, e.g. when streaming stops. Decimated samples not yet emitted are dropped.
+    pub fn reset(&mut self) {
+        self.resampler.reset();
+        self.block.frames = 0;
+        self.next_counter = None;
+        self.started = false;
+    }
+
+    fn flush(&mut self, emit: &mut impl FnMut(&SampleBlock)) {
+        if self.block.frames > 0 {
+            emit(&self.block);
+            self.block.frames = 0;
+        }
+    }
+}
+
+/// To the nearest count, saturating at the range of a 24-bit sample. The filter overshoots at
+/// steps, which could otherwise leave that range.
+fn round(x: f32) -> i32 {
+    let rounded = if x < 0.0 {
+        (x - 0.5) as i32
+    } else {
+        (x + 0.5) as i32
+    };
+    rounded.clamp(proto::SAMPLE_MIN, proto::SAMPLE_MAX)
+}
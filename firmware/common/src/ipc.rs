@@ -0,0 +1,186 @@
+//! Messages exchanged between the two cores.
+//!
+//! The network core owns the link to the host. It forwards the commands it doesn't handle itself
+//! to the application core, which sends back replies and sample frames for the host. Each
+//! direction is a queue in shared RAM, see [`crate::IPC_CHANNELS`]. After pushing to a queue, the
+//! sending core triggers an IPC event so that the other core looks at it (see [`crate::signal`]):
+//! - channel 0: the network core started
+//! - channel 1: application → network queue
+//! - channel 2: network → application queue
+//! - channel 3: a sample block was committed, see [`crate::SAMPLE_BLOCKS`]
+//! - channel 4: application → network RPC queue, see [`crate::rpc`]
+//! - channel 5: network → application RPC queue
+
+use crate::ring_buffer::UninitRingBuffer;
+use crate::rpc::{Frame, RPC_QUEUE_LEN};
+use crate::CHANNEL_COUNT;
+use proto::capnp;
+use proto::from_edge_capnp::{from_edge, ErrorCode};
+use proto::no_alloc;
+
+/// Longest message that can be passed between the cores
+pub const MAX_MESSAGE_LEN: usize = 512;
+/// Messages that can be waiting in each direction
+pub const QUEUE_LEN: usize = 16;
+/// Samples of each channel in a [`SampleBlock`]. Chosen so that a full block still fits in a
+/// single reply to the host.
+pub const BLOCK_FRAMES: usize = 16;
+/// Sample blocks going round between the cores
+pub const SAMPLE_BLOCK_COUNT: usize = 3;
+
+/// An encoded protocol message, copied as is through the queues
+#[derive(Clone, Copy)]
+pub struct Payload {
+    len: u16,
+    bytes: [u8; MAX_MESSAGE_LEN],
+}
+
+impl Payload {
+    /// Copies `bytes`, or returns `None` if they're longer than [`MAX_MESSAGE_LEN`]
+    pub fn new(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > MAX_MESSAGE_LEN {
+            return None;
+        }
+        let mut payload = Self::empty();
+        payload.bytes[..bytes.len()].copy_from_slice(bytes);
+        payload.len = bytes.len() as u16;
+        Some(payload)
+    }
+
+    /// Creates a payload in place, `write` returns how many bytes it wrote to its buffer
+    pub fn encode<E>(write: impl FnOnce(&mut [u8]) -> Result<usize, E>) -> Result<Self, E> {
+        let mut payload = Self::empty();
+        payload.len = write(&mut payload.bytes)? as u16;
+        Ok(payload)
+    }
+
+    const fn empty() -> Self {
+        Self {
+            len: 0,
+            bytes: [0; MAX_MESSAGE_LEN],
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Message {
+    /// `ToEdge` command from the host, for the application core to handle (network → application)
+    Command(Payload),
+    /// `FromEdge` message to send to the host as is, e.g. the reply to a command or a sample frame
+    /// (application → network)
+    ToHost(Payload),
+}
+
+impl defmt::Format for Message {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Message::Command(payload) => defmt::write!(f, "Command({} bytes)", payload.len),
+            Message::ToHost(payload) => defmt::write!(f, "ToHost({} bytes)", payload.len),
+        }
+    }
+}
+
+/// Fills in `reply` with an acknowledgement of the command `request_id`, for either core to send
+pub fn build_ack(mut reply: from_edge::Builder, request_id: u32) -> capnp::Result<()> {
+    reply.set_request_id(request_id);
+    reply.set_ack(());
+    Ok(())
+}
+
+/// Fills in `reply` with an error for the command `request_id`, for either core to send
+pub fn build_error(
+    mut reply: from_edge::Builder,
+    request_id: u32,
+    code: ErrorCode,
+    message: &str,
+) -> capnp::Result<()> {
+    reply.set_request_id(request_id);
+    let mut error = reply.init_error();
+    error.set_code(code);
+    error.set_message(no_alloc::check_text(message)?);
+    Ok(())
+}
+
+/// One queue of messages and one of RPC frames in each direction between the cores
+pub struct Channels<const N: usize> {
+    pub app_to_net: UninitRingBuffer<Message, N>,
+    pub net_to_app: UninitRingBuffer<Message, N>,
+    pub rpc_app_to_net: UninitRingBuffer<Frame, RPC_QUEUE_LEN>,
+    pub rpc_net_to_app: UninitRingBuffer<Frame, RPC_QUEUE_LEN>,
+}
+
+impl<const N: usize> Default for Channels<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Channels<N> {
+    pub const fn new() -> Self {
+        Self {
+            app_to_net: UninitRingBuffer::new(),
+            net_to_app: UninitRingBuffer::new(),
+            rpc_app_to_net: UninitRingBuffer::new(),
+            rpc_net_to_app: UninitRingBuffer::new(),
+        }
+    }
+
+    /// Empties every queue. Called by the application core before it releases the network core.
+    ///
+    /// # Safety
+    ///
+    /// Neither core may be using the queues
+    pub unsafe fn init(&self) {
+        unsafe {
+            self.app_to_net.init();
+            self.net_to_app.init();
+            self.rpc_app_to_net.init();
+            self.rpc_net_to_app.init();
+        }
+    }
+}
+
+/// Consecutive samples of every enabled channel, filled in place in shared RAM by the application
+/// core and sent to the host by the network core
+#[derive(Clone)]
+pub struct SampleBlock {
+    /// Index of the first sample in this block since streaming started
+    pub sample_counter: u64,
+    /// Device time at which the first sample was taken (microseconds)
+    pub timestamp: u64,
+    pub sample_rate: u32,
+    /// Bit `n` is set if the electrode of channel `n` has lost contact
+    pub lead_off: u32,
+    /// Number of channels interleaved in `samples`
+    pub channel_count: u8,
+    /// Samples taken of each channel, up to [`BLOCK_FRAMES`]
+    pub frames: u16,
+    /// Interleaved by channel, only the first `frames * channel_count` are valid
+    pub samples: [i32; BLOCK_FRAMES * CHANNEL_COUNT as usize],
+}
+
+impl Default for SampleBlock {
+    fn default() -> Self {
+        Self {
+            sample_counter: 0,
+            timestamp: 0,
+            sample_rate: 0,
+            lead_off: 0,
+            channel_count: 0,
+            frames: 0,
+            samples: [0; BLOCK_FRAMES * CHANNEL_COUNT as usize],
+        }
+    }
+}
+
+impl SampleBlock {
+    /// The valid samples of the block
+    pub fn samples(&self) -> &[i32] {
+        let len = self.frames as usize * self.channel_count as usize;
+        &self.samples[..len.min(self.samples.len())]
+    }
+}
@@ -1,96 +1,47 @@
 #![no_std]
 
-pub const EEG_DATA_SERVICE_UUID: [u8; 16] = [
-    255, 77, 189, 23, 34, 96, 77, 13, 167, 102, 45, 228, 119, 88, 43, 141,
-];
-
-#[allow(dead_code)]
-#[unsafe(link_section = ".shared_ram.ble_queue")]
-pub static BLE_QUEUE: crate::ring_buffer::UninitRingBuffer<u64, 1024> =
-    crate::ring_buffer::UninitRingBuffer::new();
-
-pub mod ring_buffer {
-
-    use core::{cell::UnsafeCell, sync::atomic::AtomicBool};
-    use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch};
-    use heapless::spsc;
-
-    unsafe impl<T: Sync + Copy, const N: usize> Sync for UninitRingBuffer<T, N> {}
-
-    pub struct RingBufferProducer<'a, T, const M: usize> {
-        sender: spsc::Producer<'a, T>,
-        signal: watch::Sender<'a, CriticalSectionRawMutex, (), M>,
-    }
-
-    impl<'a, T, const M: usize> RingBufferProducer<'a, T, M> {
-        pub fn send(&mut self, value: T) -> Result<(), T> {
-            let res = self.sender.enqueue(value);
-            if res.is_ok() {
-                self.signal.send(())
-            }
-            res
-        }
-    }
-
-    pub struct RingBufferConsumer<'a, T, const M: usize> {
-        receiver: spsc::Consumer<'a, T>,
-        signal: watch::Receiver<'a, CriticalSectionRawMutex, (), M>,
-    }
-
-    impl<'a, T, const M: usize> RingBufferConsumer<'a, T, M> {
-        pub async fn recv(&mut self) -> T {
-            loop {
-                self.signal.changed().await;
-                if self.receiver.ready() {
-                    return unsafe { self.receiver.dequeue_unchecked() };
-                };
-            }
-        }
-    }
-    /// Ring buffer that has not yet been initialized
-    pub struct UninitRingBuffer<T: Copy, const N: usize> {
-        ring_buffer: UnsafeCell<spsc::Queue<T, N>>,
-    }
-
-    impl<T: Copy, const N: usize> UninitRingBuffer<T, N> {
-        pub const fn new() -> Self {
-            Self {
-                ring_buffer: UnsafeCell::new(spsc::Queue::new()),
-            }
-        }
-
-        /// Gets the sender part of this channel. Safety: This should only be called once
-        pub unsafe fn get_sender(&self) -> spsc::Producer<'_, T> {
-            unsafe { &mut *(self.ring_buffer.get() as *mut spsc::Queue<T, N>) }
-                .split()
-                .0
-        }
-
-        /// Gets the sender part of this channel. Safety: This should only be called once
-        pub unsafe fn get_receiver(&self) -> spsc::Consumer<'_, T> {
-            unsafe { &mut *(self.ring_buffer.get() as *mut spsc::Queue<T, N>) }
-                .split()
-                .1
-        }
-
-        pub unsafe fn get_receiver_with_signal<'a, const M: usize>(
-            &'a self,
-            signal: watch::Receiver<'a, CriticalSectionRawMutex, (), M>,
-        ) -> RingBufferConsumer<'a, T, M> {
-            RingBufferConsumer {
-                receiver: unsafe { self.get_receiver() },
-                signal,
-            }
-        }
-
-        pub unsafe fn get_sender_with_signal<'a, const M: usize>(
-            &'a self,
-            signal: watch::Sender<'a, CriticalSectionRawMutex, (), M>,
-        ) -> RingBufferProducer<'a, T, M> {
-            RingBufferProducer {
-                sender: unsafe { self.get_sender() },
-                signal,
-            }
-        }
-    }
-}
+pub use proto;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "band-power")]
+pub mod band_power;
+pub mod block_buffer;
+pub mod clock;
+pub mod config_store;
+#[cfg(feature = "impedance")]
+pub mod contact;
+#[cfg(feature = "decimation")]
+pub mod decimate;
+pub mod ipc;
+pub mod log;
+pub mod ring_buffer;
+pub mod rpc;
+pub mod shared_ram;
+pub mod signal;
+
+pub const EEG_DATA_SERVICE_UUID: [u8; 16] = proto::SERVICE_UUID;
+
+/// Board the firmware is built for, as reported to the host
+pub const HARDWARE_REV: &str = "nrf5340-dk";
+/// Number of EEG channels on the analog front-end
+pub const CHANNEL_COUNT: u8 = 8;
+/// Sample rates the analog front-end can be configured to (Hz)
+pub const SAMPLE_RATES: [u32; 7] = [250, 500, 1000, 2000, 4000, 8000, 16000];
+
+/// Layout of shared RAM as seen by the application core, see [`shared_ram`]. Placed at the start of
+/// shared RAM by both `memory.x`, which check it through its unmangled name.
+#[unsafe(no_mangle)]
+#[unsafe(link_section = ".shared_ram.header")]
+pub static SHARED_RAM_HEADER: shared_ram::LayoutHeader = shared_ram::LayoutHeader::new();
+
+/// Queues between the two cores, see [`ipc`]. Only used once [`SHARED_RAM_HEADER`] was checked.
+#[unsafe(link_section = ".shared_ram.ipc")]
+pub static IPC_CHANNELS: ipc::Channels<{ ipc::QUEUE_LEN }> = ipc::Channels::new();
+
+/// Sample blocks from the application core to the network core, see [`block_buffer`]. Only used
+/// once [`SHARED_RAM_HEADER`] was checked.
+#[unsafe(link_section = ".shared_ram.blocks")]
+pub static SAMPLE_BLOCKS: block_buffer::BlockBuffer<ipc::SampleBlock, { ipc::SAMPLE_BLOCK_COUNT }> =
+    block_buffer::BlockBuffer::new();
@@ -0,0 +1,158 @@
+//! Log records forwarded to the host over the data link.
+//!
+//! Records logged through a [`LogSink`] always go to defmt, and the ones at or above the level
+//! chosen by the host are also queued, for the network core to send out as `LogRecord` messages.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use proto::from_edge_capnp::{Core as ProtoCore, LogLevel};
+
+/// Longest text forwarded for a single record, longer ones are truncated
+pub const MAX_TEXT_LEN: usize = proto::no_alloc::MAX_TEXT_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// One of the two cores, e.g. the one a record was logged on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Core {
+    App,
+    Net,
+}
+
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Trace => LogLevel::Trace,
+            Level::Debug => LogLevel::Debug,
+            Level::Info => LogLevel::Info,
+            Level::Warn => LogLevel::Warn,
+            Level::Error => LogLevel::Error,
+        }
+    }
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => Level::Trace,
+            LogLevel::Debug => Level::Debug,
+            LogLevel::Info => Level::Info,
+            LogLevel::Warn => Level::Warn,
+            LogLevel::Error => Level::Error,
+        }
+    }
+}
+
+impl From<Core> for ProtoCore {
+    fn from(core: Core) -> Self {
+        match core {
+            Core::App => ProtoCore::App,
+            Core::Net => ProtoCore::Net,
+        }
+    }
+}
+
+pub struct Record {
+    pub level: Level,
+    pub core: Core,
+    /// Time since boot (µs)
+    pub timestamp: u64,
+    pub text: heapless::String<MAX_TEXT_LEN>,
+}
+
+/// Writes as much as fits into a string, dropping the rest
+struct Truncating<'a>(&'a mut heapless::String<MAX_TEXT_LEN>);
+
+impl Write for Truncating<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Nothing is forwarded while the level is set to this
+const FORWARDING_OFF: u8 = u8::MAX;
+
+/// Queue of the records of one core waiting to be sent to the host
+pub struct LogSink<const N: usize> {
+    core: Core,
+    /// Lowest [`Level`] forwarded, as a `u8`
+    level: AtomicU8,
+    records: Channel<CriticalSectionRawMutex, Record, N>,
+    /// Records that didn't fit in the queue
+    dropped: AtomicU32,
+}
+
+impl<const N: usize> LogSink<N> {
+    /// Creates a sink that doesn't forward anything until [`LogSink::set_level`] is called
+    pub const fn new(core: Core) -> Self {
+        Self {
+            core,
+            level: AtomicU8::new(FORWARDING_OFF),
+            records: Channel::new(),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    /// Forwards records of `level` and above, or nothing for `None`
+    pub fn set_level(&self, level: Option<Level>) {
+        let level = level.map_or(FORWARDING_OFF, |level| level as u8);
+        self.level.store(level, Ordering::Relaxed);
+    }
+
+    pub fn log(&self, level: Level, args: fmt::Arguments) {
+        let mut text = heapless::String::new();
+        let _ = Truncating(&mut text).write_fmt(args);
+        match level {
+            Level::Trace => defmt::trace!("{=str}", text),
+            Level::Debug => defmt::debug!("{=str}", text),
+            Level::Info => defmt::info!("{=str}", text),
+            Level::Warn => defmt::warn!("{=str}", text),
+            Level::Error => defmt::error!("{=str}", text),
+        }
+
+        if (level as u8) < self.level.load(Ordering::Relaxed) {
+            return;
+        }
+        let record = Record {
+            level,
+            core: self.core,
+            timestamp: embassy_time::Instant::now().as_micros(),
+            text,
+        };
+        if self.records.try_send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Waits for the next record to forward
+    pub async fn receive(&self) -> Record {
+        self.records.receive().await
+    }
+
+    /// Number of records dropped because the queue was full since the last call
+    pub fn take_dropped(&self) -> u32 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// Logs to a [`LogSink`] with `format_args!` syntax, e.g. `log!(LOG, Warn, "Lost {} frames", n)`
+#[macro_export]
+macro_rules! log {
+    ($sink:expr, $level:ident, $($arg:tt)*) => {
+        $sink.log($crate::log::Level::$level, format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,439 @@
+//! Single-producer single-consumer queues in shared RAM, with an end on each core.
+//!
+//! Exclusive loads and stores aren't coherent between the cores of the nRF5340, so every position
+//! and counter is only ever written by one end. When the producer drops the oldest item it writes
+//! over it, and the consumer skips what was written over.
+//!
+//! Either end of a queue can only be taken once, and belongs to a fixed core: the one named by the
+//! field of [`crate::ipc::Channels`] it's in. Whether it was taken is stored in the queue itself,
+//! with a flag for each core that only that core writes, so a second attempt fails on either core.
+//! The images only agree on which core takes what if they agree on the layout of shared RAM, so
+//! neither takes an end before [`crate::shared_ram::LayoutHeader`] checked that. The
+//! [`QueueStats`] of a queue are stored in it too, for either core to read.
+
+use crate::log::Core;
+use crate::signal::{Notify, Wait};
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use embassy_time::{with_timeout, Duration, Timer};
+
+/// How often a producer blocked by [`OverflowPolicy::Block`] checks for room. The consumer is on
+/// the other core, so it can't wake the producer up.
+const BLOCK_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+unsafe impl<T: Sync + Copy, const N: usize> Sync for UninitRingBuffer<T, N> {}
+
+/// What [`RingBufferProducer::send`] does with an item when the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum OverflowPolicy {
+    /// Hands the new item back
+    #[default]
+    DropNewest,
+    /// Drops the oldest item in the queue to make room
+    DropOldest,
+    /// Waits for the consumer to make room
+    Block,
+}
+
+/// Counters of a queue, kept up to date by its ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct QueueStats {
+    /// Items pushed to the queue
+    pub sent: u32,
+    /// Items lost because the queue was full, either refused or pushed out by newer ones
+    pub dropped: u32,
+    /// Most items that were waiting in the queue at once
+    pub high_water: u32,
+    pub capacity: u32,
+}
+
+type Slot<T> = UnsafeCell<MaybeUninit<T>>;
+
+/// Which core took an end of a queue or [`crate::block_buffer::BlockBuffer`]. Exclusive accesses
+/// only work within a core, so each core sets its own flag and then checks the other one.
+pub(crate) struct Taken {
+    app: AtomicBool,
+    net: AtomicBool,
+}
+
+impl Taken {
+    pub(crate) const fn new() -> Self {
+        Self {
+            app: AtomicBool::new(false),
+            net: AtomicBool::new(false),
+        }
+    }
+
+    /// Marks the end as taken by `core`. Returns `false` if either core took it already, or if
+    /// the other core is trying to at the same time, in which case neither gets it.
+    pub(crate) fn take(&self, core: Core) -> bool {
+        let (own, other) = match core {
+            Core::App => (&self.app, &self.net),
+            Core::Net => (&self.net, &self.app),
+        };
+        if own.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        // Our flag is visible to the other core before we look at its own
+        fence(Ordering::SeqCst);
+        if other.load(Ordering::SeqCst) {
+            own.store(false, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+
+    pub(crate) fn reset(&self) {
+        self.app.store(false, Ordering::Release);
+        self.net.store(false, Ordering::Release);
+    }
+}
+
+/// Positions and counters of a queue, shared by both ends
+struct State {
+    /// Index of the next item the consumer takes, wrapping. Only moved by the consumer, which skips
+    /// ahead to `tail - N` if the producer wrote over older items.
+    head: AtomicUsize,
+    /// Index the next item goes to, wrapping. Only moved by the producer.
+    tail: AtomicUsize,
+    /// One past the index of the slot the producer is writing or last wrote, wrapping. Set before
+    /// the slot is written, so that the consumer can tell whether the item it copied out was
+    /// written over meanwhile.
+    claimed: AtomicUsize,
+    sent: AtomicU32,
+    /// Items refused by the producer
+    dropped: AtomicU32,
+    /// Items written over, counted by the consumer as it skips them
+    skipped: AtomicU32,
+    high_water: AtomicU32,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            claimed: AtomicUsize::new(0),
+            sent: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+            skipped: AtomicU32::new(0),
+            high_water: AtomicU32::new(0),
+        }
+    }
+
+    fn reset(&self) {
+        self.head.store(0, Ordering::Release);
+        self.tail.store(0, Ordering::Release);
+        self.claimed.store(0, Ordering::Release);
+        self.sent.store(0, Ordering::Relaxed);
+        self.dropped.store(0, Ordering::Relaxed);
+        self.skipped.store(0, Ordering::Relaxed);
+        self.high_water.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Pushing end of a queue
+pub struct Producer<'a, T> {
+    state: &'a State,
+    slots: &'a [Slot<T>],
+}
+
+// Safety: Only the producer writes to the slots, and the consumer never keeps a reference to them
+unsafe impl<T: Send> Send for Producer<'_, T> {}
+
+impl<T: Copy> Producer<'_, T> {
+    /// Pushes `value`, or hands it back if the queue is full
+    pub fn enqueue(&mut self, value: T) -> Result<(), T> {
+        let tail = self.state.tail.load(Ordering::Relaxed);
+        let head = self.state.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= self.slots.len() {
+            return Err(value);
+        }
+        self.push(tail, value);
+        Ok(())
+    }
+
+    /// Pushes `value` even if the queue is full, writing over the oldest item. The consumer skips
+    /// it, unless it took it in the meantime.
+    fn overwrite_oldest(&mut self, value: T) {
+        self.push(self.state.tail.load(Ordering::Relaxed), value);
+    }
+
+    fn push(&mut self, tail: usize, value: T) {
+        let next = tail.wrapping_add(1);
+        self.state.claimed.store(next, Ordering::Relaxed);
+        // Orders the claim before the write of the slot, for a consumer that sees any of it
+        fence(Ordering::Release);
+        // Safety: Only the producer writes to the slots. If the consumer is copying this one out,
+        // it sees the claim afterwards and throws the copy away.
+        unsafe {
+            ptr::write_volatile(
+                self.slots[tail % self.slots.len()].get(),
+                MaybeUninit::new(value),
+            )
+        };
+        self.state.tail.store(next, Ordering::Release);
+
+        let len = next.wrapping_sub(self.state.head.load(Ordering::Acquire));
+        self.state.sent.fetch_add(1, Ordering::Relaxed);
+        self.state
+            .high_water
+            .fetch_max(len.min(self.slots.len()) as u32, Ordering::Relaxed);
+    }
+
+    fn count_dropped(&self) {
+        self.state.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Popping end of a queue
+pub struct Consumer<'a, T> {
+    state: &'a State,
+    slots: &'a [Slot<T>],
+}
+
+// Safety: See `Producer`
+unsafe impl<T: Send> Send for Consumer<'_, T> {}
+
+impl<T: Copy> Consumer<'_, T> {
+    /// Takes the oldest item, if there is one
+    pub fn dequeue(&mut self) -> Option<T> {
+        let capacity = self.slots.len();
+        loop {
+            let mut head = self.state.head.load(Ordering::Relaxed);
+            let tail = self.state.tail.load(Ordering::Acquire);
+            let overwritten = tail.wrapping_sub(head).saturating_sub(capacity);
+            if overwritten > 0 {
+                head = head.wrapping_add(overwritten);
+                self.state.head.store(head, Ordering::Release);
+                self.state
+                    .skipped
+                    .fetch_add(overwritten as u32, Ordering::Relaxed);
+            }
+            if head == tail {
+                return None;
+            }
+            // The producer may write over this item while we copy it out, in which case it
+            // claimed the slot first and the copy is thrown away
+            let value = unsafe { ptr::read_volatile(self.slots[head % capacity].get()) };
+            fence(Ordering::Acquire);
+            let claimed = self.state.claimed.load(Ordering::Relaxed);
+            if claimed.wrapping_sub(head) <= capacity {
+                self.state
+                    .head
+                    .store(head.wrapping_add(1), Ordering::Release);
+                // Safety: The slot was written to before the tail moved past it
+                return Some(unsafe { value.assume_init() });
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.state.tail.load(Ordering::Acquire);
+        let len = tail.wrapping_sub(self.state.head.load(Ordering::Acquire));
+        len.min(self.slots.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub struct RingBufferProducer<'a, T, S> {
+    sender: Producer<'a, T>,
+    signal: S,
+    policy: OverflowPolicy,
+}
+
+impl<T: Copy, S: Notify> RingBufferProducer<'_, T, S> {
+    /// Changes what happens to items sent while the queue is full, [`OverflowPolicy::DropNewest`]
+    /// by default
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    /// Pushes `value` to the queue, or follows the overflow policy if it's full. Only hands
+    /// `value` back with [`OverflowPolicy::DropNewest`].
+    pub async fn send(&mut self, mut value: T) -> Result<(), T> {
+        loop {
+            match self.sender.enqueue(value) {
+                Ok(()) => {
+                    self.signal.notify();
+                    return Ok(());
+                }
+                Err(rejected) => value = rejected,
+            }
+            match self.policy {
+                OverflowPolicy::DropNewest => {
+                    self.sender.count_dropped();
+                    return Err(value);
+                }
+                OverflowPolicy::DropOldest => {
+                    self.sender.overwrite_oldest(value);
+                    self.signal.notify();
+                    return Ok(());
+                }
+                OverflowPolicy::Block => Timer::after(BLOCK_POLL_INTERVAL).await,
+            }
+        }
+    }
+}
+
+pub struct RingBufferConsumer<'a, T, W> {
+    receiver: Consumer<'a, T>,
+    signal: W,
+}
+
+impl<T: Copy, W: Wait> RingBufferConsumer<'_, T, W> {
+    /// Waits for the next item. Returns straight away if the queue isn't empty, however many
+    /// items were pushed under the last signal.
+    pub async fn recv(&mut self) -> T {
+        loop {
+            if let Some(value) = self.receiver.dequeue() {
+                return value;
+            }
+            // Only returns for signals sent after the last one we waited for, so an item pushed
+            // since the queue was checked still wakes us up
+            self.signal.wait().await;
+        }
+    }
+
+    /// Takes the next item, if there is one
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.receiver.dequeue()
+    }
+
+    /// Waits for the next item for at most `timeout`
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> Option<T> {
+        with_timeout(timeout, self.recv()).await.ok()
+    }
+
+    /// Moves as many items as are waiting, up to `out.len()`, to the start of `out`. Returns how
+    /// many were moved.
+    pub fn drain_into(&mut self, out: &mut [T]) -> usize {
+        let mut count = 0;
+        for slot in out.iter_mut() {
+            let Some(value) = self.receiver.dequeue() else {
+                break;
+            };
+            *slot = value;
+            count += 1;
+        }
+        count
+    }
+}
+
+/// Ring buffer that has not yet been initialized. `N` has to be a power of two.
+pub struct UninitRingBuffer<T: Copy, const N: usize> {
+    state: State,
+    slots: [Slot<T>; N],
+    /// Set once the producer was handed out
+    sender_taken: Taken,
+    /// Set once the consumer was handed out
+    receiver_taken: Taken,
+}
+
+impl<T: Copy, const N: usize> Default for UninitRingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const N: usize> UninitRingBuffer<T, N> {
+    pub const fn new() -> Self {
+        // So that the slot of an index stays the same when the index wraps around
+        const {
+            assert!(
+                N.is_power_of_two(),
+                "ring buffer size has to be a power of two"
+            )
+        };
+        Self {
+            state: State::new(),
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            sender_taken: Taken::new(),
+            receiver_taken: Taken::new(),
+        }
+    }
+
+    /// Empties the queue, clears its stats and makes both ends available again. Shared RAM isn't
+    /// initialized at boot, so this has to be called once before either core takes an end.
+    ///
+    /// # Safety
+    ///
+    /// Neither end may be in use, on either core
+    pub unsafe fn init(&self) {
+        self.state.reset();
+        self.sender_taken.reset();
+        self.receiver_taken.reset();
+    }
+
+    /// Counters of the queue so far, on either core
+    pub fn stats(&self) -> QueueStats {
+        // Including the items written over that the consumer hasn't skipped yet
+        let head = self.state.head.load(Ordering::Acquire);
+        let tail = self.state.tail.load(Ordering::Acquire);
+        let overwritten = tail.wrapping_sub(head).saturating_sub(N) as u32;
+        QueueStats {
+            sent: self.state.sent.load(Ordering::Relaxed),
+            dropped: self.state.dropped.load(Ordering::Relaxed)
+                + self.state.skipped.load(Ordering::Relaxed)
+                + overwritten,
+            high_water: self.state.high_water.load(Ordering::Relaxed),
+            capacity: N as u32,
+        }
+    }
+
+    /// Gets the sender part of this channel on `core`, or `None` if it was already taken by either
+    /// core
+    pub fn take_sender(&self, core: Core) -> Option<Producer<'_, T>> {
+        if !self.sender_taken.take(core) {
+            return None;
+        }
+        Some(Producer {
+            state: &self.state,
+            slots: &self.slots,
+        })
+    }
+
+    /// Gets the receiver part of this channel on `core`, or `None` if it was already taken by
+    /// either core
+    pub fn take_receiver(&self, core: Core) -> Option<Consumer<'_, T>> {
+        if !self.receiver_taken.take(core) {
+            return None;
+        }
+        Some(Consumer {
+            state: &self.state,
+            slots: &self.slots,
+        })
+    }
+
+    /// Gets the receiver part of this channel on `core`, woken up by `signal`, or `None` if it was
+    /// already taken
+    pub fn take_receiver_with_signal<W: Wait>(
+        &self,
+        core: Core,
+        signal: W,
+    ) -> Option<RingBufferConsumer<'_, T, W>> {
+        Some(RingBufferConsumer {
+            receiver: self.take_receiver(core)?,
+            signal,
+        })
+    }
+
+    /// Gets the sender part of this channel on `core`, which signals `signal` after every item, or
+    /// `None` if it was already taken. It starts out with [`OverflowPolicy::DropNewest`].
+    pub fn take_sender_with_signal<S: Notify>(
+        &self,
+        core: Core,
+        signal: S,
+    ) -> Option<RingBufferProducer<'_, T, S>> {
+        Some(RingBufferProducer {
+            sender: self.take_sender(core)?,
+            signal,
+            policy: OverflowPolicy::default(),
+        })
+    }
+}
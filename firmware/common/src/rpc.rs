@@ -0,0 +1,216 @@
+//! Requests from one core to the other, and their replies.
+//!
+//! Each core has an [`Endpoint`], which sends its own requests and answers those of the other core
+//! with a [`Handler`]. Frames go through a [`Transport`], on the device a pair of queues in shared
+//! RAM (see [`crate::ipc`]). An endpoint only needs one task: while a call waits for its reply, the
+//! requests of the other core are answered as they come in, so both cores can call each other at
+//! the same time.
+
+use crate::ring_buffer::{RingBufferConsumer, RingBufferProducer};
+use crate::signal::{Notify, Wait};
+use core::future::Future;
+use embassy_time::{with_deadline, Duration, Instant};
+
+/// Frames that can be waiting in each direction
+pub const RPC_QUEUE_LEN: usize = 4;
+
+/// What one core can ask the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Request {
+    /// Charge of the battery, answered by the application core
+    Battery,
+    /// State of the link with the host, answered by the network core
+    Link,
+    /// Device time, answered by the network core, which the host sets it on
+    Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Reply {
+    Battery(Battery),
+    Link(Link),
+    /// Device time when the request was answered (µs), see [`crate::clock`]
+    Time(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Battery {
+    pub percentage: f32,
+    pub charging: bool,
+    /// Until the battery is full when charging, or empty when discharging (seconds)
+    pub estimated_time: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Link {
+    /// Whether a host is connected
+    pub connected: bool,
+    /// Signal strength of the connection when last measured (dBm)
+    pub rssi: Option<i8>,
+}
+
+/// Why the other core couldn't answer a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum RemoteError {
+    /// The request is answered by the other core
+    Unsupported,
+    /// The core can't answer right now, or lacks the hardware to
+    Unavailable,
+}
+
+/// Why a call failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum RpcError {
+    /// The queue to the other core is full
+    QueueFull,
+    /// No reply came back in time
+    Timeout,
+    /// The other core answered with an error
+    Remote(RemoteError),
+    /// The other core answered with a reply to another kind of request
+    UnexpectedReply,
+}
+
+/// What goes through a [`Transport`]
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Frame {
+    /// Picked by the caller, and sent back with the reply
+    pub id: u16,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Body {
+    Request(Request),
+    Reply(Result<Reply, RemoteError>),
+}
+
+/// Carries frames to the other core and back
+pub trait Transport {
+    /// Sends `frame` to the other core, or hands it back if it can't be sent
+    fn send(&mut self, frame: Frame) -> impl Future<Output = Result<(), Frame>>;
+
+    /// Waits for the next frame from the other core. Dropping the future doesn't lose a frame.
+    fn recv(&mut self) -> impl Future<Output = Frame>;
+}
+
+/// Transport over a queue in each direction
+pub struct QueueTransport<'a, S, W> {
+    pub sender: RingBufferProducer<'a, Frame, S>,
+    pub receiver: RingBufferConsumer<'a, Frame, W>,
+}
+
+impl<S: Notify, W: Wait> Transport for QueueTransport<'_, S, W> {
+    async fn send(&mut self, frame: Frame) -> Result<(), Frame> {
+        self.sender.send(frame).await
+    }
+
+    async fn recv(&mut self) -> Frame {
+        self.receiver.recv().await
+    }
+}
+
+/// Answers the requests of the other core
+pub trait Handler {
+    fn handle(&mut self, request: Request) -> Result<Reply, RemoteError>;
+}
+
+impl<F: FnMut(Request) -> Result<Reply, RemoteError>> Handler for F {
+    fn handle(&mut self, request: Request) -> Result<Reply, RemoteError> {
+        self(request)
+    }
+}
+
+/// One side of the RPC link between the cores
+pub struct Endpoint<T, H> {
+    transport: T,
+    handler: H,
+    next_id: u16,
+}
+
+impl<T: Transport, H: Handler> Endpoint<T, H> {
+    pub fn new(transport: T, handler: H) -> Self {
+        Self {
+            transport,
+            handler,
+            next_id: 0,
+        }
+    }
+
+    /// Waits for the next request of the other core and answers it. Replies that come in too late
+    /// for their call are dropped on the way. Can be cancelled between requests.
+    pub async fn serve(&mut self) {
+        loop {
+            let frame = self.transport.recv().await;
+            if self.answer(frame).await {
+                return;
+            }
+        }
+    }
+
+    /// Sends `request` to the other core and waits at most `timeout` for the reply
+    pub async fn call(&mut self, request: Request, timeout: Duration) -> Result<Reply, RpcError> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let deadline = Instant::now() + timeout;
+        let frame = Frame {
+            id,
+            body: Body::Request(request),
+        };
+        if self.transport.send(frame).await.is_err() {
+            return Err(RpcError::QueueFull);
+        }
+        loop {
+            let Ok(frame) = with_deadline(deadline, self.transport.recv()).await else {
+                return Err(RpcError::Timeout);
+            };
+            match frame.body {
+                Body::Reply(reply) if frame.id == id => return reply.map_err(RpcError::Remote),
+                _ => {
+                    self.answer(frame).await;
+                }
+            }
+        }
+    }
+
+    /// Asks the application core for the charge of the battery
+    pub async fn battery(&mut self, timeout: Duration) -> Result<Battery, RpcError> {
+        match self.call(Request::Battery, timeout).await? {
+            Reply::Battery(battery) => Ok(battery),
+            _ => Err(RpcError::UnexpectedReply),
+        }
+    }
+
+    /// Asks the network core for the state of the link with the host
+    pub async fn link(&mut self, timeout: Duration) -> Result<Link, RpcError> {
+        match self.call(Request::Link, timeout).await? {
+            Reply::Link(link) => Ok(link),
+            _ => Err(RpcError::UnexpectedReply),
+        }
+    }
+
+    /// Asks the network core for the device time
+    pub async fn time(&mut self, timeout: Duration) -> Result<u64, RpcError> {
+        match self.call(Request::Time, timeout).await? {
+            Reply::Time(time) => Ok(time),
+            _ => Err(RpcError::UnexpectedReply),
+        }
+    }
+
+    /// Answers `frame` if it's a request. Returns whether it was.
+    async fn answer(&mut self, frame: Frame) -> bool {
+        let Body::Request(request) = frame.body else {
+            defmt::debug!("Dropping the late reply to call {}", frame.id);
+            return false;
+        };
+        let reply = Frame {
+            id: frame.id,
+            body: Body::Reply(self.handler.handle(request)),
+        };
+        // The caller times out instead
+        if self.transport.send(reply).await.is_err() {
+            defmt::warn!("Couldn't reply to call {}", frame.id);
+        }
+        true
+    }
+}
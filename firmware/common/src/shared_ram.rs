@@ -0,0 +1,242 @@
+//! Layout of the RAM shared by the two cores.
+//!
+//! Each core places `.shared_ram` through its own `memory.x` and has its own copy of the types
+//! stored there, so nothing stops two images built from different versions of this crate from
+//! disagreeing on where things are. The application core describes its layout in a
+//! [`LayoutHeader`] at [`SHARED_RAM_START`] before releasing the network core, which compares it
+//! to its own and records whether they match. Neither core uses the queues or the sample blocks
+//! unless both agree.
+
+use crate::block_buffer::BlockBuffer;
+use crate::ipc::{
+    Channels, Message, Payload, SampleBlock, BLOCK_FRAMES, MAX_MESSAGE_LEN, QUEUE_LEN,
+    SAMPLE_BLOCK_COUNT,
+};
+use crate::rpc::{self, RPC_QUEUE_LEN};
+use core::mem::{align_of, size_of};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Start of `SHARED_RAM` in both `memory.x`, where the header has to be
+pub const SHARED_RAM_START: usize = 0x2004_0000;
+
+/// Marks a header written by the application core, rather than what was left in RAM
+const MAGIC: u32 = u32::from_le_bytes(*b"EEGH");
+/// Bumped on changes to shared RAM that don't show in the sizes below, e.g. reordering fields
+const LAYOUT_VERSION: u32 = 1;
+
+/// Hash of everything both images have to agree on
+const LAYOUT_HASH: u32 = {
+    let sizes = [
+        LAYOUT_VERSION,
+        size_of::<LayoutHeader>() as u32,
+        size_of::<Channels<QUEUE_LEN>>() as u32,
+        align_of::<Channels<QUEUE_LEN>>() as u32,
+        size_of::<Message>() as u32,
+        size_of::<Payload>() as u32,
+        QUEUE_LEN as u32,
+        MAX_MESSAGE_LEN as u32,
+        size_of::<rpc::Frame>() as u32,
+        RPC_QUEUE_LEN as u32,
+        size_of::<Blocks>() as u32,
+        align_of::<Blocks>() as u32,
+        size_of::<SampleBlock>() as u32,
+        BLOCK_FRAMES as u32,
+    ];
+    let mut hash = fnv1a(FNV_OFFSET, env!("CARGO_PKG_VERSION").as_bytes());
+    let mut i = 0;
+    while i < sizes.len() {
+        hash = fnv1a(hash, &sizes[i].to_le_bytes());
+        i += 1;
+    }
+    hash
+};
+
+type Blocks = BlockBuffer<SampleBlock, SAMPLE_BLOCK_COUNT>;
+
+const FNV_OFFSET: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+const fn fnv1a(mut hash: u32, bytes: &[u8]) -> u32 {
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
+/// Whether the network core accepted the layout of the application core
+const NET_CORE_PENDING: u32 = 0;
+const NET_CORE_ACCEPTED: u32 = 1;
+const NET_CORE_REJECTED: u32 = 2;
+
+/// Field of the header that differs between the images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Field {
+    Version,
+    LayoutHash,
+    HeaderSize,
+    ChannelsSize,
+    /// Where the queues are, relative to the header
+    ChannelsOffset,
+    BlocksSize,
+    /// Where the sample blocks are, relative to the header
+    BlocksOffset,
+}
+
+/// Why the queues and sample blocks between the cores can't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LayoutError {
+    /// The header isn't at [`SHARED_RAM_START`] in this image
+    Misplaced { address: usize },
+    /// The application core didn't write a header
+    Missing,
+    /// The header was written by an image with a different layout
+    Mismatch {
+        field: Field,
+        expected: u32,
+        found: u32,
+    },
+    /// The network core found that the layout didn't match its own, or is too old to check it
+    RejectedByNetCore,
+}
+
+/// Description of shared RAM, written by the application core at boot
+#[repr(C)]
+pub struct LayoutHeader {
+    /// Written last, once the rest of the header is valid
+    magic: AtomicU32,
+    version: AtomicU32,
+    layout_hash: AtomicU32,
+    header_size: AtomicU32,
+    channels_size: AtomicU32,
+    channels_offset: AtomicU32,
+    blocks_size: AtomicU32,
+    blocks_offset: AtomicU32,
+    /// Set by the network core once it checked the header
+    net_core: AtomicU32,
+}
+
+impl Default for LayoutHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LayoutHeader {
+    pub const fn new() -> Self {
+        Self {
+            magic: AtomicU32::new(0),
+            version: AtomicU32::new(0),
+            layout_hash: AtomicU32::new(0),
+            header_size: AtomicU32::new(0),
+            channels_size: AtomicU32::new(0),
+            channels_offset: AtomicU32::new(0),
+            blocks_size: AtomicU32::new(0),
+            blocks_offset: AtomicU32::new(0),
+            net_core: AtomicU32::new(NET_CORE_PENDING),
+        }
+    }
+
+    /// Describes the layout of this image. Called by the application core before it releases the
+    /// network core.
+    ///
+    /// # Safety
+    ///
+    /// The network core may not be running
+    pub unsafe fn write(&self) {
+        self.magic.store(0, Ordering::Relaxed);
+        self.version.store(LAYOUT_VERSION, Ordering::Relaxed);
+        self.layout_hash.store(LAYOUT_HASH, Ordering::Relaxed);
+        self.header_size
+            .store(size_of::<Self>() as u32, Ordering::Relaxed);
+        self.channels_size
+            .store(size_of::<Channels<QUEUE_LEN>>() as u32, Ordering::Relaxed);
+        self.channels_offset
+            .store(channels_offset(), Ordering::Relaxed);
+        self.blocks_size
+            .store(size_of::<Blocks>() as u32, Ordering::Relaxed);
+        self.blocks_offset.store(blocks_offset(), Ordering::Relaxed);
+        self.net_core.store(NET_CORE_PENDING, Ordering::Relaxed);
+        self.magic.store(MAGIC, Ordering::Release);
+    }
+
+    /// Compares the header to the layout of this image
+    pub fn check(&self) -> Result<(), LayoutError> {
+        let address = self as *const Self as usize;
+        if address != SHARED_RAM_START {
+            return Err(LayoutError::Misplaced { address });
+        }
+        if self.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(LayoutError::Missing);
+        }
+        let fields = [
+            (Field::Version, &self.version, LAYOUT_VERSION),
+            (Field::LayoutHash, &self.layout_hash, LAYOUT_HASH),
+            (
+                Field::HeaderSize,
+                &self.header_size,
+                size_of::<Self>() as u32,
+            ),
+            (
+                Field::ChannelsSize,
+                &self.channels_size,
+                size_of::<Channels<QUEUE_LEN>>() as u32,
+            ),
+            (
+                Field::ChannelsOffset,
+                &self.channels_offset,
+                channels_offset(),
+            ),
+            (
+                Field::BlocksSize,
+                &self.blocks_size,
+                size_of::<Blocks>() as u32,
+            ),
+            (Field::BlocksOffset, &self.blocks_offset, blocks_offset()),
+        ];
+        for (field, value, expected) in fields {
+            let found = value.load(Ordering::Relaxed);
+            if found != expected {
+                return Err(LayoutError::Mismatch {
+                    field,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the header on the network core, and records the result for the application core
+    pub fn check_on_net_core(&self) -> Result<(), LayoutError> {
+        let result = self.check();
+        let state = match result {
+            Ok(()) => NET_CORE_ACCEPTED,
+            Err(_) => NET_CORE_REJECTED,
+        };
+        self.net_core.store(state, Ordering::Release);
+        result
+    }
+
+    /// Checks the header on the application core, once the network core had its own look at it
+    pub fn check_on_app_core(&self) -> Result<(), LayoutError> {
+        self.check()?;
+        match self.net_core.load(Ordering::Acquire) {
+            NET_CORE_ACCEPTED => Ok(()),
+            _ => Err(LayoutError::RejectedByNetCore),
+        }
+    }
+}
+
+/// Offset of the queues from the start of shared RAM in this image
+fn channels_offset() -> u32 {
+    (&crate::IPC_CHANNELS as *const Channels<QUEUE_LEN> as usize).wrapping_sub(SHARED_RAM_START)
+        as u32
+}
+
+/// Offset of the sample blocks from the start of shared RAM in this image
+fn blocks_offset() -> u32 {
+    (&crate::SAMPLE_BLOCKS as *const Blocks as usize).wrapping_sub(SHARED_RAM_START) as u32
+}
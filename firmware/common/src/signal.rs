@@ -0,0 +1,96 @@
+//! How one side of a queue wakes up the other.
+//!
+//! The queues in [`crate::ring_buffer`] and [`crate::block_buffer`] are in memory both sides can
+//! read, and only need a way to tell the other side to look at them again. On the device, that's
+//! an IPC event between the cores, implemented by each core as they use different versions of
+//! embassy-nrf. A [`watch`](embassy_sync::watch) works within a core, and with the `std` feature
+//! [`thread_signal`] works between threads, so the logic of both cores can run on the host.
+
+use core::future::Future;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::watch;
+
+#[cfg(feature = "std")]
+pub use thread::{thread_signal, ThreadNotifier, ThreadWaiter};
+
+/// Sending end of a signal
+pub trait Notify {
+    /// Wakes up the other side, or makes its next wait return straight away if it isn't waiting
+    fn notify(&mut self);
+}
+
+/// Receiving end of a signal
+pub trait Wait {
+    /// Waits for a notification sent since the last wait returned. Notifications sent in the
+    /// meantime only count once. Dropping the future doesn't lose a notification.
+    fn wait(&mut self) -> impl Future<Output = ()>;
+}
+
+impl<M: RawMutex, const N: usize> Notify for watch::Sender<'_, M, (), N> {
+    fn notify(&mut self) {
+        self.send(());
+    }
+}
+
+impl<M: RawMutex, const N: usize> Wait for watch::Receiver<'_, M, (), N> {
+    async fn wait(&mut self) {
+        self.changed().await;
+    }
+}
+
+#[cfg(feature = "std")]
+mod thread {
+    use super::{Notify, Wait};
+    use core::future::poll_fn;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::task::{Poll, Waker};
+    use std::sync::{Arc, Mutex};
+
+    struct Shared {
+        /// Set by a notification until a wait returns for it
+        pending: AtomicBool,
+        /// Of the task waiting, if any
+        waker: Mutex<Option<Waker>>,
+    }
+
+    /// Sending end of a [`thread_signal`]
+    pub struct ThreadNotifier(Arc<Shared>);
+
+    /// Receiving end of a [`thread_signal`]
+    pub struct ThreadWaiter(Arc<Shared>);
+
+    /// A signal from one thread to another, whatever executor they run
+    pub fn thread_signal() -> (ThreadNotifier, ThreadWaiter) {
+        let shared = Arc::new(Shared {
+            pending: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+        (ThreadNotifier(shared.clone()), ThreadWaiter(shared))
+    }
+
+    impl Notify for ThreadNotifier {
+        fn notify(&mut self) {
+            self.0.pending.store(true, Ordering::Release);
+            if let Some(waker) = self.0.waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl Wait for ThreadWaiter {
+        async fn wait(&mut self) {
+            poll_fn(|cx| {
+                if self.0.pending.swap(false, Ordering::Acquire) {
+                    return Poll::Ready(());
+                }
+                *self.0.waker.lock().unwrap() = Some(cx.waker().clone());
+                // Notified before the waker was in place
+                if self.0.pending.swap(false, Ordering::Acquire) {
+                    return Poll::Ready(());
+                }
+                Poll::Pending
+            })
+            .await
+        }
+    }
+}
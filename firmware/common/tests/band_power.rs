@@ -0,0 +1,101 @@
+use common::band_power::{BandPowerStream, DEFAULT_CONFIG, MAX_BANDS};
+use common::ipc::{SampleBlock, BLOCK_FRAMES};
+use common::proto::from_edge_capnp::from_edge;
+use common::proto::no_alloc;
+use dsp::welch::EEG_BANDS;
+use std::f64::consts::PI;
+
+const SAMPLE_RATE: u32 = 250;
+
+/// Block `index` of a 10 Hz sine of amplitude 1000 on channel 0 and silence on channel 1
+fn block(index: u64) -> SampleBlock {
+    let mut block = SampleBlock {
+        sample_counter: index * BLOCK_FRAMES as u64,
+        timestamp: 1_000_000 + index * BLOCK_FRAMES as u64 * 4_000,
+        sample_rate: SAMPLE_RATE,
+        channel_count: 2,
+        frames: BLOCK_FRAMES as u16,
+        ..Default::default()
+    };
+    for frame in 0..BLOCK_FRAMES {
+        let t = (block.sample_counter + frame as u64) as f64 / f64::from(SAMPLE_RATE);
+        block.samples[2 * frame] = (1_000.0 * (2.0 * PI * 10.0 * t).sin()) as i32;
+        block.samples[2 * frame + 1] = -7;
+    }
+    block
+}
+
+/// Blocks that produced a message, and the first one
+fn run(stream: &mut BandPowerStream, blocks: impl Iterator<Item = u64>) -> (Vec<u64>, Vec<u8>) {
+    let mut out = [0; 512];
+    let mut first = None;
+    let mut sent = Vec::new();
+    for index in blocks {
+        if let Some(len) = stream.push(&block(index), &mut out) {
+            first.get_or_insert_with(|| out[..len].to_vec());
+            sent.push(index);
+        }
+    }
+    (sent, first.unwrap_or_default())
+}
+
+#[test]
+fn estimates_are_encoded_as_band_power() {
+    let mut stream = BandPowerStream::new(DEFAULT_CONFIG, &EEG_BANDS);
+    let (sent, message) = run(&mut stream, 0..100);
+    // Every 512 samples after the first 640
+    assert_eq!(sent, [39, 71]);
+
+    let message = no_alloc::read(&message).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    let Ok(from_edge::BandPower(band_power)) = root.which() else {
+        panic!("not band power");
+    };
+    let band_power = band_power.unwrap();
+    assert_eq!(band_power.get_sample_counter(), 0);
+    assert_eq!(band_power.get_timestamp(), 1_000_000);
+    assert_eq!(band_power.get_sample_count(), 640);
+    assert_eq!(band_power.get_sample_rate(), SAMPLE_RATE);
+    assert_eq!(band_power.get_channel_count(), 2);
+    let edges: Vec<f32> = band_power.get_band_edges().unwrap().iter().collect();
+    assert_eq!(edges, EEG_BANDS);
+
+    let power: Vec<f32> = band_power.get_power().unwrap().iter().collect();
+    assert_eq!(power.len(), 2 * 5);
+    // A sine of amplitude A has a power of A² / 2, in the alpha band
+    assert!((power[2] / 500_000.0 - 1.0).abs() < 0.01, "{power:?}");
+    assert!(power[5..].iter().all(|&power| power == 0.0), "{power:?}");
+}
+
+#[test]
+fn gaps_start_the_estimate_over() {
+    let mut stream = BandPowerStream::new(DEFAULT_CONFIG, &EEG_BANDS);
+    let (sent, _) = run(&mut stream, (0..30).chain(31..100));
+    assert_eq!(sent, [70]);
+
+    stream.reset();
+    let (sent, message) = run(&mut stream, 50..100);
+    assert_eq!(sent, [89]);
+    let message = no_alloc::read(&message).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    let Ok(from_edge::BandPower(band_power)) = root.which() else {
+        panic!("not band power");
+    };
+    let band_power = band_power.unwrap();
+    assert_eq!(band_power.get_sample_counter(), 50 * BLOCK_FRAMES as u64);
+    assert_eq!(band_power.get_timestamp(), 1_000_000 + 50 * 64_000);
+}
+
+#[test]
+fn empty_blocks_are_ignored() {
+    let mut stream = BandPowerStream::new(DEFAULT_CONFIG, &EEG_BANDS);
+    let mut out = [0; 512];
+    assert_eq!(stream.push(&SampleBlock::default(), &mut out), None);
+}
+
+#[test]
+#[should_panic(expected = "too many bands")]
+fn bands_are_limited() {
+    let edges: Vec<f32> = (0..MAX_BANDS + 2).map(|edge| edge as f32).collect();
+    BandPowerStream::new(DEFAULT_CONFIG, &edges);
+}
@@ -0,0 +1,226 @@
+mod support;
+
+use common::block_buffer::{BlockBuffer, BlockState};
+use common::log::Core;
+use embassy_futures::{block_on, poll_once};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::watch::Watch;
+use std::pin::pin;
+use std::task::Poll;
+use std::thread;
+
+/// Stands in for the IPC event of the other core, fired by hand
+type MockSignal = Watch<CriticalSectionRawMutex, (), 1>;
+
+fn new_buffer<const N: usize>() -> BlockBuffer<u32, N> {
+    let buffer = BlockBuffer::new();
+    // Safety: Neither end was taken
+    unsafe { buffer.init() };
+    buffer
+}
+
+fn states<const N: usize>(buffer: &BlockBuffer<u32, N>) -> [BlockState; N] {
+    std::array::from_fn(|index| buffer.state(index))
+}
+
+#[test]
+fn each_end_can_only_be_taken_once() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<2>();
+    assert!(buffer.take_writer(Core::App, signal.sender()).is_some());
+    assert!(buffer.take_writer(Core::App, signal.sender()).is_none());
+    assert!(buffer
+        .take_reader(Core::Net, signal.receiver().unwrap())
+        .is_some());
+    let receiver = Watch::<CriticalSectionRawMutex, (), 2>::new();
+    assert!(buffer
+        .take_reader(Core::Net, receiver.receiver().unwrap())
+        .is_none());
+}
+
+#[test]
+fn block_goes_from_writer_to_reader_and_back() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<2>();
+    let mut writer = buffer.take_writer(Core::App, signal.sender()).unwrap();
+    let mut reader = buffer
+        .take_reader(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+    assert_eq!(states(&buffer), [BlockState::Free; 2]);
+
+    let mut block = writer.acquire().unwrap();
+    *block = 42;
+    assert_eq!(buffer.state(0), BlockState::Writing);
+    assert!(reader.try_acquire().is_none());
+    block.commit();
+    assert_eq!(buffer.state(0), BlockState::Ready);
+
+    let block = reader.try_acquire().unwrap();
+    assert_eq!(*block, 42);
+    assert_eq!(buffer.state(0), BlockState::Reading);
+    drop(block);
+    assert_eq!(states(&buffer), [BlockState::Free; 2]);
+}
+
+#[test]
+fn dropping_an_uncommitted_block_gives_it_back_to_the_writer() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<2>();
+    let mut writer = buffer.take_writer(Core::App, signal.sender()).unwrap();
+    let mut reader = buffer
+        .take_reader(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    *writer.acquire().unwrap() = 1;
+    assert_eq!(states(&buffer), [BlockState::Free; 2]);
+    assert!(reader.try_acquire().is_none());
+
+    // The same block is filled again rather than skipped
+    let mut block = writer.acquire().unwrap();
+    *block += 1;
+    block.commit();
+    assert_eq!(buffer.state(0), BlockState::Ready);
+    assert_eq!(*reader.try_acquire().unwrap(), 2);
+}
+
+#[test]
+fn ping_pong_writes_one_block_while_the_other_is_read() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<2>();
+    let mut writer = buffer.take_writer(Core::App, signal.sender()).unwrap();
+    let mut reader = buffer
+        .take_reader(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    let mut block = writer.acquire().unwrap();
+    *block = 0;
+    block.commit();
+    let read = reader.try_acquire().unwrap();
+
+    let mut block = writer.acquire().unwrap();
+    *block = 1;
+    block.commit();
+    assert_eq!(states(&buffer), [BlockState::Reading, BlockState::Ready]);
+
+    // The first block is still being read
+    assert!(writer.acquire().is_none());
+    assert_eq!(buffer.overruns(), 1);
+    assert_eq!(*read, 0);
+    drop(read);
+    assert!(writer.acquire().is_some());
+}
+
+#[test]
+fn triple_buffer_lets_the_reader_fall_a_block_behind() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<3>();
+    let mut writer = buffer.take_writer(Core::App, signal.sender()).unwrap();
+    let mut reader = buffer
+        .take_reader(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    for value in 0..3 {
+        let mut block = writer.acquire().unwrap();
+        *block = value;
+        block.commit();
+    }
+    assert_eq!(states(&buffer), [BlockState::Ready; 3]);
+    assert!(writer.acquire().is_none());
+    assert!(writer.acquire().is_none());
+    assert_eq!(buffer.overruns(), 2);
+
+    for value in 0..3 {
+        assert_eq!(*reader.try_acquire().unwrap(), value);
+    }
+    assert!(reader.try_acquire().is_none());
+    assert_eq!(states(&buffer), [BlockState::Free; 3]);
+}
+
+#[test]
+fn reader_is_woken_up_by_a_commit() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<2>();
+    let mut writer = buffer.take_writer(Core::App, signal.sender()).unwrap();
+    let mut reader = buffer
+        .take_reader(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    {
+        let mut ready = pin!(reader.ready());
+        assert_eq!(poll_once(ready.as_mut()), Poll::Pending);
+        let mut block = writer.acquire().unwrap();
+        *block = 7;
+        assert_eq!(poll_once(ready.as_mut()), Poll::Pending);
+        block.commit();
+        assert_eq!(poll_once(ready.as_mut()), Poll::Ready(()));
+    }
+    assert_eq!(*block_on(reader.acquire()), 7);
+}
+
+#[test]
+fn blocks_committed_under_one_signal_are_all_read() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<3>();
+    let mut writer = buffer.take_writer(Core::App, signal.sender()).unwrap();
+    let mut reader = buffer
+        .take_reader(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    for value in 0..3 {
+        let mut block = writer.acquire().unwrap();
+        *block = value;
+        block.commit();
+    }
+    for value in 0..3 {
+        let block = pin!(reader.acquire());
+        let Poll::Ready(block) = poll_once(block) else {
+            panic!("block {value} wasn't ready");
+        };
+        assert_eq!(*block, value);
+    }
+}
+
+#[test]
+fn init_frees_every_block() {
+    let signal = MockSignal::new();
+    let buffer = new_buffer::<2>();
+    let mut writer = buffer.take_writer(Core::App, signal.sender()).unwrap();
+    writer.acquire().unwrap().commit();
+    let _writing = writer.acquire().unwrap();
+    assert_eq!(states(&buffer), [BlockState::Ready, BlockState::Writing]);
+
+    // Safety: Stands in for a reboot of both cores, the ends aren't used after this
+    unsafe { buffer.init() };
+    assert_eq!(states(&buffer), [BlockState::Free; 2]);
+    assert!(buffer.take_writer(Core::App, signal.sender()).is_some());
+}
+
+#[test]
+fn blocks_arrive_in_order_across_threads() {
+    static SIGNAL: MockSignal = MockSignal::new();
+    static BUFFER: BlockBuffer<u32, 3> = BlockBuffer::new();
+    // Safety: Neither end was taken
+    unsafe { BUFFER.init() };
+    const BLOCKS: u32 = 1_000;
+
+    let mut reader = BUFFER
+        .take_reader(Core::Net, SIGNAL.receiver().unwrap())
+        .unwrap();
+    let writer = thread::spawn(|| {
+        let mut writer = BUFFER.take_writer(Core::App, SIGNAL.sender()).unwrap();
+        for value in 0..BLOCKS {
+            loop {
+                if let Some(mut block) = writer.acquire() {
+                    *block = value;
+                    block.commit();
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+    });
+    for value in 0..BLOCKS {
+        assert_eq!(*block_on(reader.acquire()), value);
+    }
+    writer.join().unwrap();
+}
@@ -0,0 +1,429 @@
+use common::config_store::{ConfigStore, Error, Key, MAX_VALUE_LEN};
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use std::collections::HashMap;
+
+/// Small pages, so that tests go through many compactions
+const PAGE_SIZE: usize = 256;
+const PAGES: u32 = 4;
+/// Where the store starts in the mock flash, after a page of something else
+const BASE: u32 = PAGE_SIZE as u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MockError {
+    /// The power went out during or before the operation
+    PowerCut,
+    /// Programming flash that wasn't erased
+    NotErased,
+    OutOfBounds,
+    Unaligned,
+}
+
+impl NorFlashError for MockError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::Unaligned => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// NOR flash in RAM, which can lose power part way through a write or an erase
+#[derive(Clone)]
+struct MockFlash {
+    bytes: Vec<u8>,
+    /// How many times each page was erased
+    erases: Vec<u32>,
+    /// Bytes written and pages erased so far
+    used: usize,
+    /// The power goes out once `used` gets there
+    power_cut_at: Option<usize>,
+}
+
+impl MockFlash {
+    fn new() -> Self {
+        let pages = PAGES as usize + 1;
+        Self {
+            bytes: vec![0xFF; pages * PAGE_SIZE],
+            erases: vec![0; pages],
+            used: 0,
+            power_cut_at: None,
+        }
+    }
+
+    fn check(&self, offset: u32, len: usize, align: usize) -> Result<(), MockError> {
+        if !(offset as usize).is_multiple_of(align) || !len.is_multiple_of(align) {
+            return Err(MockError::Unaligned);
+        }
+        if offset as usize + len > self.bytes.len() {
+            return Err(MockError::OutOfBounds);
+        }
+        Ok(())
+    }
+
+    /// Whether the power is still on, after using some more of it
+    fn spend(&mut self) -> bool {
+        if self.power_cut_at.is_some_and(|at| self.used >= at) {
+            return false;
+        }
+        self.used += 1;
+        true
+    }
+
+    /// Puts the power back on, as after a reboot
+    fn reboot(&mut self) {
+        self.power_cut_at = None;
+    }
+
+    /// Erases of the pages of the store
+    fn store_erases(&self) -> &[u32] {
+        &self.erases[1..]
+    }
+}
+
+impl ErrorType for MockFlash {
+    type Error = MockError;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), MockError> {
+        self.check(offset, bytes.len(), Self::READ_SIZE)?;
+        if self.power_cut_at.is_some_and(|at| self.used >= at) {
+            return Err(MockError::PowerCut);
+        }
+        bytes.copy_from_slice(&self.bytes[offset as usize..][..bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = PAGE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), MockError> {
+        self.check(from, (to - from) as usize, Self::ERASE_SIZE)?;
+        for page in from as usize / PAGE_SIZE..to as usize / PAGE_SIZE {
+            let page_bytes = &mut self.bytes[page * PAGE_SIZE..][..PAGE_SIZE];
+            if self.power_cut_at.is_some_and(|at| self.used >= at) {
+                // Cut half way through, the start of the page is left as it was
+                page_bytes[PAGE_SIZE / 2..].fill(0xFF);
+                return Err(MockError::PowerCut);
+            }
+            self.used += 1;
+            page_bytes.fill(0xFF);
+            self.erases[page] += 1;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), MockError> {
+        self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+        let target = offset as usize..offset as usize + bytes.len();
+        if self.bytes[target.clone()].iter().any(|&byte| byte != 0xFF) {
+            return Err(MockError::NotErased);
+        }
+        for (i, &byte) in target.zip(bytes) {
+            if !self.spend() {
+                return Err(MockError::PowerCut);
+            }
+            self.bytes[i] = byte;
+        }
+        Ok(())
+    }
+}
+
+type Store<'a> = ConfigStore<&'a mut MockFlash>;
+
+fn mount(flash: &mut MockFlash) -> Store<'_> {
+    ConfigStore::mount(flash, BASE, PAGES).unwrap()
+}
+
+fn get(store: &mut Store, key: Key) -> Option<Vec<u8>> {
+    let mut buf = [0; MAX_VALUE_LEN];
+    let len = store.get(key, &mut buf).unwrap()?;
+    Some(buf[..len].to_vec())
+}
+
+/// A change made to the store
+#[derive(Clone, Copy)]
+enum Change {
+    Set(Key, &'static [u8]),
+    Remove(Key),
+}
+
+impl Change {
+    fn key(&self) -> Key {
+        match *self {
+            Change::Set(key, _) | Change::Remove(key) => key,
+        }
+    }
+
+    fn apply(&self, store: &mut Store) -> Result<(), Error<MockError>> {
+        match *self {
+            Change::Set(key, value) => store.set(key, value),
+            Change::Remove(key) => store.remove(key),
+        }
+    }
+
+    fn apply_to(&self, expected: &mut HashMap<Key, Vec<u8>>) {
+        match *self {
+            Change::Set(key, value) => expected.insert(key, value.to_vec()),
+            Change::Remove(key) => expected.remove(&key),
+        };
+    }
+}
+
+/// Enough changes to fill the page they start in and compact it more than once
+const CHANGES: &[Change] = &[
+    Change::Set(1, &[1; 40]),
+    Change::Set(2, b"headband"),
+    Change::Set(3, &[3; 60]),
+    Change::Set(1, &[4; 40]),
+    Change::Remove(2),
+    Change::Set(4, &[5; 24]),
+    Change::Set(3, &[6; 60]),
+    Change::Set(5, &[7; 16]),
+    Change::Set(1, &[8; 8]),
+    Change::Set(2, b"renamed"),
+    Change::Remove(4),
+    Change::Set(3, &[9; 52]),
+    Change::Set(6, &[10; 32]),
+];
+
+/// Flash with some settings in the store, before the changes
+fn initial() -> (MockFlash, HashMap<Key, Vec<u8>>) {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    let mut expected = HashMap::new();
+    for change in [Change::Set(1, &[0; 40]), Change::Set(7, b"untouched")] {
+        change.apply(&mut store).unwrap();
+        change.apply_to(&mut expected);
+    }
+    (flash, expected)
+}
+
+/// Applies [`CHANGES`] until the power goes out. Updates `expected` to the settings that have to
+/// be there after a reboot, and returns the change that was cut short.
+fn apply_until_cut(flash: &mut MockFlash, expected: &mut HashMap<Key, Vec<u8>>) -> Option<Change> {
+    let mut store = match ConfigStore::mount(flash, BASE, PAGES) {
+        Ok(store) => store,
+        Err(Error::Flash(MockError::PowerCut)) => return None,
+        Err(error) => panic!("{error:?}"),
+    };
+    for change in CHANGES {
+        match change.apply(&mut store) {
+            Ok(()) => change.apply_to(expected),
+            Err(Error::Flash(MockError::PowerCut)) => return Some(*change),
+            Err(error) => panic!("{error:?}"),
+        }
+    }
+    None
+}
+
+/// Checks every setting is either as expected, or as set by the change that was cut short
+fn check_settings(store: &mut Store, expected: &HashMap<Key, Vec<u8>>, cut: Option<Change>) {
+    for key in 1..=7 {
+        let found = get(store, key);
+        let before = expected.get(&key).cloned();
+        match cut {
+            Some(change) if change.key() == key => {
+                let mut after = expected.clone();
+                change.apply_to(&mut after);
+                let after = after.get(&key).cloned();
+                assert!(found == before || found == after, "key {key}: {found:?}");
+            }
+            _ => assert_eq!(found, before, "key {key}"),
+        }
+    }
+}
+
+/// Bytes written and pages erased while applying every change
+fn cost_of_changes() -> usize {
+    let (mut flash, mut expected) = initial();
+    let start = flash.used;
+    let erases = flash.store_erases().iter().sum::<u32>();
+    assert!(apply_until_cut(&mut flash, &mut expected).is_none());
+    assert!(
+        flash.store_erases().iter().sum::<u32>() > erases + 1,
+        "the changes should compact more than once"
+    );
+    flash.used - start
+}
+
+#[test]
+fn empty_flash_has_no_settings() {
+    let mut flash = MockFlash::new();
+    assert_eq!(get(&mut mount(&mut flash), 1), None);
+    assert_eq!(get(&mut mount(&mut flash), 1), None);
+}
+
+#[test]
+fn settings_are_kept_across_reboots() {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    store.set(1, &250u32.to_le_bytes()).unwrap();
+    store.set(2, b"headband").unwrap();
+    store.set(3, &[]).unwrap();
+
+    let mut store = mount(&mut flash);
+    assert_eq!(get(&mut store, 1), Some(250u32.to_le_bytes().to_vec()));
+    assert_eq!(get(&mut store, 2), Some(b"headband".to_vec()));
+    assert_eq!(get(&mut store, 3), Some(vec![]));
+    assert_eq!(get(&mut store, 4), None);
+}
+
+#[test]
+fn latest_value_is_read_back() {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    store.set(1, b"first").unwrap();
+    store.set(1, b"second value").unwrap();
+    assert_eq!(get(&mut store, 1), Some(b"second value".to_vec()));
+    assert_eq!(
+        get(&mut mount(&mut flash), 1),
+        Some(b"second value".to_vec())
+    );
+}
+
+#[test]
+fn removed_settings_stay_removed() {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    store.set(1, b"gone").unwrap();
+    store.set(2, b"kept").unwrap();
+    store.remove(1).unwrap();
+    assert_eq!(get(&mut store, 1), None);
+
+    // Through compactions too
+    for i in 0..100u32 {
+        store.set(3, &i.to_le_bytes()).unwrap();
+    }
+    let mut store = mount(&mut flash);
+    assert_eq!(get(&mut store, 1), None);
+    assert_eq!(get(&mut store, 2), Some(b"kept".to_vec()));
+    assert_eq!(get(&mut store, 3), Some(99u32.to_le_bytes().to_vec()));
+}
+
+#[test]
+fn unchanged_values_arent_written_again() {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    store.set(1, b"same").unwrap();
+    store.remove(2).unwrap();
+    let used = flash.used;
+
+    let mut store = mount(&mut flash);
+    store.set(1, b"same").unwrap();
+    store.remove(2).unwrap();
+    assert_eq!(flash.used, used);
+}
+
+#[test]
+fn erases_are_spread_over_every_page() {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    for i in 0..1000u32 {
+        store.set((i % 3) as Key, &i.to_le_bytes()).unwrap();
+    }
+    let erases = flash.store_erases();
+    let least = *erases.iter().min().unwrap();
+    let most = *erases.iter().max().unwrap();
+    assert!(least > 10, "{erases:?}");
+    assert!(most - least <= 1, "{erases:?}");
+    // The page before the store is left alone
+    assert_eq!(flash.erases[0], 0);
+    assert!(flash.bytes[..PAGE_SIZE].iter().all(|&byte| byte == 0xFF));
+}
+
+#[test]
+fn invalid_settings_are_refused() {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    assert_eq!(store.set(1, &[0; MAX_VALUE_LEN + 1]), Err(Error::TooLong));
+    assert_eq!(store.set(0xFFFF, b"erased"), Err(Error::InvalidKey));
+
+    store.set(1, &[0; 16]).unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(
+        store.get(1, &mut buf),
+        Err(Error::BufferTooSmall { len: 16 })
+    );
+}
+
+#[test]
+fn full_store_keeps_what_it_had() {
+    let mut flash = MockFlash::new();
+    let mut store = mount(&mut flash);
+    let value = [0x5A; 56];
+    let mut key = 0;
+    while store.set(key, &value).is_ok() {
+        key += 1;
+    }
+    assert_eq!(store.set(key, &value), Err(Error::Full));
+
+    // Smaller values of the keys that are there still fit
+    store.set(0, &[1; 8]).unwrap();
+    let mut store = mount(&mut flash);
+    assert_eq!(get(&mut store, 0), Some(vec![1; 8]));
+    for key in 1..key {
+        assert_eq!(get(&mut store, key), Some(value.to_vec()));
+    }
+    assert_eq!(get(&mut store, key), None);
+}
+
+#[test]
+fn power_cut_at_any_point_loses_nothing_written() {
+    let cost = cost_of_changes();
+    for cut_after in 0..=cost {
+        let (mut flash, mut expected) = initial();
+        flash.power_cut_at = Some(flash.used + cut_after);
+        let cut = apply_until_cut(&mut flash, &mut expected);
+
+        flash.reboot();
+        let mut store = mount(&mut flash);
+        check_settings(&mut store, &expected, cut);
+
+        // The store can be written to after recovering
+        store.set(7, b"after the cut").unwrap();
+        assert_eq!(
+            get(&mut mount(&mut flash), 7),
+            Some(b"after the cut".to_vec())
+        );
+    }
+}
+
+#[test]
+fn power_cut_while_recovering_loses_nothing_written() {
+    let cost = cost_of_changes();
+    for cut_after in (0..=cost).step_by(5) {
+        let (mut cut_flash, mut expected) = initial();
+        cut_flash.power_cut_at = Some(cut_flash.used + cut_after);
+        let cut = apply_until_cut(&mut cut_flash, &mut expected);
+
+        // Cut the power again at every point of the mount after the first cut
+        for recovery_cut_after in 0.. {
+            let mut flash = cut_flash.clone();
+            flash.reboot();
+            flash.power_cut_at = Some(flash.used + recovery_cut_after);
+            let recovered = match ConfigStore::mount(&mut flash, BASE, PAGES) {
+                Ok(_) => true,
+                Err(Error::Flash(MockError::PowerCut)) => false,
+                Err(error) => panic!("{error:?}"),
+            };
+
+            flash.reboot();
+            check_settings(&mut mount(&mut flash), &expected, cut);
+            if recovered {
+                break;
+            }
+        }
+    }
+}
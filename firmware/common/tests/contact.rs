@@ -0,0 +1,114 @@
+use common::contact::{microvolts_per_count, ContactMeter, Measurement, PERIODS, TEST_CURRENT};
+use common::ipc::{SampleBlock, BLOCK_FRAMES};
+use common::proto::from_edge_capnp::from_edge;
+use common::proto::no_alloc;
+use common::proto::to_edge_capnp::Gain;
+use std::f64::consts::PI;
+
+const SAMPLE_RATE: u32 = 250;
+
+/// Block `index` with a response to the test current of `amplitudes` (ADC counts) on each channel,
+/// over the offsets of the electrodes
+fn block(index: u64, amplitudes: &[f64]) -> SampleBlock {
+    let channels = amplitudes.len();
+    let mut block = SampleBlock {
+        sample_counter: index * BLOCK_FRAMES as u64,
+        timestamp: 1_000_000 + index * BLOCK_FRAMES as u64 * 4_000,
+        sample_rate: SAMPLE_RATE,
+        channel_count: channels as u8,
+        frames: BLOCK_FRAMES as u16,
+        ..Default::default()
+    };
+    for frame in 0..BLOCK_FRAMES {
+        let n = block.sample_counter + frame as u64;
+        // A quarter of the sample rate
+        let phase = PI / 2.0 * n as f64 + 0.5;
+        for (channel, amplitude) in amplitudes.iter().enumerate() {
+            let offset = 20_000.0 * (channel as f64 + 1.0);
+            block.samples[channels * frame + channel] = (offset + amplitude * phase.sin()) as i32;
+        }
+    }
+    block
+}
+
+/// Blocks that ended a measurement, and the first measurement
+fn run(
+    meter: &mut ContactMeter,
+    blocks: impl Iterator<Item = u64>,
+    amplitudes: &[f64],
+) -> (Vec<u64>, Option<Measurement>) {
+    let mut first = None;
+    let mut ended = Vec::new();
+    for index in blocks {
+        if let Some(measurement) = meter.push(&block(index, amplitudes)) {
+            first.get_or_insert(measurement);
+            ended.push(index);
+        }
+    }
+    (ended, first)
+}
+
+#[test]
+fn measures_the_response_of_every_channel() {
+    let mut meter = ContactMeter::new();
+    let (ended, measurement) = run(&mut meter, 0..70, &[5_000.0, 300.0, 0.0]);
+    // Four samples per period
+    assert_eq!(PERIODS * 4, 500);
+    assert_eq!(ended, [31, 62]);
+
+    let measurement = measurement.unwrap();
+    assert_eq!(measurement.timestamp, 1_000_000 + 499 * 4_000);
+    assert_eq!(measurement.test_frequency, 62.5);
+    assert_eq!(measurement.channel_count, 3);
+    let [first, second, third, ..] = measurement.amplitudes;
+    assert!((first - 5_000.0).abs() < 1.0, "{first}");
+    assert!((second - 300.0).abs() < 1.0, "{second}");
+    assert!(third < 1.0, "{third}");
+}
+
+#[test]
+fn contact_is_sent_in_microvolts() {
+    let mut meter = ContactMeter::new();
+    let (_, measurement) = run(&mut meter, 0..32, &[5_000.0]);
+    let scale = microvolts_per_count(Gain::X24);
+    let contact = measurement.unwrap().contact(0, 6, scale);
+
+    let mut out = [0; 128];
+    let len = meter.encode(&contact, &mut out).unwrap();
+    let message = no_alloc::read(&out[..len]).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    let Ok(from_edge::ContactQuality(quality)) = root.which() else {
+        panic!("not contact quality");
+    };
+    let quality = quality.unwrap();
+    assert_eq!(quality.get_channel(), 6);
+    assert_eq!(quality.get_timestamp(), contact.timestamp);
+    assert_eq!(quality.get_test_current(), TEST_CURRENT);
+    assert_eq!(quality.get_test_frequency(), 62.5);
+    // About 112 µV, so 19 kΩ at 6 nA
+    let amplitude = quality.get_amplitude();
+    assert!(
+        (amplitude / (5_000.0 * scale) - 1.0).abs() < 1e-3,
+        "{amplitude}"
+    );
+}
+
+#[test]
+fn gaps_start_the_measurement_over() {
+    let mut meter = ContactMeter::new();
+    let (ended, _) = run(&mut meter, (0..20).chain(21..60), &[1_000.0, 1_000.0]);
+    assert_eq!(ended, [52]);
+
+    meter.reset();
+    let (ended, _) = run(&mut meter, 60..100, &[1_000.0, 1_000.0]);
+    assert_eq!(ended, [91]);
+}
+
+#[test]
+fn gain_sets_the_size_of_a_count() {
+    assert!((microvolts_per_count(Gain::X24) - 0.022_35).abs() < 1e-5);
+    assert_eq!(
+        microvolts_per_count(Gain::X1),
+        24.0 * microvolts_per_count(Gain::X24)
+    );
+}
@@ -0,0 +1,146 @@
+use common::decimate::{self, BlockDecimator};
+use common::ipc::{SampleBlock, BLOCK_FRAMES};
+use common::proto::{SAMPLE_MAX, SAMPLE_MIN};
+use std::f64::consts::PI;
+
+/// Block `index` at `sample_rate` of two channels, an offset and a tone at `frequency` (Hz)
+fn block(index: u64, sample_rate: u32, frequency: f64) -> SampleBlock {
+    let mut block = SampleBlock {
+        sample_counter: index * BLOCK_FRAMES as u64,
+        timestamp: 1_000_000 + index * BLOCK_FRAMES as u64 * 1_000_000 / u64::from(sample_rate),
+        sample_rate,
+        lead_off: 0b10,
+        channel_count: 2,
+        frames: BLOCK_FRAMES as u16,
+        ..Default::default()
+    };
+    for frame in 0..BLOCK_FRAMES {
+        let n = block.sample_counter + frame as u64;
+        let t = n as f64 / f64::from(sample_rate);
+        block.samples[2 * frame] = -40_000;
+        block.samples[2 * frame + 1] = (1_000.0 * (2.0 * PI * frequency * t).sin()) as i32;
+    }
+    block
+}
+
+/// Every decimated block of `blocks`
+fn run(
+    decimator: &mut BlockDecimator,
+    blocks: impl Iterator<Item = SampleBlock>,
+) -> Vec<SampleBlock> {
+    let mut decimated = Vec::new();
+    for block in blocks {
+        decimator.push(&block, |block| decimated.push(block.clone()));
+    }
+    decimated
+}
+
+#[test]
+fn blocks_are_filled_at_the_reduced_rate() {
+    let mut decimator = BlockDecimator::new(4);
+    let decimated = run(
+        &mut decimator,
+        (0..16).map(|index| block(index, 1_000, 10.0)),
+    );
+    assert_eq!(decimated.len(), 4);
+    for (index, block) in decimated.iter().enumerate() {
+        assert_eq!(block.sample_rate, 250);
+        assert_eq!(block.channel_count, 2);
+        assert_eq!(block.lead_off, 0b10);
+        assert_eq!(usize::from(block.frames), BLOCK_FRAMES);
+        assert_eq!(block.sample_counter, (index * BLOCK_FRAMES) as u64);
+        // Taken with the first full rate sample it is made of
+        assert_eq!(block.timestamp, 1_000_000 + index as u64 * 64_000);
+    }
+}
+
+#[test]
+fn pass_band_is_kept_and_aliases_are_filtered_out() {
+    let mut decimator = BlockDecimator::new(2);
+    let kept = run(&mut decimator, (0..40).map(|index| block(index, 500, 10.0)));
+    let mut decimator = BlockDecimator::new(2);
+    // 200 Hz would fold back to 50 Hz at 250 Hz
+    let filtered = run(
+        &mut decimator,
+        (0..40).map(|index| block(index, 500, 200.0)),
+    );
+
+    let peak = |blocks: &[SampleBlock]| {
+        let samples = blocks[4..]
+            .iter()
+            .flat_map(|block| block.samples().to_vec());
+        let (offsets, tones): (Vec<_>, Vec<_>) = samples.enumerate().partition(|(i, _)| i % 2 == 0);
+        assert!(offsets.iter().all(|&(_, offset)| offset == -40_000));
+        tones.iter().map(|(_, tone)| tone.abs()).max().unwrap()
+    };
+    let kept = peak(&kept);
+    assert!((990..=1_000).contains(&kept), "{kept}");
+    let filtered = peak(&filtered);
+    assert!(filtered <= 2, "{filtered}");
+}
+
+#[test]
+fn missing_blocks_start_the_filter_over() {
+    let mut decimator = BlockDecimator::new(4);
+    let blocks = (0..3).chain(6..10).map(|index| block(index, 1_000, 10.0));
+    let decimated = run(&mut decimator, blocks);
+    let counters: Vec<_> = decimated
+        .iter()
+        .map(|block| (block.sample_counter, block.frames))
+        .collect();
+    // The samples before the gap are flushed, and counting goes on at the reduced rate
+    assert_eq!(counters, [(0, 12), (24, 16)]);
+}
+
+#[test]
+fn rates_the_factor_doesnt_divide_pass_through() {
+    let mut decimator = BlockDecimator::new(4);
+    let decimated = run(&mut decimator, (0..2).map(|index| block(index, 250, 10.0)));
+    assert_eq!(decimated.len(), 2);
+    assert_eq!(decimated[1].sample_rate, 250);
+    assert_eq!(decimated[1].samples(), block(1, 250, 10.0).samples());
+}
+
+#[test]
+fn overshoot_of_a_full_scale_step_saturates() {
+    let mut decimator = BlockDecimator::new(4);
+    let blocks = (0..8).map(|index| {
+        let mut block = block(index, 1_000, 10.0);
+        for (n, sample) in block.samples.iter_mut().enumerate() {
+            let frame = block.sample_counter + (n / 2) as u64;
+            *sample = if frame < 64 { SAMPLE_MIN } else { SAMPLE_MAX };
+        }
+        block
+    });
+    let decimated = run(&mut decimator, blocks);
+    let samples: Vec<_> = decimated
+        .iter()
+        .flat_map(|block| block.samples().to_vec())
+        .collect();
+    // The filter rings past both rails, which are as far as a sample goes
+    assert!(samples.contains(&SAMPLE_MIN));
+    assert!(samples.contains(&SAMPLE_MAX));
+    assert!(samples
+        .iter()
+        .all(|sample| (SAMPLE_MIN..=SAMPLE_MAX).contains(sample)));
+}
+
+#[test]
+fn factors_that_dont_divide_the_sample_rate_arent_picked() {
+    // 3 and 4 would come closest, but neither divides 250
+    assert_eq!(decimate::factor(250, 80), 2);
+    assert_eq!(decimate::factor(250, 60), 2);
+    let mut decimator = BlockDecimator::new(decimate::factor(250, 60));
+    let decimated = run(&mut decimator, (0..4).map(|index| block(index, 250, 10.0)));
+    assert!(decimated.iter().all(|block| block.sample_rate == 125));
+}
+
+#[test]
+fn factor_comes_closest_to_the_stream_rate() {
+    assert_eq!(decimate::factor(1_000, 0), 1);
+    assert_eq!(decimate::factor(1_000, 400), 2);
+    assert_eq!(decimate::factor(1_000, 250), 4);
+    assert_eq!(decimate::factor(1_000, 100), 4);
+    assert_eq!(decimate::factor(16_000, 8_000), 2);
+    assert_eq!(decimate::factor(16_000, 20_000), 1);
+}
@@ -0,0 +1,50 @@
+use common::ipc::{build_ack, build_error};
+use common::proto::from_edge_capnp::{from_edge, ErrorCode};
+use common::proto::no_alloc::{self, ScratchBuffer};
+
+/// Reads back a reply encoded into `out`
+fn decode(out: &[u8], check: impl FnOnce(from_edge::Reader)) {
+    let message = no_alloc::read(out).unwrap();
+    check(message.get_root().unwrap());
+}
+
+#[test]
+fn ack_answers_the_request() {
+    let mut out = [0; 256];
+    let len = ScratchBuffer::<64>::new()
+        .encode::<from_edge::Owned>(&mut out, |reply| build_ack(reply, 42))
+        .unwrap();
+    decode(&out[..len], |reply| {
+        assert_eq!(reply.get_request_id(), 42);
+        assert!(matches!(reply.which(), Ok(from_edge::Ack(()))));
+    });
+}
+
+#[test]
+fn error_carries_its_code_and_message() {
+    let mut out = [0; 256];
+    let len = ScratchBuffer::<64>::new()
+        .encode::<from_edge::Owned>(&mut out, |reply| {
+            build_error(reply, 7, ErrorCode::Busy, "Already recording")
+        })
+        .unwrap();
+    decode(&out[..len], |reply| {
+        assert_eq!(reply.get_request_id(), 7);
+        let Ok(from_edge::Error(error)) = reply.which() else {
+            panic!("expected an error");
+        };
+        let error = error.unwrap();
+        assert_eq!(error.get_code(), Ok(ErrorCode::Busy));
+        assert_eq!(error.get_message().unwrap(), "Already recording");
+    });
+}
+
+#[test]
+fn overlong_error_message_is_an_error() {
+    let mut out = [0; 1024];
+    let message = "x".repeat(no_alloc::MAX_TEXT_LEN + 1);
+    let result = ScratchBuffer::<64>::new().encode::<from_edge::Owned>(&mut out, |reply| {
+        build_error(reply, 7, ErrorCode::Unknown, &message)
+    });
+    assert!(result.is_err());
+}
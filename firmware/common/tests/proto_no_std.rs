@@ -0,0 +1,50 @@
+//! The firmware builds proto without std or an allocator. Encodes the messages of the current
+//! version of the corpus that way, and checks that they come out as the bytes recorded on the host.
+
+#[path = "../../../proto/tests/messages/mod.rs"]
+mod messages;
+
+use messages::{build_from_edge, build_to_edge, FROM_EDGE, TO_EDGE};
+use proto::from_edge_capnp::from_edge;
+use proto::no_alloc::ScratchBuffer;
+use proto::to_edge_capnp::to_edge;
+use proto::ProtocolVersion;
+use std::path::{Path, PathBuf};
+
+fn corpus_file(prefix: &str, name: &str) -> PathBuf {
+    let ProtocolVersion { major, minor } = ProtocolVersion::CURRENT;
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../proto/corpus")
+        .join(format!("v{major}.{minor}"))
+        .join(format!("{prefix}_{name}.bin"))
+}
+
+#[test]
+fn messages_to_the_device_match_the_corpus() {
+    for (name, request_id) in TO_EDGE {
+        let mut out = [0; 1024];
+        let len = ScratchBuffer::<128>::new()
+            .encode::<to_edge::Owned>(&mut out, |root| {
+                build_to_edge(name, *request_id, root);
+                Ok(())
+            })
+            .unwrap();
+        let expected = std::fs::read(corpus_file("to_edge", name)).unwrap();
+        assert_eq!(&out[..len], &expected[..], "{name}");
+    }
+}
+
+#[test]
+fn messages_from_the_device_match_the_corpus() {
+    for (name, request_id) in FROM_EDGE {
+        let mut out = [0; 1024];
+        let len = ScratchBuffer::<128>::new()
+            .encode::<from_edge::Owned>(&mut out, |root| {
+                build_from_edge(name, *request_id, root);
+                Ok(())
+            })
+            .unwrap();
+        let expected = std::fs::read(corpus_file("from_edge", name)).unwrap();
+        assert_eq!(&out[..len], &expected[..], "{name}");
+    }
+}
@@ -0,0 +1,404 @@
+mod support;
+
+use common::log::Core;
+use common::ring_buffer::{OverflowPolicy, QueueStats, UninitRingBuffer};
+use embassy_futures::{block_on, poll_once};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::watch::Watch;
+use embassy_time::{Duration, Instant};
+use std::pin::pin;
+use std::sync::Barrier;
+use std::task::Poll;
+use std::thread;
+
+/// Stands in for the IPC event of the other core, fired by hand
+type MockSignal = Watch<CriticalSectionRawMutex, (), 1>;
+
+#[test]
+fn sender_can_only_be_taken_once() {
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    assert!(buffer.take_sender(Core::App).is_some());
+    assert!(buffer.take_sender(Core::App).is_none());
+    assert!(buffer.take_receiver(Core::Net).is_some());
+}
+
+#[test]
+fn receiver_can_only_be_taken_once() {
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    assert!(buffer.take_receiver(Core::Net).is_some());
+    assert!(buffer.take_receiver(Core::Net).is_none());
+    assert!(buffer.take_sender(Core::App).is_some());
+}
+
+#[test]
+fn ends_taken_with_a_signal_count_as_taken() {
+    let watch = Watch::<CriticalSectionRawMutex, (), 2>::new();
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    assert!(buffer
+        .take_sender_with_signal(Core::App, watch.sender())
+        .is_some());
+    assert!(buffer.take_sender(Core::App).is_none());
+    assert!(buffer
+        .take_sender_with_signal(Core::App, watch.sender())
+        .is_none());
+
+    assert!(buffer.take_receiver(Core::Net).is_some());
+    let receiver = watch.receiver().unwrap();
+    assert!(buffer
+        .take_receiver_with_signal(Core::Net, receiver)
+        .is_none());
+}
+
+#[test]
+fn taken_ends_share_the_queue() {
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    let mut sender = buffer.take_sender(Core::App).unwrap();
+    let mut receiver = buffer.take_receiver(Core::Net).unwrap();
+    sender.enqueue(1).unwrap();
+    sender.enqueue(2).unwrap();
+    assert_eq!(receiver.dequeue(), Some(1));
+    assert_eq!(receiver.dequeue(), Some(2));
+    assert_eq!(receiver.dequeue(), None);
+}
+
+#[test]
+fn end_taken_on_another_thread_cant_be_taken_again() {
+    static BUFFER: UninitRingBuffer<u32, 4> = UninitRingBuffer::new();
+    thread::spawn(|| {
+        BUFFER.take_sender(Core::App).unwrap().enqueue(7).unwrap();
+    })
+    .join()
+    .unwrap();
+    assert!(BUFFER.take_sender(Core::App).is_none());
+    assert_eq!(BUFFER.take_receiver(Core::Net).unwrap().dequeue(), Some(7));
+}
+
+#[test]
+fn racing_takes_hand_out_each_end_once() {
+    const THREADS: usize = 8;
+    static BUFFER: UninitRingBuffer<u32, 4> = UninitRingBuffer::new();
+    let barrier = Barrier::new(THREADS);
+    let (senders, receivers) = thread::scope(|scope| {
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                scope.spawn(|| {
+                    barrier.wait();
+                    let sender = BUFFER.take_sender(Core::App).is_some();
+                    let receiver = BUFFER.take_receiver(Core::Net).is_some();
+                    (sender as usize, receiver as usize)
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .fold((0, 0), |(s, r), (sender, receiver)| {
+                (s + sender, r + receiver)
+            })
+    });
+    assert_eq!((senders, receivers), (1, 1));
+}
+
+#[test]
+fn end_taken_on_one_core_cant_be_taken_on_the_other() {
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    assert!(buffer.take_sender(Core::App).is_some());
+    assert!(buffer.take_sender(Core::Net).is_none());
+    assert!(buffer.take_receiver(Core::Net).is_some());
+    assert!(buffer.take_receiver(Core::App).is_none());
+}
+
+#[test]
+fn cores_racing_for_an_end_never_both_get_it() {
+    for _ in 0..1_000 {
+        let buffer = UninitRingBuffer::<u32, 4>::new();
+        let barrier = Barrier::new(2);
+        let taken = thread::scope(|scope| {
+            let take = |core| {
+                let (buffer, barrier) = (&buffer, &barrier);
+                scope.spawn(move || {
+                    barrier.wait();
+                    buffer.take_sender(core).is_some()
+                })
+            };
+            let (app, net) = (take(Core::App), take(Core::Net));
+            app.join().unwrap() as usize + net.join().unwrap() as usize
+        });
+        assert!(taken <= 1);
+    }
+}
+
+#[test]
+fn init_makes_both_ends_available_again() {
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    buffer.take_sender(Core::App).unwrap().enqueue(1).unwrap();
+    assert!(buffer.take_receiver(Core::Net).is_some());
+    // Safety: Neither end is in use anymore
+    unsafe { buffer.init() };
+
+    let mut receiver = buffer.take_receiver(Core::Net).unwrap();
+    assert_eq!(receiver.dequeue(), None);
+    assert!(buffer.take_sender(Core::App).is_some());
+}
+
+#[test]
+fn items_pushed_under_one_signal_are_all_received() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 8>::new();
+    let mut sender = buffer.take_sender(Core::App).unwrap();
+    let mut consumer = buffer
+        .take_receiver_with_signal(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    for value in 1..=3 {
+        sender.enqueue(value).unwrap();
+    }
+    signal.sender().send(());
+    for value in 1..=3 {
+        assert_eq!(poll_once(consumer.recv()), Poll::Ready(value));
+    }
+    assert_eq!(poll_once(consumer.recv()), Poll::Pending);
+}
+
+#[test]
+fn items_queued_before_any_signal_are_received() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 8>::new();
+    buffer.take_sender(Core::App).unwrap().enqueue(5).unwrap();
+    let mut consumer = buffer
+        .take_receiver_with_signal(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+    assert_eq!(poll_once(consumer.recv()), Poll::Ready(5));
+}
+
+#[test]
+fn item_pushed_while_waiting_wakes_the_consumer() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 8>::new();
+    let mut sender = buffer.take_sender(Core::App).unwrap();
+    let mut consumer = buffer
+        .take_receiver_with_signal(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    {
+        let mut received = pin!(consumer.recv());
+        assert_eq!(poll_once(&mut received), Poll::Pending);
+        // The signal was already sent when the consumer checks the queue again
+        sender.enqueue(1).unwrap();
+        signal.sender().send(());
+        sender.enqueue(2).unwrap();
+        assert_eq!(poll_once(&mut received), Poll::Ready(1));
+    }
+    assert_eq!(poll_once(consumer.recv()), Poll::Ready(2));
+}
+
+#[test]
+fn try_recv_doesnt_wait() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 8>::new();
+    let mut sender = buffer.take_sender(Core::App).unwrap();
+    let mut consumer = buffer
+        .take_receiver_with_signal(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    assert_eq!(consumer.try_recv(), None);
+    sender.enqueue(3).unwrap();
+    assert_eq!(consumer.try_recv(), Some(3));
+    assert_eq!(consumer.try_recv(), None);
+}
+
+#[test]
+fn recv_timeout_gives_up_on_an_empty_queue() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 8>::new();
+    let mut sender = buffer.take_sender(Core::App).unwrap();
+    let mut consumer = buffer
+        .take_receiver_with_signal(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    let timeout = Duration::from_millis(20);
+    let start = Instant::now();
+    assert_eq!(block_on(consumer.recv_timeout(timeout)), None);
+    assert!(start.elapsed() >= timeout);
+
+    sender.enqueue(4).unwrap();
+    assert_eq!(block_on(consumer.recv_timeout(timeout)), Some(4));
+}
+
+#[test]
+fn drain_into_takes_what_fits() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 8>::new();
+    let mut sender = buffer.take_sender(Core::App).unwrap();
+    let mut consumer = buffer
+        .take_receiver_with_signal(Core::Net, signal.receiver().unwrap())
+        .unwrap();
+
+    for value in 1..=5 {
+        sender.enqueue(value).unwrap();
+    }
+    let mut out = [0; 3];
+    assert_eq!(consumer.drain_into(&mut out), 3);
+    assert_eq!(out, [1, 2, 3]);
+    let mut out = [0; 8];
+    assert_eq!(consumer.drain_into(&mut out), 2);
+    assert_eq!(out[..2], [4, 5]);
+    assert_eq!(consumer.drain_into(&mut out), 0);
+}
+
+#[test]
+fn no_item_is_stranded_across_threads() {
+    const ITEMS: u32 = 1_000;
+    static SIGNAL: MockSignal = MockSignal::new();
+    static BUFFER: UninitRingBuffer<u32, 4> = UninitRingBuffer::new();
+    let mut producer = BUFFER
+        .take_sender_with_signal(Core::App, SIGNAL.sender())
+        .unwrap();
+    let mut consumer = BUFFER
+        .take_receiver_with_signal(Core::Net, SIGNAL.receiver().unwrap())
+        .unwrap();
+
+    let sending = thread::spawn(move || {
+        for value in 0..ITEMS {
+            while block_on(producer.send(value)).is_err() {
+                thread::yield_now();
+            }
+        }
+    });
+    for value in 0..ITEMS {
+        let received = block_on(consumer.recv_timeout(Duration::from_secs(5)));
+        assert_eq!(received, Some(value));
+    }
+    sending.join().unwrap();
+}
+
+#[test]
+fn drop_newest_hands_back_what_doesnt_fit() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    let mut producer = buffer
+        .take_sender_with_signal(Core::App, signal.sender())
+        .unwrap();
+    let mut consumer = buffer.take_receiver(Core::Net).unwrap();
+
+    for value in 0..4 {
+        assert_eq!(block_on(producer.send(value)), Ok(()));
+    }
+    assert_eq!(block_on(producer.send(4)), Err(4));
+    assert_eq!(block_on(producer.send(5)), Err(5));
+    for value in 0..4 {
+        assert_eq!(consumer.dequeue(), Some(value));
+    }
+    let stats = QueueStats {
+        sent: 4,
+        dropped: 2,
+        high_water: 4,
+        capacity: 4,
+    };
+    assert_eq!(buffer.stats(), stats);
+}
+
+#[test]
+fn drop_oldest_keeps_the_newest_items() {
+    let signal = MockSignal::new();
+    let buffer = UninitRingBuffer::<u32, 4>::new();
+    let mut producer = buffer
+        .take_sender_with_signal(Core::App, signal.sender())
+        .unwrap();
+    producer.set_policy(OverflowPolicy::DropOldest);
+    let mut consumer = buffer.take_receiver(Core::Net).unwrap();
+
+    for value in 0..6 {
+        assert_eq!(block_on(producer.send(value)), Ok(()));
+    }
+    for value in 2..6 {
+        assert_eq!(consumer.dequeue(), Some(value));
+    }
+    assert_eq!(consumer.dequeue(), None);
+    let stats = buffer.stats();
+    assert_eq!((stats.sent, stats.dropped, stats.high_water), (6, 2, 4));
+}
+
+#[test]
+fn drop_oldest_never_hands_out_a_dropped_item() {
+    const ITEMS: u32 = 100_000;
+    static SIGNAL: MockSignal = MockSignal::new();
+    static BUFFER: UninitRingBuffer<u32, 4> = UninitRingBuffer::new();
+    let mut producer = BUFFER
+        .take_sender_with_signal(Core::App, SIGNAL.sender())
+        .unwrap();
+    producer.set_policy(OverflowPolicy::DropOldest);
+    let mut consumer = BUFFER.take_receiver(Core::Net).unwrap();
+
+    let sending = thread::spawn(move || {
+        for value in 0..ITEMS {
+            block_on(producer.send(value)).unwrap();
+        }
+    });
+    // Items can be skipped, but never received twice, out of order or torn
+    let mut received = 0;
+    let mut last = None;
+    while !sending.is_finished() || !consumer.is_empty() {
+        if let Some(value) = consumer.dequeue() {
+            assert!(value < ITEMS);
+            assert!(last.is_none_or(|last| value > last));
+            last = Some(value);
+            received += 1;
+        }
+    }
+    sending.join().unwrap();
+    let stats = BUFFER.stats();
+    assert_eq!(stats.sent, ITEMS);
+    assert_eq!(received + stats.dropped, ITEMS);
+}
+
+#[test]
+fn block_waits_for_room() {
+    const ITEMS: u32 = 64;
+    static SIGNAL: MockSignal = MockSignal::new();
+    static BUFFER: UninitRingBuffer<u32, 4> = UninitRingBuffer::new();
+    let mut producer = BUFFER
+        .take_sender_with_signal(Core::App, SIGNAL.sender())
+        .unwrap();
+    producer.set_policy(OverflowPolicy::Block);
+    let mut consumer = BUFFER
+        .take_receiver_with_signal(Core::Net, SIGNAL.receiver().unwrap())
+        .unwrap();
+
+    let sending = thread::spawn(move || {
+        for value in 0..ITEMS {
+            block_on(producer.send(value)).unwrap();
+        }
+    });
+    for value in 0..ITEMS {
+        let received = block_on(consumer.recv_timeout(Duration::from_secs(5)));
+        assert_eq!(received, Some(value));
+    }
+    sending.join().unwrap();
+    let stats = BUFFER.stats();
+    assert_eq!((stats.sent, stats.dropped), (ITEMS, 0));
+    assert!(stats.high_water <= 4);
+}
+
+#[test]
+fn high_water_mark_is_the_peak() {
+    let buffer = UninitRingBuffer::<u32, 8>::new();
+    let mut sender = buffer.take_sender(Core::App).unwrap();
+    let mut receiver = buffer.take_receiver(Core::Net).unwrap();
+    for value in 0..3 {
+        sender.enqueue(value).unwrap();
+    }
+    while receiver.dequeue().is_some() {}
+    sender.enqueue(3).unwrap();
+    assert_eq!(buffer.stats().high_water, 3);
+
+    // Safety: Neither end is in use anymore
+    unsafe { buffer.init() };
+    assert_eq!(
+        buffer.stats(),
+        QueueStats {
+            capacity: 8,
+            ..QueueStats::default()
+        }
+    );
+}
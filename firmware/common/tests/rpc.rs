@@ -0,0 +1,236 @@
+mod support;
+
+use common::log::Core;
+use common::ring_buffer::UninitRingBuffer;
+use common::rpc::{
+    Battery, Body, Endpoint, Frame, Link, QueueTransport, RemoteError, Reply, Request, RpcError,
+    Transport, RPC_QUEUE_LEN,
+};
+use embassy_futures::block_on;
+use embassy_futures::join::join;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::watch::{Receiver, Sender, Watch};
+use embassy_time::Duration;
+
+/// Stands in for the IPC event of the other core
+type MockSignal = Watch<CriticalSectionRawMutex, (), 1>;
+type Queue = UninitRingBuffer<Frame, RPC_QUEUE_LEN>;
+type WiredTransport<'a> = QueueTransport<
+    'a,
+    Sender<'a, CriticalSectionRawMutex, (), 1>,
+    Receiver<'a, CriticalSectionRawMutex, (), 1>,
+>;
+
+const TIMEOUT: Duration = Duration::from_millis(50);
+const BATTERY: Battery = Battery {
+    percentage: 72.5,
+    charging: false,
+    estimated_time: 14_400,
+};
+const LINK: Link = Link {
+    connected: true,
+    rssi: Some(-60),
+};
+const DEVICE_TIME: u64 = 1_700_000_000_123_456;
+
+/// Queues and signals of a link between two simulated cores
+struct Wires {
+    app_to_net: Queue,
+    net_to_app: Queue,
+    to_net: MockSignal,
+    to_app: MockSignal,
+}
+
+impl Wires {
+    fn new() -> Self {
+        Self {
+            app_to_net: Queue::new(),
+            net_to_app: Queue::new(),
+            to_net: MockSignal::new(),
+            to_app: MockSignal::new(),
+        }
+    }
+
+    fn app(&self) -> WiredTransport<'_> {
+        QueueTransport {
+            sender: self
+                .app_to_net
+                .take_sender_with_signal(Core::App, self.to_net.sender())
+                .unwrap(),
+            receiver: self
+                .net_to_app
+                .take_receiver_with_signal(Core::App, self.to_app.receiver().unwrap())
+                .unwrap(),
+        }
+    }
+
+    fn net(&self) -> WiredTransport<'_> {
+        QueueTransport {
+            sender: self
+                .net_to_app
+                .take_sender_with_signal(Core::Net, self.to_app.sender())
+                .unwrap(),
+            receiver: self
+                .app_to_net
+                .take_receiver_with_signal(Core::Net, self.to_net.receiver().unwrap())
+                .unwrap(),
+        }
+    }
+}
+
+fn app_handler(request: Request) -> Result<Reply, RemoteError> {
+    match request {
+        Request::Battery => Ok(Reply::Battery(BATTERY)),
+        _ => Err(RemoteError::Unsupported),
+    }
+}
+
+fn net_handler(request: Request) -> Result<Reply, RemoteError> {
+    match request {
+        Request::Link => Ok(Reply::Link(LINK)),
+        Request::Time => Ok(Reply::Time(DEVICE_TIME)),
+        _ => Err(RemoteError::Unsupported),
+    }
+}
+
+type FnHandler = fn(Request) -> Result<Reply, RemoteError>;
+
+/// Drops every frame sent through it, like a core that stopped listening
+struct Lossy<T>(T);
+
+impl<T: Transport> Transport for Lossy<T> {
+    async fn send(&mut self, _frame: Frame) -> Result<(), Frame> {
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Frame {
+        self.0.recv().await
+    }
+}
+
+#[test]
+fn call_gets_the_reply_of_the_other_core() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(wires.app(), app_handler as FnHandler);
+    let mut net = Endpoint::new(wires.net(), net_handler as FnHandler);
+
+    let (link, ()) = block_on(join(app.link(TIMEOUT), net.serve()));
+    assert_eq!(link, Ok(LINK));
+    let (battery, ()) = block_on(join(net.battery(TIMEOUT), app.serve()));
+    assert_eq!(battery, Ok(BATTERY));
+    let (time, ()) = block_on(join(app.time(TIMEOUT), net.serve()));
+    assert_eq!(time, Ok(DEVICE_TIME));
+}
+
+#[test]
+fn remote_errors_are_passed_back() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(wires.app(), app_handler as FnHandler);
+    let mut net = Endpoint::new(wires.net(), net_handler as FnHandler);
+
+    let (battery, ()) = block_on(join(app.battery(TIMEOUT), net.serve()));
+    assert_eq!(battery, Err(RpcError::Remote(RemoteError::Unsupported)));
+}
+
+#[test]
+fn reply_of_the_wrong_kind_is_an_error() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(wires.app(), app_handler as FnHandler);
+    let mut net = Endpoint::new(wires.net(), |_| Ok(Reply::Battery(BATTERY)));
+
+    let (link, ()) = block_on(join(app.link(TIMEOUT), net.serve()));
+    assert_eq!(link, Err(RpcError::UnexpectedReply));
+}
+
+#[test]
+fn call_times_out_when_nobody_answers() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(wires.app(), app_handler as FnHandler);
+    let _net = wires.net();
+
+    assert_eq!(block_on(app.link(TIMEOUT)), Err(RpcError::Timeout));
+}
+
+#[test]
+fn call_times_out_when_the_request_is_lost() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(Lossy(wires.app()), app_handler as FnHandler);
+    let mut net = Endpoint::new(wires.net(), net_handler as FnHandler);
+
+    let Either::First(link) = block_on(select(app.link(TIMEOUT), net.serve())) else {
+        panic!("the network core got a request");
+    };
+    assert_eq!(link, Err(RpcError::Timeout));
+}
+
+#[test]
+fn call_fails_when_the_queue_is_full() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(wires.app(), app_handler as FnHandler);
+    let _net = wires.net();
+
+    for _ in 0..RPC_QUEUE_LEN {
+        assert_eq!(
+            block_on(app.call(Request::Link, Duration::from_millis(1))),
+            Err(RpcError::Timeout)
+        );
+    }
+    assert_eq!(block_on(app.link(TIMEOUT)), Err(RpcError::QueueFull));
+}
+
+#[test]
+fn late_reply_isnt_taken_for_the_next_one() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(wires.app(), app_handler as FnHandler);
+    let mut answered = 0;
+    let mut net = Endpoint::new(wires.net(), |_| {
+        answered += 1;
+        Ok(Reply::Link(Link {
+            connected: true,
+            rssi: Some(answered),
+        }))
+    });
+
+    assert_eq!(block_on(app.link(TIMEOUT)), Err(RpcError::Timeout));
+    // Answers the call that timed out, then the next one
+    let serve_twice = async {
+        net.serve().await;
+        net.serve().await;
+    };
+    let (link, ()) = block_on(join(app.link(TIMEOUT), serve_twice));
+    assert_eq!(link.unwrap().rssi, Some(2));
+}
+
+#[test]
+fn both_cores_can_call_each_other_at_once() {
+    let wires = Wires::new();
+    let mut app = Endpoint::new(wires.app(), app_handler as FnHandler);
+    let mut net = Endpoint::new(wires.net(), net_handler as FnHandler);
+
+    let (link, battery) = block_on(join(app.link(TIMEOUT), net.battery(TIMEOUT)));
+    assert_eq!(link, Ok(LINK));
+    assert_eq!(battery, Ok(BATTERY));
+}
+
+#[test]
+fn ids_of_replies_match_their_requests() {
+    let wires = Wires::new();
+    let mut app = wires.app();
+    let mut net = Endpoint::new(wires.net(), net_handler as FnHandler);
+
+    let request = Frame {
+        id: 7,
+        body: Body::Request(Request::Link),
+    };
+    block_on(app.send(request)).unwrap();
+    block_on(net.serve());
+    let reply = block_on(app.recv());
+    assert_eq!(
+        reply,
+        Frame {
+            id: 7,
+            body: Body::Reply(Ok(Reply::Link(LINK))),
+        }
+    );
+}
@@ -0,0 +1,44 @@
+mod support;
+
+use common::signal::{thread_signal, Notify, Wait};
+use embassy_futures::{block_on, poll_once};
+use std::pin::pin;
+use std::task::Poll;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn notification_before_waiting_isnt_lost() {
+    let (mut notifier, mut waiter) = thread_signal();
+    notifier.notify();
+    block_on(waiter.wait());
+}
+
+#[test]
+fn notifications_while_not_waiting_count_once() {
+    let (mut notifier, mut waiter) = thread_signal();
+    notifier.notify();
+    notifier.notify();
+    assert_eq!(poll_once(waiter.wait()), Poll::Ready(()));
+    assert_eq!(poll_once(waiter.wait()), Poll::Pending);
+}
+
+#[test]
+fn dropped_wait_doesnt_lose_a_notification() {
+    let (mut notifier, mut waiter) = thread_signal();
+    {
+        let mut wait = pin!(waiter.wait());
+        assert_eq!(poll_once(wait.as_mut()), Poll::Pending);
+    }
+    notifier.notify();
+    assert_eq!(poll_once(waiter.wait()), Poll::Ready(()));
+}
+
+#[test]
+fn notification_wakes_up_another_thread() {
+    let (mut notifier, mut waiter) = thread_signal();
+    let waiting = thread::spawn(move || block_on(waiter.wait()));
+    thread::sleep(Duration::from_millis(10));
+    notifier.notify();
+    waiting.join().unwrap();
+}
@@ -0,0 +1,18 @@
+//! Shared by the tests. embassy-sync logs through defmt, which has nowhere to go on the host.
+
+#[defmt::global_logger]
+struct NoLogger;
+
+unsafe impl defmt::Logger for NoLogger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("");
+
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
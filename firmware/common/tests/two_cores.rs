@@ -0,0 +1,195 @@
+//! Both cores as threads, talking through the same queues, sample blocks and RPC as on the device.
+//!
+//! Only the transport between the cores is the one of the firmware: what the cores do with the
+//! commands is left to stand-ins, which echo them back.
+
+mod support;
+
+use common::block_buffer::{BlockBuffer, BlockReader, BlockWriter};
+use common::ipc::{Channels, Message, Payload, SampleBlock, QUEUE_LEN, SAMPLE_BLOCK_COUNT};
+use common::log::Core;
+use common::ring_buffer::{RingBufferConsumer, RingBufferProducer};
+use common::rpc::{Battery, Endpoint, Link, QueueTransport, RemoteError, Reply, Request};
+use common::signal::{thread_signal, ThreadNotifier, ThreadWaiter};
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_time::Duration;
+use std::thread;
+
+type Sender = RingBufferProducer<'static, Message, ThreadNotifier>;
+type Receiver = RingBufferConsumer<'static, Message, ThreadWaiter>;
+type Transport = QueueTransport<'static, ThreadNotifier, ThreadWaiter>;
+type Handler = fn(Request) -> Result<Reply, RemoteError>;
+type Rpc = Endpoint<Transport, Handler>;
+
+/// Generous, the threads share the machine with the other tests
+const RPC_TIMEOUT: Duration = Duration::from_secs(1);
+const COMMANDS: usize = 10;
+const BATTERY: Battery = Battery {
+    percentage: 40.0,
+    charging: true,
+    estimated_time: 3_600,
+};
+const LINK: Link = Link {
+    connected: true,
+    rssi: Some(-52),
+};
+
+/// Stands in for the application core, with a sample block and an echo of every command
+fn app_core(
+    mut from_net: Receiver,
+    mut to_net: Sender,
+    mut rpc: Rpc,
+    mut blocks: BlockWriter<'static, SampleBlock, SAMPLE_BLOCK_COUNT, ThreadNotifier>,
+) {
+    block_on(async {
+        assert_eq!(rpc.link(RPC_TIMEOUT).await, Ok(LINK));
+        let mut counter = 0;
+        while counter < COMMANDS as u64 {
+            let message = match select(from_net.recv(), rpc.serve()).await {
+                Either::First(message) => message,
+                Either::Second(()) => continue,
+            };
+            let Message::Command(command) = message else {
+                panic!("unexpected message from the network core");
+            };
+
+            let mut block = blocks
+                .acquire()
+                .expect("the network core freed every block");
+            block.sample_counter = counter;
+            block.commit();
+
+            let reply = Payload::encode(|out| {
+                let bytes = command.as_bytes();
+                out[..4].copy_from_slice(b"ack ");
+                out[4..][..bytes.len()].copy_from_slice(bytes);
+                Ok::<_, ()>(4 + bytes.len())
+            });
+            assert!(to_net.send(Message::ToHost(reply.unwrap())).await.is_ok());
+            counter += 1;
+        }
+    });
+}
+
+/// Stands in for the network core, forwarding commands as if they came from the host
+fn net_core(
+    mut to_app: Sender,
+    mut from_app: Receiver,
+    mut rpc: Rpc,
+    mut blocks: BlockReader<'static, SampleBlock, SAMPLE_BLOCK_COUNT, ThreadWaiter>,
+) {
+    block_on(async {
+        for counter in 0..COMMANDS {
+            if counter == COMMANDS / 2 {
+                assert_eq!(rpc.battery(RPC_TIMEOUT).await, Ok(BATTERY));
+            }
+            let command = format!("command {counter}");
+            let payload = Payload::new(command.as_bytes()).unwrap();
+            assert!(to_app.send(Message::Command(payload)).await.is_ok());
+
+            let reply = loop {
+                match select(from_app.recv(), rpc.serve()).await {
+                    Either::First(message) => break message,
+                    Either::Second(()) => {}
+                }
+            };
+            let Message::ToHost(reply) = reply else {
+                panic!("unexpected message from the application core");
+            };
+            assert_eq!(reply.as_bytes(), format!("ack {command}").as_bytes());
+
+            // Committed before the reply was sent
+            let block = blocks.try_acquire().expect("a block came with the reply");
+            assert_eq!(block.sample_counter, counter as u64);
+        }
+    });
+}
+
+fn answer_on_app_core(request: Request) -> Result<Reply, RemoteError> {
+    match request {
+        Request::Battery => Ok(Reply::Battery(BATTERY)),
+        Request::Link | Request::Time => Err(RemoteError::Unsupported),
+    }
+}
+
+fn answer_on_net_core(request: Request) -> Result<Reply, RemoteError> {
+    match request {
+        Request::Link => Ok(Reply::Link(LINK)),
+        Request::Time => Ok(Reply::Time(0)),
+        Request::Battery => Err(RemoteError::Unsupported),
+    }
+}
+
+#[test]
+fn cores_exchange_commands_blocks_and_calls() {
+    static CHANNELS: Channels<QUEUE_LEN> = Channels::new();
+    static BLOCKS: BlockBuffer<SampleBlock, SAMPLE_BLOCK_COUNT> = BlockBuffer::new();
+    // Safety: Nothing took an end yet
+    unsafe {
+        CHANNELS.init();
+        BLOCKS.init();
+    }
+
+    // One signal per IPC channel
+    let (to_net, from_app) = thread_signal();
+    let (to_app, from_net) = thread_signal();
+    let (blocks_to_net, blocks_from_app) = thread_signal();
+    let (rpc_to_net, rpc_from_app) = thread_signal();
+    let (rpc_to_app, rpc_from_net) = thread_signal();
+
+    let app_rpc = Transport {
+        sender: CHANNELS
+            .rpc_app_to_net
+            .take_sender_with_signal(Core::App, rpc_to_net)
+            .unwrap(),
+        receiver: CHANNELS
+            .rpc_net_to_app
+            .take_receiver_with_signal(Core::App, rpc_from_net)
+            .unwrap(),
+    };
+    let app = (
+        CHANNELS
+            .net_to_app
+            .take_receiver_with_signal(Core::App, from_net)
+            .unwrap(),
+        CHANNELS
+            .app_to_net
+            .take_sender_with_signal(Core::App, to_net)
+            .unwrap(),
+        Endpoint::new(app_rpc, answer_on_app_core as Handler),
+        BLOCKS.take_writer(Core::App, blocks_to_net).unwrap(),
+    );
+    let net_rpc = Transport {
+        sender: CHANNELS
+            .rpc_net_to_app
+            .take_sender_with_signal(Core::Net, rpc_to_app)
+            .unwrap(),
+        receiver: CHANNELS
+            .rpc_app_to_net
+            .take_receiver_with_signal(Core::Net, rpc_from_app)
+            .unwrap(),
+    };
+    let net = (
+        CHANNELS
+            .net_to_app
+            .take_sender_with_signal(Core::Net, to_app)
+            .unwrap(),
+        CHANNELS
+            .app_to_net
+            .take_receiver_with_signal(Core::Net, from_app)
+            .unwrap(),
+        Endpoint::new(net_rpc, answer_on_net_core as Handler),
+        BLOCKS.take_reader(Core::Net, blocks_from_app).unwrap(),
+    );
+
+    let app = thread::spawn(move || app_core(app.0, app.1, app.2, app.3));
+    let net = thread::spawn(move || net_core(net.0, net.1, net.2, net.3));
+    app.join().unwrap();
+    net.join().unwrap();
+
+    assert_eq!(CHANNELS.app_to_net.stats().sent, COMMANDS as u32);
+    assert_eq!(CHANNELS.net_to_app.stats().sent, COMMANDS as u32);
+    assert_eq!(CHANNELS.net_to_app.stats().dropped, 0);
+    assert_eq!(BLOCKS.overruns(), 0);
+}
@@ -0,0 +1,262 @@
+//! Artifacts that swamp the EEG: eye blinks, muscle activity, a saturated ADC and electrodes that
+//! lost contact. Each channel is flagged for every segment of consecutive samples.
+//!
+//! Blinks are slow and large, so they are looked for in a band below alpha. Muscle activity is
+//! spread over the higher frequencies, where the EEG itself is weak.
+
+use crate::biquad::{Cascade, Coefficients, Design, Section, BUTTERWORTH_Q, MAINS_NOTCH_Q};
+use crate::{math, Mains};
+use core::fmt;
+use core::ops::{BitOr, BitOrAssign};
+
+/// Band blinks are looked for in (Hz)
+pub const BLINK_BAND: (f32, f32) = (1.0, 8.0);
+/// Muscle activity is looked for above this frequency (Hz)
+pub const MUSCLE_CUTOFF: f32 = 30.0;
+
+/// Artifacts found in a segment of a channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Artifacts(u8);
+
+impl Artifacts {
+    pub const NONE: Self = Self(0);
+    /// Large slow deflection, as an eye blink causes on the frontal electrodes
+    pub const BLINK: Self = Self(1 << 0);
+    /// Burst of high frequency power, as tensing the jaw or forehead causes
+    pub const MUSCLE: Self = Self(1 << 1);
+    /// Samples at the limits of the ADC
+    pub const CLIPPING: Self = Self(1 << 2);
+    /// Next to no variation at all, as a disconnected electrode gives
+    pub const FLATLINE: Self = Self(1 << 3);
+
+    const NAMES: [(Self, &'static str); 4] = [
+        (Self::BLINK, "blink"),
+        (Self::MUSCLE, "muscle"),
+        (Self::CLIPPING, "clipping"),
+        (Self::FLATLINE, "flatline"),
+    ];
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Ignores bits that aren't an artifact
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & 0b1111)
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Artifacts {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Artifacts {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// Names of the artifacts separated by commas, or `clean`
+impl fmt::Display for Artifacts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("clean");
+        }
+        let names = Self::NAMES.iter().filter(|(flag, _)| self.contains(*flag));
+        for (n, (_, name)) in names.enumerate() {
+            if n > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Thresholds in the units of the samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArtifactConfig {
+    /// Length of the segments flagged (s)
+    pub segment: f32,
+    /// Samples of this magnitude or more are clipped
+    pub clip_level: f32,
+    /// A segment is flat when all its samples lie within this range
+    pub flat_range: f32,
+    /// Peak to peak amplitude in [`BLINK_BAND`] from which a segment has a blink
+    pub blink_amplitude: f32,
+    /// RMS above [`MUSCLE_CUTOFF`] from which a segment has muscle activity
+    pub muscle_rms: f32,
+}
+
+impl ArtifactConfig {
+    /// Half second segments with the usual thresholds, for samples that go up to `full_scale` with
+    /// `per_microvolt` units per µV
+    pub fn new(full_scale: f32, per_microvolt: f32) -> Self {
+        Self {
+            segment: 0.5,
+            clip_level: 0.99 * full_scale,
+            flat_range: 0.5 * per_microvolt,
+            blink_amplitude: 100.0 * per_microvolt,
+            muscle_rms: 10.0 * per_microvolt,
+        }
+    }
+
+    /// Artifacts of a segment of `len` samples
+    fn flags(&self, segment: &Segment, len: usize) -> Artifacts {
+        let mut flags = Artifacts::NONE;
+        if segment.clipped {
+            flags |= Artifacts::CLIPPING;
+        }
+        if segment.max - segment.min <= self.flat_range {
+            flags |= Artifacts::FLATLINE;
+        }
+        if segment.blink_max - segment.blink_min >= self.blink_amplitude {
+            flags |= Artifacts::BLINK;
+        }
+        let rms = math::sqrt(f64::from(segment.muscle_energy) / len as f64);
+        if rms >= f64::from(self.muscle_rms) {
+            flags |= Artifacts::MUSCLE;
+        }
+        flags
+    }
+}
+
+/// What was seen of a channel in the current segment
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    min: f32,
+    max: f32,
+    clipped: bool,
+    blink_min: f32,
+    blink_max: f32,
+    /// Sum of the squares of the muscle band
+    muscle_energy: f32,
+}
+
+impl Default for Segment {
+    fn default() -> Self {
+        Self {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            clipped: false,
+            blink_min: f32::INFINITY,
+            blink_max: f32::NEG_INFINITY,
+            muscle_energy: 0.0,
+        }
+    }
+}
+
+/// Flags the artifacts of `C` channels, segment by segment, filtering them with sections of type
+/// `F`. The [`Coefficients`] in `f32` are fast enough for the firmware, but the lower edge of
+/// [`BLINK_BAND`] is too low for them at the higher sample rates. A [`Design`] filters in `f64` and
+/// holds it at every sample rate, where that's affordable.
+#[derive(Debug, Clone)]
+pub struct ArtifactDetector<const C: usize, F: Section = Coefficients> {
+    config: ArtifactConfig,
+    segment_len: usize,
+    blink: Cascade<F, 2, C>,
+    /// High pass, then a notch for the mains which is well within the band
+    muscle: Cascade<F, 2, C>,
+    /// First sample of each channel, taken off the input of the filters so that they don't ring
+    /// from the DC offset of the electrodes
+    offset: Option<[f32; C]>,
+    segments: [Segment; C],
+    /// Samples in the current segment
+    filled: usize,
+    flags: [Artifacts; C],
+}
+
+impl<const C: usize, F> ArtifactDetector<C, F>
+where
+    F: Section + From<Design>,
+    F::Sample: From<f32> + Into<f64>,
+{
+    /// Panics if the segments are shorter than a sample, or the muscle band is above the Nyquist
+    /// frequency
+    pub fn new(config: ArtifactConfig, mains: Mains, sample_rate: f32) -> Self {
+        let segment_len = math::round(f64::from(config.segment * sample_rate)) as usize;
+        assert!(
+            segment_len > 0,
+            "segments have to be at least a sample long"
+        );
+        assert!(
+            MUSCLE_CUTOFF < sample_rate / 2.0,
+            "sample rate too low for muscle activity"
+        );
+        let (low, high) = BLINK_BAND;
+        let muscle = [
+            Design::high_pass(MUSCLE_CUTOFF, BUTTERWORTH_Q, sample_rate).into(),
+            Design::notch(mains.frequency(), MAINS_NOTCH_Q, sample_rate).into(),
+        ];
+        Self {
+            config,
+            segment_len,
+            blink: Cascade::new(Design::band_pass(low, high, sample_rate).map(Into::into)),
+            muscle: Cascade::new(muscle),
+            offset: None,
+            segments: [Segment::default(); C],
+            filled: 0,
+            flags: [Artifacts::NONE; C],
+        }
+    }
+
+    pub fn config(&self) -> ArtifactConfig {
+        self.config
+    }
+
+    /// Samples in each segment
+    pub fn segment_len(&self) -> usize {
+        self.segment_len
+    }
+
+    /// Adds the next sample of every channel. Returns the artifacts of each channel at the end of
+    /// every segment.
+    pub fn push(&mut self, frame: &[f32; C]) -> Option<&[Artifacts; C]> {
+        let offset = *self.offset.get_or_insert(*frame);
+        for (channel, segment) in self.segments.iter_mut().enumerate() {
+            let x = frame[channel];
+            segment.min = segment.min.min(x);
+            segment.max = segment.max.max(x);
+            segment.clipped |= x.abs() >= self.config.clip_level;
+
+            let centred = x - offset[channel];
+            let blink = self.blink.process(channel, centred.into()).into() as f32;
+            segment.blink_min = segment.blink_min.min(blink);
+            segment.blink_max = segment.blink_max.max(blink);
+            let muscle = self.muscle.process(channel, centred.into()).into() as f32;
+            segment.muscle_energy += muscle * muscle;
+        }
+        self.filled += 1;
+        if self.filled < self.segment_len {
+            return None;
+        }
+
+        for (segment, flags) in self.segments.iter().zip(&mut self.flags) {
+            *flags = self.config.flags(segment, self.segment_len);
+        }
+        self.segments = [Segment::default(); C];
+        self.filled = 0;
+        Some(&self.flags)
+    }
+
+    /// Starts over, e.g. after samples were lost
+    pub fn reset(&mut self) {
+        self.blink.reset();
+        self.muscle.reset();
+        self.offset = None;
+        self.segments = [Segment::default(); C];
+        self.filled = 0;
+    }
+}
@@ -0,0 +1,265 @@
+//! Second-order IIR filters ("biquads") and [`Cascade`]s of them.
+//!
+//! A [`Design`] holds exact coefficients, from the bilinear transform of an analog prototype as in
+//! the Audio EQ Cookbook. It's turned into [`Coefficients`] to filter in `f32`, or
+//! [`FixedCoefficients`](crate::FixedCoefficients) to filter integer samples, or filters in `f64`
+//! as it is where that's affordable. A cascade runs its sections on any number of channels, each
+//! with its own state, so it can be fed samples as they come in.
+
+use crate::math;
+use crate::Mains;
+use core::f64::consts::PI;
+use core::fmt;
+
+/// Q of a second-order Butterworth filter, as flat as can be in the pass band
+pub const BUTTERWORTH_Q: f32 = core::f32::consts::FRAC_1_SQRT_2;
+/// Q of the mains notch, which is the mains frequency over this wide at -3 dB
+pub const MAINS_NOTCH_Q: f32 = 20.0;
+/// Cut-off of the high-pass removing the drift of the electrodes (Hz)
+pub const DRIFT_CUTOFF: f32 = 0.5;
+
+/// Coefficients of `y[n] = b0·x[n] + b1·x[n-1] + b2·x[n-2] - a1·y[n-1] - a2·y[n-2]`, normalised so
+/// that `a0` is 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Design {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl Design {
+    /// Lets everything through unchanged
+    pub const IDENTITY: Self = Self {
+        b0: 1.0,
+        b1: 0.0,
+        b2: 0.0,
+        a1: 0.0,
+        a2: 0.0,
+    };
+
+    /// Removes `frequency` (Hz), over a band `frequency / q` wide at -3 dB
+    pub fn notch(frequency: f32, q: f32, sample_rate: f32) -> Self {
+        Self::cookbook(frequency, q, sample_rate, |cos, _| [1.0, -2.0 * cos, 1.0])
+    }
+
+    /// Removes what is under `frequency` (Hz), which is let through at -3 dB with
+    /// [`BUTTERWORTH_Q`]
+    pub fn high_pass(frequency: f32, q: f32, sample_rate: f32) -> Self {
+        Self::cookbook(frequency, q, sample_rate, |cos, _| {
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0]
+        })
+    }
+
+    /// Removes what is over `frequency` (Hz), which is let through at -3 dB with
+    /// [`BUTTERWORTH_Q`]
+    pub fn low_pass(frequency: f32, q: f32, sample_rate: f32) -> Self {
+        Self::cookbook(frequency, q, sample_rate, |cos, _| {
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0]
+        })
+    }
+
+    /// Lets through what is between `low` and `high` (Hz), both at -3 dB, as a Butterworth
+    /// high-pass and low-pass
+    pub fn band_pass(low: f32, high: f32, sample_rate: f32) -> [Self; 2] {
+        [
+            Self::high_pass(low, BUTTERWORTH_Q, sample_rate),
+            Self::low_pass(high, BUTTERWORTH_Q, sample_rate),
+        ]
+    }
+
+    /// Cleans up raw EEG: removes the drift of the electrodes and the interference of the mains
+    pub fn preprocessing(mains: Mains, sample_rate: f32) -> [Self; 2] {
+        [
+            Self::high_pass(DRIFT_CUTOFF, BUTTERWORTH_Q, sample_rate),
+            Self::notch(mains.frequency(), MAINS_NOTCH_Q, sample_rate),
+        ]
+    }
+
+    /// `numerator` gets the cosine of the centre frequency and the `alpha` of the cookbook
+    fn cookbook(
+        frequency: f32,
+        q: f32,
+        sample_rate: f32,
+        numerator: impl FnOnce(f64, f64) -> [f64; 3],
+    ) -> Self {
+        debug_assert!(
+            frequency > 0.0 && frequency < sample_rate / 2.0,
+            "the frequency has to be under half the sample rate"
+        );
+        let w0 = 2.0 * PI * frequency as f64 / sample_rate as f64;
+        let (sin, cos) = math::sin_cos(w0);
+        let alpha = sin / (2.0 * q as f64);
+        let [b0, b1, b2] = numerator(cos, alpha);
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// Gain at `frequency` (Hz), 1 being unchanged
+    pub fn gain(&self, frequency: f32, sample_rate: f32) -> f64 {
+        let w = 2.0 * PI * frequency as f64 / sample_rate as f64;
+        let (sin, cos) = math::sin_cos(w);
+        let (sin2, cos2) = math::sin_cos(2.0 * w);
+        // H(e^jw) = (b0 + b1·e^-jw + b2·e^-2jw) / (1 + a1·e^-jw + a2·e^-2jw)
+        let num_re = self.b0 + self.b1 * cos + self.b2 * cos2;
+        let num_im = self.b1 * sin + self.b2 * sin2;
+        let den_re = 1.0 + self.a1 * cos + self.a2 * cos2;
+        let den_im = self.a1 * sin + self.a2 * sin2;
+        math::sqrt((num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im))
+    }
+}
+
+/// One section of a [`Cascade`]
+pub trait Section {
+    type Sample: Copy;
+    /// What each channel keeps between samples
+    type State: Copy + Default + fmt::Debug;
+
+    /// Filters the next sample `x` of a channel
+    fn process(&self, state: &mut Self::State, x: Self::Sample) -> Self::Sample;
+
+    /// The coefficients actually used, after rounding
+    fn design(&self) -> Design;
+}
+
+/// A [`Design`] rounded to `f32`, run in transposed direct form II.
+///
+/// Cut-offs far under the sample rate lose precision in `f32`: [`DRIFT_CUTOFF`] is accurate up to
+/// 1 kHz. Fixed point keeps more of it, and a [`Design`] run in `f64` all of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl From<Design> for Coefficients {
+    fn from(design: Design) -> Self {
+        Self {
+            b0: design.b0 as f32,
+            b1: design.b1 as f32,
+            b2: design.b2 as f32,
+            a1: design.a1 as f32,
+            a2: design.a2 as f32,
+        }
+    }
+}
+
+/// State of a channel through [`Coefficients`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct State {
+    s1: f32,
+    s2: f32,
+}
+
+impl Section for Coefficients {
+    type Sample = f32;
+    type State = State;
+
+    fn process(&self, state: &mut State, x: f32) -> f32 {
+        let y = self.b0 * x + state.s1;
+        state.s1 = self.b1 * x - self.a1 * y + state.s2;
+        state.s2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    fn design(&self) -> Design {
+        Design {
+            b0: self.b0 as f64,
+            b1: self.b1 as f64,
+            b2: self.b2 as f64,
+            a1: self.a1 as f64,
+            a2: self.a2 as f64,
+        }
+    }
+}
+
+/// State of a channel through a [`Design`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DesignState {
+    s1: f64,
+    s2: f64,
+}
+
+/// A [`Design`] runs in `f64`, in transposed direct form II. Its cut-offs hold at every sample rate
+/// of the front-end, but without an FPU for `f64` it's slow.
+impl Section for Design {
+    type Sample = f64;
+    type State = DesignState;
+
+    fn process(&self, state: &mut DesignState, x: f64) -> f64 {
+        let y = self.b0 * x + state.s1;
+        state.s1 = self.b1 * x - self.a1 * y + state.s2;
+        state.s2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    fn design(&self) -> Design {
+        *self
+    }
+}
+
+/// `S` sections run one after the other, on each of `C` channels
+#[derive(Debug, Clone)]
+pub struct Cascade<F: Section, const S: usize, const C: usize> {
+    sections: [F; S],
+    states: [[F::State; S]; C],
+}
+
+impl<F: Section, const S: usize, const C: usize> Cascade<F, S, C> {
+    pub fn new(sections: [F; S]) -> Self {
+        Self {
+            sections,
+            states: [[F::State::default(); S]; C],
+        }
+    }
+
+    pub fn sections(&self) -> &[F; S] {
+        &self.sections
+    }
+
+    /// Filters the next sample of `channel`
+    pub fn process(&mut self, channel: usize, x: F::Sample) -> F::Sample {
+        let states = &mut self.states[channel];
+        self.sections
+            .iter()
+            .zip(states)
+            .fold(x, |x, (section, state)| section.process(state, x))
+    }
+
+    /// Filters the next sample of every channel in place
+    pub fn process_frame(&mut self, frame: &mut [F::Sample; C]) {
+        for (channel, x) in frame.iter_mut().enumerate() {
+            *x = self.process(channel, *x);
+        }
+    }
+
+    /// Filters samples interleaved by channel in place, starting with channel 0
+    pub fn process_interleaved(&mut self, samples: &mut [F::Sample]) {
+        for (i, x) in samples.iter_mut().enumerate() {
+            *x = self.process(i % C, *x);
+        }
+    }
+
+    /// Forgets the past samples of every channel, e.g. after samples were lost
+    pub fn reset(&mut self) {
+        self.states = [[F::State::default(); S]; C];
+    }
+
+    /// Gain of the whole cascade at `frequency` (Hz)
+    pub fn gain(&self, frequency: f32, sample_rate: f32) -> f64 {
+        self.sections
+            .iter()
+            .map(|section| section.design().gain(frequency, sample_rate))
+            .product()
+    }
+}
@@ -0,0 +1,104 @@
+//! Fast Fourier transform of a power of two points, radix 2 and in place.
+
+use crate::math;
+use core::f64::consts::PI;
+use core::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub const fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    /// Squared magnitude, the power at a bin of a transform
+    pub fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// Transform of `N` points, with its twiddle factors worked out once
+#[derive(Debug, Clone)]
+pub struct Fft<const N: usize> {
+    /// `e^(-2πik/N)` for `k < N / 2`, the rest is unused
+    twiddles: [Complex; N],
+}
+
+impl<const N: usize> Default for Fft<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Fft<N> {
+    /// Panics if `N` isn't a power of two
+    pub fn new() -> Self {
+        assert!(N.is_power_of_two(), "FFT length has to be a power of two");
+        let mut twiddles = [Complex::default(); N];
+        for (k, twiddle) in twiddles.iter_mut().take(N / 2).enumerate() {
+            let (sin, cos) = math::sin_cos(-2.0 * PI * k as f64 / N as f64);
+            *twiddle = Complex::new(cos as f32, sin as f32);
+        }
+        Self { twiddles }
+    }
+
+    /// Replaces `data` with its discrete Fourier transform, `X[k] = Σ x[n]·e^(-2πikn/N)`
+    pub fn process(&self, data: &mut [Complex; N]) {
+        if N < 2 {
+            return;
+        }
+        let bits = N.trailing_zeros();
+        for i in 0..N {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if j > i {
+                data.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= N {
+            let half = len / 2;
+            let stride = N / len;
+            for start in (0..N).step_by(len) {
+                for k in 0..half {
+                    let odd = self.twiddles[k * stride] * data[start + k + half];
+                    let even = data[start + k];
+                    data[start + k] = even + odd;
+                    data[start + k + half] = even - odd;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Biquads on integer samples, as they come out of the front-end.
+//!
+//! Coefficients are Q2.30, with 30 fractional bits: 6 more than an `f32`, which the poles of a
+//! high-pass with a low cut-off need. Sections run in direct form I with a 64-bit accumulator. The
+//! rounding errors of the last two outputs are fed back through the poles ("error feedback"), so
+//! that the output is the exact one rounded down, instead of rounding noise amplified by poles
+//! close to the unit circle.
+
+use crate::biquad::{Design, Section};
+use crate::math;
+
+/// Fractional bits of the coefficients
+pub const FRACTION_BITS: u32 = 30;
+/// Samples saturate there, which leaves room for the 24 bits of the front-end and keeps the
+/// accumulator from overflowing
+pub const SAMPLE_LIMIT: i32 = 1 << 28;
+
+const ONE: f64 = (1u64 << FRACTION_BITS) as f64;
+
+/// A [`Design`] rounded to Q2.30
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedCoefficients {
+    pub b0: i32,
+    pub b1: i32,
+    pub b2: i32,
+    pub a1: i32,
+    pub a2: i32,
+}
+
+impl From<Design> for FixedCoefficients {
+    fn from(design: Design) -> Self {
+        let fixed = |c: f64| math::round(c * ONE).clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+        Self {
+            b0: fixed(design.b0),
+            b1: fixed(design.b1),
+            b2: fixed(design.b2),
+            a1: fixed(design.a1),
+            a2: fixed(design.a2),
+        }
+    }
+}
+
+/// State of a channel through [`FixedCoefficients`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FixedState {
+    x1: i32,
+    x2: i32,
+    y1: i32,
+    y2: i32,
+    /// Left over from rounding the last two outputs, in units of 2⁻³⁰
+    e1: i64,
+    e2: i64,
+}
+
+impl Section for FixedCoefficients {
+    type Sample = i32;
+    type State = FixedState;
+
+    fn process(&self, state: &mut FixedState, x: i32) -> i32 {
+        let x = x.clamp(-SAMPLE_LIMIT, SAMPLE_LIMIT);
+        let acc = i64::from(self.b0) * i64::from(x)
+            + i64::from(self.b1) * i64::from(state.x1)
+            + i64::from(self.b2) * i64::from(state.x2)
+            - i64::from(self.a1) * i64::from(state.y1)
+            - i64::from(self.a2) * i64::from(state.y2)
+            - ((i64::from(self.a1) * state.e1 + i64::from(self.a2) * state.e2) >> FRACTION_BITS);
+        let y = acc >> FRACTION_BITS;
+        let saturated = y.clamp(-i64::from(SAMPLE_LIMIT), i64::from(SAMPLE_LIMIT));
+        state.e2 = state.e1;
+        state.e1 = if y == saturated {
+            acc - (y << FRACTION_BITS)
+        } else {
+            // Far from the exact output anyway
+            0
+        };
+        state.x2 = state.x1;
+        state.x1 = x;
+        state.y2 = state.y1;
+        state.y1 = saturated as i32;
+        state.y1
+    }
+
+    fn design(&self) -> Design {
+        Design {
+            b0: f64::from(self.b0) / ONE,
+            b1: f64::from(self.b1) / ONE,
+            b2: f64::from(self.b2) / ONE,
+            a1: f64::from(self.a1) / ONE,
+            a2: f64::from(self.a2) / ONE,
+        }
+    }
+}
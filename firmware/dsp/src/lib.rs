@@ -0,0 +1,34 @@
+//! Signal processing shared by the firmware and the host. Nothing here allocates.
+
+#![no_std]
+
+pub mod artifact;
+pub mod biquad;
+pub mod fft;
+pub mod fixed;
+pub mod lock_in;
+mod math;
+pub mod resample;
+pub mod welch;
+
+pub use artifact::{ArtifactConfig, ArtifactDetector, Artifacts};
+pub use biquad::{Cascade, Coefficients, Design, Section};
+pub use fixed::FixedCoefficients;
+pub use resample::Resampler;
+pub use welch::{Welch, WelchConfig, Window};
+
+/// Frequency of the mains, picked up by the electrodes as an interference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mains {
+    Hz50,
+    Hz60,
+}
+
+impl Mains {
+    pub fn frequency(self) -> f32 {
+        match self {
+            Mains::Hz50 => 50.0,
+            Mains::Hz60 => 60.0,
+        }
+    }
+}
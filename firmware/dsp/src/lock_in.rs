@@ -0,0 +1,86 @@
+//! Amplitude of a tone of known frequency in a noisy signal, found by multiplying the signal with a
+//! sine and a cosine at that frequency and averaging, as a lock-in amplifier does.
+//!
+//! Measurements are made over whole periods of the tone, so that anything at other frequencies
+//! averages out. The offset of the signal is taken off first, as electrodes can sit hundreds of
+//! millivolts away from each other.
+
+use crate::math;
+use core::f64::consts::PI;
+
+/// Measures the amplitude of a tone again and again, over consecutive stretches of samples
+#[derive(Debug, Clone)]
+pub struct LockIn {
+    /// Phase the reference advances by each sample (radians)
+    step: f64,
+    phase: f64,
+    /// Samples in each measurement
+    len: usize,
+    count: usize,
+    sum: f64,
+    in_phase: f64,
+    quadrature: f64,
+    /// Of the reference itself, to take the offset off the products
+    cos_sum: f64,
+    sin_sum: f64,
+}
+
+impl LockIn {
+    /// Measures the tone at `frequency` over `periods` of it. Panics if that isn't at least a
+    /// sample at `sample_rate`.
+    pub fn new(frequency: f32, sample_rate: f32, periods: u32) -> Self {
+        let len = f64::from(periods) * f64::from(sample_rate) / f64::from(frequency);
+        let len = math::round(len) as usize;
+        assert!(len > 0, "measurements have to be at least a sample long");
+        Self {
+            step: 2.0 * PI * f64::from(frequency) / f64::from(sample_rate),
+            phase: 0.0,
+            len,
+            count: 0,
+            sum: 0.0,
+            in_phase: 0.0,
+            quadrature: 0.0,
+            cos_sum: 0.0,
+            sin_sum: 0.0,
+        }
+    }
+
+    /// Samples in each measurement
+    pub fn measurement_len(&self) -> usize {
+        self.len
+    }
+
+    /// Adds the next sample. Returns the amplitude of the tone (peak, in the units of the samples)
+    /// at the end of every measurement.
+    pub fn push(&mut self, x: f32) -> Option<f32> {
+        let x = f64::from(x);
+        let (sin, cos) = math::sin_cos(self.phase);
+        self.phase = (self.phase + self.step) % (2.0 * PI);
+        self.sum += x;
+        self.in_phase += x * cos;
+        self.quadrature += x * sin;
+        self.cos_sum += cos;
+        self.sin_sum += sin;
+        self.count += 1;
+        if self.count < self.len {
+            return None;
+        }
+
+        let mean = self.sum / self.len as f64;
+        let in_phase = self.in_phase - mean * self.cos_sum;
+        let quadrature = self.quadrature - mean * self.sin_sum;
+        let amplitude = 2.0 * math::sqrt(in_phase * in_phase + quadrature * quadrature);
+        self.reset();
+        Some((amplitude / self.len as f64) as f32)
+    }
+
+    /// Starts the measurement over, e.g. after samples were lost
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.sum = 0.0;
+        self.in_phase = 0.0;
+        self.quadrature = 0.0;
+        self.cos_sum = 0.0;
+        self.sin_sum = 0.0;
+    }
+}
@@ -0,0 +1,67 @@
+//! What filter design needs of `libm`, which `core` doesn't have. Accurate to a few ULP of an
+//! `f64`, far beyond the `f32` the coefficients end up in.
+
+use core::f64::consts::{FRAC_PI_2, FRAC_PI_4, TAU};
+
+/// Sine and cosine of `x` (radians)
+pub fn sin_cos(x: f64) -> (f64, f64) {
+    // Down to [-π, π], then to [-π/4, π/4] and a quadrant
+    let x = x - TAU * round(x / TAU);
+    let quadrant = round(x / FRAC_PI_2);
+    let r = x - quadrant * FRAC_PI_2;
+    debug_assert!(r.abs() <= FRAC_PI_4 + 1e-12);
+    let (sin, cos) = (sin_taylor(r), cos_taylor(r));
+    match quadrant as i32 {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        -1 => (-cos, sin),
+        _ => (-sin, -cos),
+    }
+}
+
+pub fn sqrt(x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // Halving the exponent is a close enough start for Newton's method
+    let mut y = f64::from_bits((x.to_bits() >> 1) + (1023 << 51));
+    for _ in 0..6 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+/// To the nearest integer, halves away from zero
+pub fn round(x: f64) -> f64 {
+    let truncated = x as i64 as f64;
+    if x - truncated >= 0.5 {
+        truncated + 1.0
+    } else if x - truncated <= -0.5 {
+        truncated - 1.0
+    } else {
+        truncated
+    }
+}
+
+/// Terms up to x¹⁷, the next one is under 1e-19 for |x| ≤ π/4
+fn sin_taylor(x: f64) -> f64 {
+    let x2 = x * x;
+    let mut term = x;
+    let mut sum = x;
+    for n in (2..=16).step_by(2) {
+        term *= -x2 / (n * (n + 1)) as f64;
+        sum += term;
+    }
+    sum
+}
+
+fn cos_taylor(x: f64) -> f64 {
+    let x2 = x * x;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in (1..=15).step_by(2) {
+        term *= -x2 / (n * (n + 1)) as f64;
+        sum += term;
+    }
+    sum
+}
@@ -0,0 +1,131 @@
+//! Sample rate conversion by a rational factor `up / down`, with a polyphase FIR filter that keeps
+//! what is above the new Nyquist frequency from aliasing into the band.
+//!
+//! The prototype low-pass runs at `up` times the input rate. It is split into `up` phases of
+//! `TAPS / up` taps each, and only the outputs that are kept are ever computed, so decimating by
+//! `down` costs `TAPS / down` multiplications per input sample and channel.
+
+use crate::math;
+use crate::welch::Window;
+use core::f64::consts::PI;
+
+/// Fraction of the output Nyquist frequency the pass band of the filter ends at. The transition
+/// band above it narrows as the filter gets longer.
+pub const PASS_BAND: f64 = 0.8;
+
+/// Converts `C` channels from one sample rate to another, with a prototype filter of `TAPS` taps
+#[derive(Debug, Clone)]
+pub struct Resampler<const TAPS: usize, const C: usize> {
+    up: usize,
+    down: usize,
+    /// Prototype low-pass, summing to `up` so that the pass band has unity gain
+    coefficients: [f32; TAPS],
+    /// Last input samples of each channel, the newest at `head`. Only the last `TAPS / up` are
+    /// used.
+    history: [[f32; TAPS]; C],
+    head: usize,
+    /// Of the next output, between inputs at `up` times the input rate
+    phase: usize,
+}
+
+impl<const TAPS: usize, const C: usize> Resampler<TAPS, C> {
+    /// Converts to `up / down` times the input rate. Panics if either is 0, or `TAPS` isn't a
+    /// multiple of `up` once the ratio is reduced.
+    pub fn new(up: usize, down: usize) -> Self {
+        assert!(up > 0 && down > 0, "ratio can't be 0");
+        let divisor = gcd(up, down);
+        let (up, down) = (up / divisor, down / divisor);
+        assert!(
+            TAPS.is_multiple_of(up) && TAPS >= up,
+            "taps have to split evenly into the phases"
+        );
+
+        // Windowed sinc, symmetric around the middle tap
+        let cutoff = PASS_BAND * 0.5 / up.max(down) as f64;
+        let middle = (TAPS - 1) as f64 / 2.0;
+        let mut coefficients = [0.0; TAPS];
+        let mut sum = 0.0;
+        for (n, coefficient) in coefficients.iter_mut().enumerate() {
+            let x = 2.0 * PI * cutoff * (n as f64 - middle);
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                math::sin_cos(x).0 / x
+            };
+            // A periodic window one tap shorter is symmetric over the taps
+            let window = if TAPS > 1 {
+                f64::from(Window::Blackman.weight(n, TAPS - 1))
+            } else {
+                1.0
+            };
+            *coefficient = sinc * window;
+            sum += sinc * window;
+        }
+        let coefficients = coefficients.map(|coefficient| (coefficient * up as f64 / sum) as f32);
+
+        Self {
+            up,
+            down,
+            coefficients,
+            history: [[0.0; TAPS]; C],
+            head: 0,
+            phase: 0,
+        }
+    }
+
+    /// Keeps one sample in `factor`
+    pub fn decimator(factor: usize) -> Self {
+        Self::new(1, factor)
+    }
+
+    /// `(up, down)`, reduced
+    pub fn ratio(&self) -> (usize, usize) {
+        (self.up, self.down)
+    }
+
+    pub fn output_rate(&self, input_rate: f32) -> f32 {
+        input_rate * self.up as f32 / self.down as f32
+    }
+
+    /// How far the output lags behind the input (input samples)
+    pub fn delay(&self) -> f32 {
+        (TAPS - 1) as f32 / 2.0 / self.up as f32
+    }
+
+    /// Adds the next sample of every channel, and hands every output sample this makes to `emit`:
+    /// up to `up / down` of them, rounded up
+    pub fn push(&mut self, frame: &[f32; C], mut emit: impl FnMut(&[f32; C])) {
+        self.head = (self.head + 1) % TAPS;
+        for (history, &x) in self.history.iter_mut().zip(frame) {
+            history[self.head] = x;
+        }
+
+        while self.phase < self.up {
+            let mut output = [0.0; C];
+            for (history, y) in self.history.iter().zip(&mut output) {
+                let taps = self.coefficients[self.phase..].iter().step_by(self.up);
+                for (k, coefficient) in taps.enumerate() {
+                    *y += coefficient * history[(self.head + TAPS - k) % TAPS];
+                }
+            }
+            emit(&output);
+            self.phase += self.down;
+        }
+        self.phase -= self.up;
+    }
+
+    /// Forgets past samples
+    pub fn reset(&mut self) {
+        self.history = [[0.0; TAPS]; C];
+        self.head = 0;
+        self.phase = 0;
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
@@ -0,0 +1,207 @@
+//! Power spectral density by Welch's method, and the power of frequency bands read from it.
+//!
+//! The signal is cut into segments of `N` samples that overlap, each with its mean removed and
+//! tapered by a [`Window`]. The spectra of consecutive segments are averaged into an estimate,
+//! which is much less noisy than the spectrum of a single segment.
+
+use crate::fft::{Complex, Fft};
+use crate::math;
+use core::f64::consts::PI;
+
+/// Edges of the usual EEG bands (Hz), see [`EEG_BAND_NAMES`]
+pub const EEG_BANDS: [f32; 6] = [0.5, 4.0, 8.0, 13.0, 30.0, 45.0];
+/// Names of the bands between consecutive [`EEG_BANDS`]
+pub const EEG_BAND_NAMES: [&str; 5] = ["delta", "theta", "alpha", "beta", "gamma"];
+
+/// Tapers each segment, trading frequency resolution for less leakage between bins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+    /// Weight of sample `n` of a segment of `len`. Periodic rather than symmetric, as suits a
+    /// spectrum.
+    pub fn weight(self, n: usize, len: usize) -> f32 {
+        let x = 2.0 * PI * n as f64 / len as f64;
+        let cos = |k: f64| math::sin_cos(k * x).1;
+        let weight = match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * cos(1.0),
+            Window::Hamming => 0.54 - 0.46 * cos(1.0),
+            Window::Blackman => 0.42 - 0.5 * cos(1.0) + 0.08 * cos(2.0),
+        };
+        weight as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WelchConfig {
+    pub window: Window,
+    /// Samples each segment shares with the one before, under the segment length. Half a segment
+    /// is usual with a Hann window.
+    pub overlap: usize,
+    /// Segments averaged into each estimate
+    pub segments: usize,
+}
+
+/// Estimates the spectrum of `C` channels from segments of `N` samples, `N` being a power of two
+#[derive(Debug, Clone)]
+pub struct Welch<const N: usize, const C: usize> {
+    config: WelchConfig,
+    sample_rate: f32,
+    fft: Fft<N>,
+    weights: [f32; N],
+    /// From `|X[k]|²` to the one-sided density, averaged over the segments:
+    /// `2 / (sample rate · Σw² · segments)`
+    scale: f32,
+    /// Last `N` samples of each channel, oldest at `head`
+    history: [[f32; N]; C],
+    head: usize,
+    /// Samples in `history`, until it fills up
+    filled: usize,
+    /// Samples since the last segment was transformed
+    since_segment: usize,
+    /// Segments averaged into `density` so far
+    averaged: usize,
+    /// Of each channel, only the first `N / 2 + 1` bins are used
+    density: [[f32; N]; C],
+}
+
+impl<const N: usize, const C: usize> Welch<N, C> {
+    /// Panics if `N` isn't a power of two, the overlap isn't under `N` or there are no segments
+    pub fn new(config: WelchConfig, sample_rate: f32) -> Self {
+        assert!(config.overlap < N, "segments can't overlap entirely");
+        assert!(config.segments > 0, "estimates need at least a segment");
+        let weights = core::array::from_fn(|n| config.window.weight(n, N));
+        let power: f32 = weights.iter().map(|w| w * w).sum();
+        Self {
+            config,
+            sample_rate,
+            fft: Fft::new(),
+            weights,
+            scale: 2.0 / (sample_rate * power * config.segments as f32),
+            history: [[0.0; N]; C],
+            head: 0,
+            filled: 0,
+            since_segment: 0,
+            averaged: 0,
+            density: [[0.0; N]; C],
+        }
+    }
+
+    pub fn config(&self) -> WelchConfig {
+        self.config
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Spacing of the bins of the density (Hz)
+    pub fn resolution(&self) -> f32 {
+        self.sample_rate / N as f32
+    }
+
+    /// Consecutive samples each estimate covers, up to the last one pushed
+    pub fn span(&self) -> usize {
+        N + (self.config.segments - 1) * self.hop()
+    }
+
+    /// Adds the next sample of every channel. Returns an estimate once enough segments were
+    /// averaged, every `segments · (N - overlap)` samples after the first one.
+    pub fn push(&mut self, frame: &[f32; C]) -> Option<Estimate<'_, N, C>> {
+        for (history, &x) in self.history.iter_mut().zip(frame) {
+            history[self.head] = x;
+        }
+        self.head = (self.head + 1) % N;
+        self.filled = (self.filled + 1).min(N);
+        self.since_segment += 1;
+        if self.filled < N || self.since_segment < self.hop() {
+            return None;
+        }
+        self.since_segment = 0;
+
+        if self.averaged == self.config.segments {
+            self.density = [[0.0; N]; C];
+            self.averaged = 0;
+        }
+        self.add_segment();
+        self.averaged += 1;
+        (self.averaged == self.config.segments).then_some(Estimate { welch: self })
+    }
+
+    /// Starts over at another sample rate
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.scale *= self.sample_rate / sample_rate;
+        self.sample_rate = sample_rate;
+        self.reset();
+    }
+
+    /// Starts over, e.g. after samples were lost
+    pub fn reset(&mut self) {
+        self.filled = 0;
+        self.since_segment = 0;
+        self.averaged = 0;
+        self.density = [[0.0; N]; C];
+    }
+
+    fn hop(&self) -> usize {
+        N - self.config.overlap
+    }
+
+    /// Adds the spectrum of the last `N` samples of every channel to the density
+    fn add_segment(&mut self) {
+        let mut segment = [Complex::default(); N];
+        for (history, density) in self.history.iter().zip(&mut self.density) {
+            let mean = history.iter().sum::<f32>() / N as f32;
+            for (n, x) in segment.iter_mut().enumerate() {
+                let sample = history[(self.head + n) % N];
+                *x = Complex::new((sample - mean) * self.weights[n], 0.0);
+            }
+            self.fft.process(&mut segment);
+            for (k, density) in density.iter_mut().take(N / 2 + 1).enumerate() {
+                // Only the bins without a negative frequency counterpart aren't doubled
+                let sides = if k == 0 || k == N / 2 { 0.5 } else { 1.0 };
+                *density += sides * self.scale * segment[k].norm_sqr();
+            }
+        }
+    }
+}
+
+/// Spectrum of every channel, averaged over the last segments
+pub struct Estimate<'a, const N: usize, const C: usize> {
+    welch: &'a Welch<N, C>,
+}
+
+impl<'a, const N: usize, const C: usize> Estimate<'a, N, C> {
+    /// Power spectral density of `channel` (units²/Hz), bin `k` being at `k` times the
+    /// [`resolution`](Self::resolution), up to half the sample rate
+    pub fn density(&self, channel: usize) -> &'a [f32] {
+        &self.welch.density[channel][..=N / 2]
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.welch.resolution()
+    }
+
+    /// Power of `channel` (units²) in each band between consecutive `edges` (Hz) into `power`
+    pub fn band_power(&self, channel: usize, edges: &[f32], power: &mut [f32]) {
+        band_power(self.density(channel), self.resolution(), edges, power);
+    }
+}
+
+/// Power in each band between consecutive `edges` (Hz) of a `density` with bins `resolution` apart
+/// into `power`: the bins from the lower edge up to, but not including, the upper edge
+pub fn band_power(density: &[f32], resolution: f32, edges: &[f32], power: &mut [f32]) {
+    for (band, power) in edges.windows(2).zip(power) {
+        let bins = density.iter().enumerate().filter(|(k, _)| {
+            let frequency = *k as f32 * resolution;
+            frequency >= band[0] && frequency < band[1]
+        });
+        *power = bins.map(|(_, density)| density).sum::<f32>() * resolution;
+    }
+}
@@ -0,0 +1,178 @@
+use dsp::{ArtifactConfig, ArtifactDetector, Artifacts, Design, Mains};
+use std::f64::consts::PI;
+
+const SAMPLE_RATE: f32 = 250.0;
+/// Samples in microvolts, clipping at 1 mV
+const CONFIG: ArtifactConfig = ArtifactConfig {
+    segment: 0.5,
+    clip_level: 990.0,
+    flat_range: 0.5,
+    blink_amplitude: 100.0,
+    muscle_rms: 10.0,
+};
+
+/// Deterministic values in [-1, 1)
+fn noise(mut seed: u32) -> impl FnMut() -> f64 {
+    move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        f64::from(seed >> 8) / f64::from(1 << 23) - 1.0
+    }
+}
+
+fn sine(frequency: f64, amplitude: f64, n: usize) -> f64 {
+    amplitude * (2.0 * PI * frequency * n as f64 / f64::from(SAMPLE_RATE)).sin()
+}
+
+/// Alpha waves and some noise over the offset of the electrode, as a clean channel looks (µV)
+fn eeg(mut noise: impl FnMut() -> f64) -> impl FnMut(usize) -> f64 {
+    move |n| 300.0 + sine(10.0, 20.0, n) + sine(21.0, 5.0, n) + 2.0 * noise()
+}
+
+/// Flags of every segment of 8 seconds of `signal`
+fn segments(mut signal: impl FnMut(usize) -> f64) -> Vec<Artifacts> {
+    let mut detector = ArtifactDetector::<1>::new(CONFIG, Mains::Hz50, SAMPLE_RATE);
+    assert_eq!(detector.segment_len(), 125);
+    (0..2_000)
+        .filter_map(|n| detector.push(&[signal(n) as f32]).map(|flags| flags[0]))
+        .collect()
+}
+
+#[test]
+fn clean_eeg_has_no_artifacts() {
+    let flags = segments(eeg(noise(1)));
+    assert_eq!(flags.len(), 16);
+    assert!(flags.iter().all(|flags| flags.is_empty()), "{flags:?}");
+}
+
+#[test]
+fn mains_isnt_muscle_activity() {
+    let mut eeg = eeg(noise(2));
+    let flags = segments(|n| eeg(n) + sine(50.0, 50.0, n));
+    // Past the notch ringing from the mains coming on
+    assert!(flags[1..].iter().all(|flags| flags.is_empty()), "{flags:?}");
+}
+
+#[test]
+fn blink_is_flagged_in_its_segment() {
+    let mut eeg = eeg(noise(3));
+    // A 300 ms deflection of 200 µV starting at 2.1 s, in the fifth segment
+    let flags = segments(|n| {
+        let t = n as f64 / f64::from(SAMPLE_RATE) - 2.1;
+        let blink = if (0.0..0.3).contains(&t) {
+            100.0 * (1.0 - (2.0 * PI * t / 0.3).cos())
+        } else {
+            0.0
+        };
+        eeg(n) + blink
+    });
+    assert_eq!(flags[4], Artifacts::BLINK);
+    for (segment, flags) in flags.iter().enumerate() {
+        assert!(
+            segment == 4 || segment == 5 || flags.is_empty(),
+            "{segment}: {flags}"
+        );
+    }
+}
+
+#[test]
+fn muscle_burst_is_flagged() {
+    let mut eeg = eeg(noise(4));
+    let mut burst = noise(5);
+    // Broadband noise above the EEG bands from 4 s to 5 s
+    let flags = segments(|n| {
+        let muscle = if (1_000..1_250).contains(&n) {
+            sine(70.0, 20.0, n) + sine(90.0, 15.0, n) + 10.0 * burst()
+        } else {
+            0.0
+        };
+        eeg(n) + muscle
+    });
+    assert_eq!(&flags[8..10], [Artifacts::MUSCLE; 2]);
+    assert!(flags[..8].iter().all(|flags| flags.is_empty()), "{flags:?}");
+    assert!(
+        flags[11..].iter().all(|flags| flags.is_empty()),
+        "{flags:?}"
+    );
+}
+
+#[test]
+fn saturated_and_disconnected_channels() {
+    let mut detector = ArtifactDetector::<3>::new(CONFIG, Mains::Hz60, SAMPLE_RATE);
+    let mut eeg = eeg(noise(6));
+    let mut last = None;
+    for n in 0..500 {
+        let sample = eeg(n) as f32;
+        let frame = [sample, 500.0, if n == 300 { -1_000.0 } else { sample }];
+        if let Some(flags) = detector.push(&frame) {
+            last = Some(*flags);
+            if n == 374 {
+                assert!(flags[2].contains(Artifacts::CLIPPING), "{}", flags[2]);
+            }
+        }
+    }
+    assert_eq!(
+        last,
+        Some([Artifacts::NONE, Artifacts::FLATLINE, Artifacts::NONE])
+    );
+}
+
+#[test]
+fn reset_takes_a_new_offset() {
+    let mut detector = ArtifactDetector::<1>::new(CONFIG, Mains::Hz50, SAMPLE_RATE);
+    let mut eeg = eeg(noise(7));
+    for n in 0..500 {
+        detector.push(&[eeg(n) as f32]);
+    }
+    // Reconnected to an electrode with a very different offset
+    detector.reset();
+    let flags: Vec<_> = (0..500)
+        .filter_map(|n| detector.push(&[(eeg(n) - 600.0) as f32]).copied())
+        .collect();
+    assert_eq!(flags, [[Artifacts::NONE]; 4]);
+}
+
+#[test]
+fn artifacts_are_named() {
+    assert_eq!(Artifacts::NONE.to_string(), "clean");
+    let flags = Artifacts::CLIPPING | Artifacts::BLINK;
+    assert_eq!(flags.to_string(), "blink, clipping");
+    assert!(flags.contains(Artifacts::BLINK) && !flags.contains(Artifacts::MUSCLE));
+    assert_eq!(Artifacts::from_bits(0xFF).bits(), 0b1111);
+}
+
+#[test]
+fn default_thresholds_scale_with_the_samples() {
+    let config = ArtifactConfig::new(8_388_607.0, 44.7);
+    assert!((config.blink_amplitude - 4_470.0).abs() < 1e-2);
+    assert!(config.clip_level < 8_388_607.0);
+    assert_eq!(config.segment, 0.5);
+}
+
+#[test]
+fn blink_is_flagged_at_the_highest_sample_rate_in_f64() {
+    let sample_rate = 16_000.0;
+    let mut detector = ArtifactDetector::<1, Design>::new(CONFIG, Mains::Hz50, sample_rate);
+    let mut noise = noise(8);
+    let flags: Vec<_> = (0..4 * 16_000)
+        .filter_map(|n| {
+            let t = n as f64 / f64::from(sample_rate);
+            let alpha = 20.0 * (2.0 * PI * 10.0 * t).sin();
+            // As in `blink_is_flagged_in_its_segment`, from 2.1 s
+            let blink = if (2.1..2.4).contains(&t) {
+                100.0 * (1.0 - (2.0 * PI * (t - 2.1) / 0.3).cos())
+            } else {
+                0.0
+            };
+            let sample = 300.0 + alpha + blink + 2.0 * noise();
+            detector.push(&[sample as f32]).map(|flags| flags[0])
+        })
+        .collect();
+    assert_eq!(flags.len(), 8);
+    assert_eq!(flags[4], Artifacts::BLINK);
+    for (segment, flags) in flags.iter().enumerate() {
+        assert!(
+            segment == 4 || segment == 5 || flags.is_empty(),
+            "{segment}: {flags}"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use dsp::biquad::{BUTTERWORTH_Q, DRIFT_CUTOFF, MAINS_NOTCH_Q};
+use dsp::{Cascade, Coefficients, Design, Mains, Section};
+use std::f64::consts::PI;
+
+/// Gain of the analog prototype of a section with its centre at `f0`, at the frequency `f` it's
+/// warped to by the bilinear transform
+fn prototype_gain(kind: Kind, f0: f64, q: f64, f: f64, sample_rate: f64) -> f64 {
+    let w = (PI * f / sample_rate).tan() / (PI * f0 / sample_rate).tan();
+    let denominator = ((1.0 - w * w).powi(2) + (w / q).powi(2)).sqrt();
+    match kind {
+        Kind::HighPass => w * w / denominator,
+        Kind::LowPass => 1.0 / denominator,
+        Kind::Notch => (1.0 - w * w).abs() / denominator,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    HighPass,
+    LowPass,
+    Notch,
+}
+
+fn design(kind: Kind, f0: f32, q: f32, sample_rate: f32) -> Design {
+    match kind {
+        Kind::HighPass => Design::high_pass(f0, q, sample_rate),
+        Kind::LowPass => Design::low_pass(f0, q, sample_rate),
+        Kind::Notch => Design::notch(f0, q, sample_rate),
+    }
+}
+
+/// Amplitude of the sine at `frequency` coming out of `cascade`, for a sine of amplitude 1 in,
+/// once the filter settled
+fn measured_gain<const S: usize>(
+    cascade: &mut Cascade<Coefficients, S, 1>,
+    frequency: f64,
+    sample_rate: f64,
+) -> f64 {
+    let settle = 10 * sample_rate as usize;
+    let measured = 20 * sample_rate as usize;
+    let (mut sin, mut cos) = (0.0, 0.0);
+    for n in 0..settle + measured {
+        let phase = 2.0 * PI * frequency * n as f64 / sample_rate;
+        let y = cascade.process(0, phase.sin() as f32) as f64;
+        if n >= settle {
+            sin += y * phase.sin();
+            cos += y * phase.cos();
+        }
+    }
+    2.0 * (sin * sin + cos * cos).sqrt() / measured as f64
+}
+
+#[test]
+fn designs_match_their_analog_prototypes() {
+    for sample_rate in [250.0, 500.0, 1000.0, 16000.0] {
+        for (kind, f0, q) in [
+            (Kind::HighPass, DRIFT_CUTOFF, BUTTERWORTH_Q),
+            (Kind::HighPass, 5.0, 2.0),
+            (Kind::LowPass, 40.0, BUTTERWORTH_Q),
+            (Kind::LowPass, 100.0, 0.5),
+            (Kind::Notch, 50.0, MAINS_NOTCH_Q),
+            (Kind::Notch, 60.0, 5.0),
+        ] {
+            let design = design(kind, f0, q, sample_rate);
+            for step in 1..100 {
+                let f = sample_rate / 2.0 * step as f32 / 100.0;
+                let expected =
+                    prototype_gain(kind, f0.into(), q.into(), f.into(), sample_rate.into());
+                let gain = design.gain(f, sample_rate);
+                assert!(
+                    (gain - expected).abs() < 1e-9,
+                    "{kind:?} at {f0} Hz, {sample_rate} Hz: {gain} instead of {expected} at {f} Hz"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn sections_filter_sines_as_designed() {
+    let sample_rate = 250.0;
+    let mut cascade = Cascade::<Coefficients, 2, 1>::new(
+        Design::preprocessing(Mains::Hz50, sample_rate).map(Into::into),
+    );
+    for frequency in [0.2, 0.5, 1.0, 10.0, 40.0, 48.0, 50.0, 52.0, 100.0] {
+        cascade.reset();
+        let expected = cascade.gain(frequency, sample_rate);
+        let gain = measured_gain(&mut cascade, frequency.into(), sample_rate.into());
+        assert!(
+            (gain - expected).abs() < 1e-3,
+            "{gain} instead of {expected} at {frequency} Hz"
+        );
+    }
+}
+
+#[test]
+fn preprocessing_removes_drift_and_mains() {
+    for (mains, sample_rate) in [
+        (Mains::Hz50, 250.0),
+        (Mains::Hz60, 500.0),
+        (Mains::Hz50, 1000.0),
+    ] {
+        let mut cascade = Cascade::<Coefficients, 2, 1>::new(
+            Design::preprocessing(mains, sample_rate).map(Into::into),
+        );
+        let mains_gain = measured_gain(&mut cascade, mains.frequency().into(), sample_rate.into());
+        assert!(
+            mains_gain < 0.01,
+            "{mains_gain} at {mains:?}, {sample_rate} Hz"
+        );
+        // Alpha waves
+        assert!((cascade.gain(10.0, sample_rate) - 1.0).abs() < 0.01);
+        assert!((cascade.gain(DRIFT_CUTOFF, sample_rate) - 0.5f64.sqrt()).abs() < 0.01);
+        assert!(cascade.gain(0.05, sample_rate) < 0.02);
+    }
+}
+
+#[test]
+fn band_pass_is_3_db_down_at_its_edges() {
+    let sample_rate = 250.0;
+    let [high_pass, low_pass] = Design::band_pass(8.0, 12.0, sample_rate);
+    assert!((high_pass.gain(8.0, sample_rate) - 0.5f64.sqrt()).abs() < 1e-7);
+    assert!((low_pass.gain(12.0, sample_rate) - 0.5f64.sqrt()).abs() < 1e-7);
+
+    let cascade = Cascade::<Coefficients, 2, 1>::new([high_pass.into(), low_pass.into()]);
+    assert!(cascade.gain(1.0, sample_rate) < 0.02);
+    assert!(cascade.gain(50.0, sample_rate) < 0.1);
+    let gain = cascade.gain(10.0, sample_rate);
+    let expected = high_pass.gain(10.0, sample_rate) * low_pass.gain(10.0, sample_rate);
+    assert!((gain - expected).abs() < 1e-4);
+}
+
+#[test]
+fn channels_keep_their_own_state() {
+    let sections = Design::preprocessing(Mains::Hz50, 250.0).map(Coefficients::from);
+    let input: Vec<[f32; 3]> = (0..1000)
+        .map(|n| [(n as f32 * 0.3).sin(), 0.0, 1.0])
+        .collect();
+
+    let mut interleaved = Cascade::<_, 2, 3>::new(sections);
+    let mut samples: Vec<f32> = input.concat();
+    interleaved.process_interleaved(&mut samples);
+
+    let mut framed = Cascade::<_, 2, 3>::new(sections);
+    let mut frames = input.clone();
+    for frame in &mut frames {
+        framed.process_frame(frame);
+    }
+    assert_eq!(samples, frames.concat());
+
+    for channel in 0..3 {
+        let mut alone = Cascade::<_, 2, 1>::new(sections);
+        for (frame, filtered) in input.iter().zip(&frames) {
+            assert_eq!(alone.process(0, frame[channel]), filtered[channel]);
+        }
+    }
+    assert!(frames.iter().all(|frame| frame[1] == 0.0));
+}
+
+#[test]
+fn reset_forgets_past_samples() {
+    let mut cascade = Cascade::<Coefficients, 2, 2>::new(
+        Design::preprocessing(Mains::Hz60, 500.0).map(Into::into),
+    );
+    for _ in 0..100 {
+        cascade.process_frame(&mut [1.0, -1.0]);
+    }
+    assert_ne!(cascade.process(0, 0.0), 0.0);
+    cascade.reset();
+    for _ in 0..100 {
+        let mut frame = [0.0; 2];
+        cascade.process_frame(&mut frame);
+        assert_eq!(frame, [0.0; 2]);
+    }
+}
+
+#[test]
+fn identity_changes_nothing() {
+    let section = Coefficients::from(Design::IDENTITY);
+    let mut state = Default::default();
+    for x in [1.0, -3.5, 0.25, 1e6] {
+        assert_eq!(section.process(&mut state, x), x);
+    }
+}
+
+#[test]
+fn drift_cutoff_holds_in_f32_up_to_1_khz() {
+    for sample_rate in [250.0, 500.0, 1000.0] {
+        let section =
+            Coefficients::from(Design::high_pass(DRIFT_CUTOFF, BUTTERWORTH_Q, sample_rate));
+        let gain = section.design().gain(DRIFT_CUTOFF, sample_rate);
+        assert!(
+            (gain - 0.5f64.sqrt()).abs() < 0.01,
+            "{gain} at {sample_rate} Hz"
+        );
+    }
+}
+
+#[test]
+fn drift_cutoff_holds_in_f64_at_every_rate() {
+    for sample_rate in [1000.0, 4000.0, 16000.0] {
+        let mut cascade = Cascade::<_, 1, 1>::new([Design::high_pass(
+            DRIFT_CUTOFF,
+            BUTTERWORTH_Q,
+            sample_rate as f32,
+        )]);
+        let settle = 10 * sample_rate as usize;
+        let measured = 20 * sample_rate as usize;
+        let (mut sin, mut cos) = (0.0, 0.0);
+        for n in 0..settle + measured {
+            let phase = 2.0 * PI * f64::from(DRIFT_CUTOFF) * n as f64 / sample_rate;
+            let y = cascade.process(0, phase.sin());
+            if n >= settle {
+                sin += y * phase.sin();
+                cos += y * phase.cos();
+            }
+        }
+        let gain = 2.0 * (sin * sin + cos * cos).sqrt() / measured as f64;
+        assert!(
+            (gain - 0.5f64.sqrt()).abs() < 0.01,
+            "{gain} at {sample_rate} Hz"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use dsp::fft::{Complex, Fft};
+use std::f64::consts::PI;
+
+/// Deterministic values in [-1, 1)
+fn noise(len: usize, mut seed: u32) -> Vec<f32> {
+    (0..len)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+        })
+        .collect()
+}
+
+/// The transform by its definition, in `f64`
+fn dft(input: &[Complex]) -> Vec<(f64, f64)> {
+    let len = input.len();
+    (0..len)
+        .map(|k| {
+            input
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, x)| {
+                    let angle = -2.0 * PI * (k * n) as f64 / len as f64;
+                    let (sin, cos) = angle.sin_cos();
+                    let (x_re, x_im) = (f64::from(x.re), f64::from(x.im));
+                    (re + x_re * cos - x_im * sin, im + x_re * sin + x_im * cos)
+                })
+        })
+        .collect()
+}
+
+fn check<const N: usize>(seed: u32) {
+    let re = noise(N, seed);
+    let im = noise(N, seed + 1);
+    let input: [Complex; N] = std::array::from_fn(|n| Complex::new(re[n], im[n]));
+    let mut output = input;
+    Fft::<N>::new().process(&mut output);
+    for (k, (x, (re, im))) in output.iter().zip(dft(&input)).enumerate() {
+        // Rounding grows with the number of stages
+        let tolerance = 1e-5 * N as f64;
+        assert!(
+            (f64::from(x.re) - re).abs() < tolerance && (f64::from(x.im) - im).abs() < tolerance,
+            "bin {k} of {N}: {x:?} instead of ({re}, {im})"
+        );
+    }
+}
+
+#[test]
+fn matches_the_definition() {
+    check::<1>(1);
+    check::<2>(2);
+    check::<4>(3);
+    check::<64>(4);
+    check::<256>(5);
+    check::<1024>(6);
+}
+
+#[test]
+fn impulse_has_a_flat_spectrum() {
+    let mut data = [Complex::default(); 32];
+    data[0] = Complex::new(1.0, 0.0);
+    Fft::new().process(&mut data);
+    assert!(data.iter().all(|x| *x == Complex::new(1.0, 0.0)));
+}
+
+#[test]
+fn cosine_lands_in_its_bins() {
+    const N: usize = 128;
+    let mut data: [Complex; N] = std::array::from_fn(|n| {
+        Complex::new((2.0 * PI * 5.0 * n as f64 / N as f64).cos() as f32, 0.0)
+    });
+    Fft::new().process(&mut data);
+    for (k, x) in data.iter().enumerate() {
+        let expected = if k == 5 || k == N - 5 {
+            N as f32 / 2.0
+        } else {
+            0.0
+        };
+        assert!(
+            (x.re - expected).abs() < 1e-3 && x.im.abs() < 1e-3,
+            "bin {k}: {x:?}"
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "power of two")]
+fn length_has_to_be_a_power_of_two() {
+    Fft::<48>::new();
+}
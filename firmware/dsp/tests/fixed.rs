@@ -0,0 +1,109 @@
+use dsp::biquad::{BUTTERWORTH_Q, DRIFT_CUTOFF};
+use dsp::fixed::{FRACTION_BITS, SAMPLE_LIMIT};
+use dsp::{Cascade, Coefficients, Design, FixedCoefficients, Mains, Section};
+use std::f64::consts::PI;
+
+/// Runs `design` in `f64`, as close to exact as the tests need
+fn reference(design: &Design, input: &[f64]) -> Vec<f64> {
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    input
+        .iter()
+        .map(|&x| {
+            let y =
+                design.b0 * x + design.b1 * x1 + design.b2 * x2 - design.a1 * y1 - design.a2 * y2;
+            (x2, x1, y2, y1) = (x1, x, y1, y);
+            y
+        })
+        .collect()
+}
+
+/// A few seconds of what the front-end could see: alpha waves, mains and an electrode offset
+fn eeg(sample_rate: f64, seconds: usize) -> Vec<i32> {
+    (0..seconds * sample_rate as usize)
+        .map(|n| {
+            let t = n as f64 / sample_rate;
+            let alpha = 200_000.0 * (2.0 * PI * 10.0 * t).sin();
+            let mains = 1_000_000.0 * (2.0 * PI * 50.0 * t).sin();
+            (alpha + mains + 3_000_000.0) as i32
+        })
+        .collect()
+}
+
+#[test]
+fn coefficients_round_to_nearest() {
+    let designs = [
+        Design::IDENTITY,
+        Design::high_pass(DRIFT_CUTOFF, BUTTERWORTH_Q, 16000.0),
+        Design::low_pass(40.0, BUTTERWORTH_Q, 250.0),
+        Design::notch(60.0, 20.0, 500.0),
+    ];
+    for design in designs {
+        let rounded = FixedCoefficients::from(design).design();
+        let lsb = 0.5f64.powi(FRACTION_BITS as i32);
+        for (c, r) in [
+            (design.b0, rounded.b0),
+            (design.b1, rounded.b1),
+            (design.b2, rounded.b2),
+            (design.a1, rounded.a1),
+            (design.a2, rounded.a2),
+        ] {
+            assert!((c - r).abs() <= lsb / 2.0, "{c} rounded to {r}");
+        }
+    }
+}
+
+#[test]
+fn fixed_point_tracks_the_design() {
+    let sample_rate = 250.0;
+    let designs = Design::preprocessing(Mains::Hz50, sample_rate);
+    let input = eeg(sample_rate.into(), 20);
+
+    let mut cascade = Cascade::<_, 2, 1>::new(designs.map(FixedCoefficients::from));
+    let output: Vec<i32> = input.iter().map(|&x| cascade.process(0, x)).collect();
+
+    // Against the rounded coefficients, to only see the rounding of the samples
+    let rounded = cascade.sections().map(|section| section.design());
+    let expected = input.iter().map(|&x| f64::from(x)).collect::<Vec<_>>();
+    let expected = reference(&rounded[1], &reference(&rounded[0], &expected));
+    for (n, (y, e)) in output.iter().zip(&expected).enumerate() {
+        assert!((f64::from(*y) - e).abs() < 2.0, "{y} instead of {e} at {n}");
+    }
+}
+
+#[test]
+fn offset_is_removed_exactly() {
+    let sample_rate = 250.0;
+    let mut cascade = Cascade::<_, 2, 1>::new(
+        Design::preprocessing(Mains::Hz50, sample_rate).map(FixedCoefficients::from),
+    );
+    let offset = (1 << 23) - 1;
+    let output: Vec<i32> = (0..30 * 250).map(|_| cascade.process(0, offset)).collect();
+    let settled = &output[output.len() - 250..];
+    assert!(settled.iter().all(|y| y.abs() <= 1), "{settled:?}");
+}
+
+#[test]
+fn keeps_low_cutoffs_that_f32_loses() {
+    let sample_rate = 16000.0;
+    let design = Design::high_pass(DRIFT_CUTOFF, BUTTERWORTH_Q, sample_rate);
+    let expected = 0.5f64.sqrt();
+    let fixed = FixedCoefficients::from(design)
+        .design()
+        .gain(DRIFT_CUTOFF, sample_rate);
+    assert!((fixed - expected).abs() < 0.01, "{fixed}");
+    let float = Coefficients::from(design)
+        .design()
+        .gain(DRIFT_CUTOFF, sample_rate);
+    assert!((float - expected).abs() > 0.1, "{float}");
+}
+
+#[test]
+fn saturates_instead_of_overflowing() {
+    let mut cascade =
+        Cascade::<_, 2, 1>::new(Design::band_pass(1.0, 40.0, 250.0).map(FixedCoefficients::from));
+    for n in 0..10_000 {
+        let x = if n / 7 % 2 == 0 { i32::MAX } else { i32::MIN };
+        let y = cascade.process(0, x);
+        assert!((-SAMPLE_LIMIT..=SAMPLE_LIMIT).contains(&y));
+    }
+}
@@ -0,0 +1,70 @@
+use dsp::lock_in::LockIn;
+use std::f64::consts::PI;
+
+/// Deterministic values in [-1, 1)
+fn noise(mut seed: u32) -> impl FnMut() -> f64 {
+    move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        f64::from(seed >> 8) / f64::from(1 << 23) - 1.0
+    }
+}
+
+/// Amplitudes of the first `count` measurements of `signal`
+fn measure(lock_in: &mut LockIn, count: usize, mut signal: impl FnMut(usize) -> f64) -> Vec<f32> {
+    (0..)
+        .filter_map(|n| lock_in.push(signal(n) as f32))
+        .take(count)
+        .collect()
+}
+
+#[test]
+fn tone_at_a_quarter_of_the_sample_rate() {
+    // As the front-end drives its test current
+    let mut lock_in = LockIn::new(62.5, 250.0, 50);
+    assert_eq!(lock_in.measurement_len(), 200);
+    let amplitudes = measure(&mut lock_in, 3, |n| {
+        300_000.0 + 120.0 * (PI / 2.0 * n as f64 + 0.3).sin()
+    });
+    // The offset leaves the samples only exact to 1/32 in `f32`
+    for amplitude in amplitudes {
+        assert!((amplitude - 120.0).abs() < 0.05, "{amplitude}");
+    }
+}
+
+#[test]
+fn other_frequencies_and_noise_average_out() {
+    let mut lock_in = LockIn::new(31.2, 1_000.0, 100);
+    let mut noise = noise(1);
+    let amplitudes = measure(&mut lock_in, 4, |n| {
+        let t = n as f64 / 1_000.0;
+        let tone = 5.0 * (2.0 * PI * 31.2 * t).cos();
+        let eeg = 40.0 * (2.0 * PI * 10.0 * t).sin() + 10.0 * (2.0 * PI * 50.0 * t).sin();
+        -2_000.0 + tone + eeg + 20.0 * noise()
+    });
+    for amplitude in amplitudes {
+        assert!((amplitude / 5.0 - 1.0).abs() < 0.1, "{amplitude}");
+    }
+}
+
+#[test]
+fn no_tone_reads_as_next_to_nothing() {
+    let mut lock_in = LockIn::new(62.5, 250.0, 50);
+    let mut noise = noise(2);
+    let amplitudes = measure(&mut lock_in, 2, |_| 1_000.0 + noise());
+    assert!(
+        amplitudes.iter().all(|&amplitude| amplitude < 0.3),
+        "{amplitudes:?}"
+    );
+}
+
+#[test]
+fn reset_drops_the_measurement_so_far() {
+    let mut lock_in = LockIn::new(62.5, 250.0, 50);
+    let tone = |n: usize| 10.0 * (PI / 2.0 * n as f64).sin();
+    for n in 0..150 {
+        assert_eq!(lock_in.push(tone(n) as f32 * 100.0), None);
+    }
+    lock_in.reset();
+    let amplitude = measure(&mut lock_in, 1, tone)[0];
+    assert!((amplitude - 10.0).abs() < 1e-3, "{amplitude}");
+}
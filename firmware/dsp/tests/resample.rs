@@ -0,0 +1,108 @@
+use dsp::Resampler;
+use std::f64::consts::PI;
+
+/// Every output of feeding `signal` for `count` inputs
+fn resample<const TAPS: usize>(
+    resampler: &mut Resampler<TAPS, 1>,
+    count: usize,
+    signal: impl Fn(usize) -> f64,
+) -> Vec<f32> {
+    let mut outputs = Vec::new();
+    for n in 0..count {
+        resampler.push(&[signal(n) as f32], |&[y]| outputs.push(y));
+    }
+    outputs
+}
+
+/// Peak of what is left once the filter has settled
+fn peak(outputs: &[f32], settled: usize) -> f32 {
+    outputs[settled..]
+        .iter()
+        .fold(0.0, |peak, y| y.abs().max(peak))
+}
+
+fn tone(frequency: f64, sample_rate: f64) -> impl Fn(usize) -> f64 {
+    move |n| (2.0 * PI * frequency * n as f64 / sample_rate).sin()
+}
+
+#[test]
+fn decimating_keeps_one_sample_in_factor() {
+    let mut decimator = Resampler::<64, 1>::decimator(4);
+    assert_eq!(decimator.ratio(), (1, 4));
+    assert_eq!(decimator.output_rate(1_000.0), 250.0);
+    assert_eq!(resample(&mut decimator, 400, |_| 0.0).len(), 100);
+}
+
+#[test]
+fn pass_band_is_kept_and_offsets_pass_unchanged() {
+    let mut decimator = Resampler::<64, 1>::decimator(2);
+    let outputs = resample(&mut decimator, 2_000, |n| {
+        100.0 + 10.0 * tone(10.0, 500.0)(n)
+    });
+    let settled = &outputs[64..];
+    let max = settled.iter().fold(f32::MIN, |max, &y| y.max(max));
+    let min = settled.iter().fold(f32::MAX, |min, &y| y.min(min));
+    assert!((max - 110.0).abs() < 0.1, "{max}");
+    assert!((min - 90.0).abs() < 0.1, "{min}");
+}
+
+#[test]
+fn what_would_alias_is_filtered_out() {
+    // 200 Hz would fold back to 50 Hz at 250 Hz
+    let mut decimator = Resampler::<64, 1>::decimator(2);
+    let outputs = resample(&mut decimator, 2_000, tone(200.0, 500.0));
+    let peak = peak(&outputs, 64);
+    assert!(peak < 1e-3, "{peak}");
+}
+
+#[test]
+fn rational_ratios_are_reduced() {
+    let mut resampler = Resampler::<96, 1>::new(4, 6);
+    assert_eq!(resampler.ratio(), (2, 3));
+    assert_eq!(resampler.output_rate(375.0), 250.0);
+    assert_eq!(resampler.delay(), 95.0 / 4.0);
+
+    let outputs = resample(&mut resampler, 3_000, tone(20.0, 375.0));
+    assert_eq!(outputs.len(), 2_000);
+    // The output is the input delayed, at the new rate
+    for (m, &y) in outputs.iter().enumerate().skip(100) {
+        let t = m as f64 / 250.0 - f64::from(resampler.delay()) / 375.0;
+        let expected = (2.0 * PI * 20.0 * t).sin();
+        assert!(
+            (f64::from(y) - expected).abs() < 0.01,
+            "{m}: {y} {expected}"
+        );
+    }
+}
+
+#[test]
+fn upsampling_interpolates_between_inputs() {
+    let mut resampler = Resampler::<64, 1>::new(2, 1);
+    let outputs = resample(&mut resampler, 500, tone(10.0, 250.0));
+    assert_eq!(outputs.len(), 1_000);
+    let peak = peak(&outputs, 100);
+    assert!((peak - 1.0).abs() < 0.01, "{peak}");
+}
+
+#[test]
+fn channels_are_filtered_separately() {
+    let mut decimator = Resampler::<64, 2>::decimator(2);
+    let mut outputs = Vec::new();
+    for n in 0..1_000 {
+        let frame = [5.0, tone(200.0, 500.0)(n) as f32];
+        decimator.push(&frame, |&output| outputs.push(output));
+    }
+    for [constant, aliased] in &outputs[64..] {
+        assert!((constant - 5.0).abs() < 1e-3, "{constant}");
+        assert!(aliased.abs() < 1e-3, "{aliased}");
+    }
+}
+
+#[test]
+fn reset_forgets_past_samples() {
+    let mut decimator = Resampler::<32, 1>::decimator(2);
+    resample(&mut decimator, 99, |_| 1_000.0);
+    decimator.reset();
+    let outputs = resample(&mut decimator, 10, |_| 0.0);
+    assert_eq!(outputs, [0.0; 5]);
+}
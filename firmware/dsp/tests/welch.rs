@@ -0,0 +1,150 @@
+use dsp::welch::{band_power, EEG_BANDS};
+use dsp::{Welch, WelchConfig, Window};
+use std::f64::consts::PI;
+
+const SAMPLE_RATE: f32 = 250.0;
+const HANN: WelchConfig = WelchConfig {
+    window: Window::Hann,
+    overlap: 128,
+    segments: 4,
+};
+
+/// Sines of `(frequency, amplitude)` over an offset, as the electrodes would see them
+fn signal(sines: &[(f64, f64)], n: usize) -> f32 {
+    let t = n as f64 / f64::from(SAMPLE_RATE);
+    let sum: f64 = sines
+        .iter()
+        .map(|(frequency, amplitude)| amplitude * (2.0 * PI * frequency * t).sin())
+        .sum();
+    (sum + 10_000.0) as f32
+}
+
+/// Deterministic values in [-1, 1)
+fn noise(mut seed: u32) -> impl FnMut() -> f32 {
+    move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+}
+
+/// Powers of the EEG bands in the first estimate of `channel` for `input`
+fn first_bands<const C: usize>(
+    welch: &mut Welch<256, C>,
+    channel: usize,
+    mut input: impl FnMut(usize) -> [f32; C],
+) -> [f32; 5] {
+    let mut power = [0.0; 5];
+    for n in 0.. {
+        if let Some(estimate) = welch.push(&input(n)) {
+            estimate.band_power(channel, &EEG_BANDS, &mut power);
+            return power;
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn sine_power_lands_in_its_band() {
+    let sines = [(10.0, 100.0), (20.0, 10.0)];
+    for window in [Window::Hann, Window::Hamming, Window::Blackman] {
+        let config = WelchConfig { window, ..HANN };
+        let mut welch = Welch::<256, 1>::new(config, SAMPLE_RATE);
+        let [delta, theta, alpha, beta, gamma] =
+            first_bands(&mut welch, 0, |n| [signal(&sines, n)]);
+
+        // A sine of amplitude A has a power of A² / 2
+        assert!((alpha / 5_000.0 - 1.0).abs() < 0.01, "{window:?}: {alpha}");
+        assert!((beta / 50.0 - 1.0).abs() < 0.02, "{window:?}: {beta}");
+        // Some leakage from the alpha sine, but none of the offset
+        for power in [delta, theta, gamma] {
+            assert!(power < 50.0, "{window:?}: {power}");
+        }
+    }
+}
+
+#[test]
+fn rectangular_window_is_exact_at_a_bin() {
+    let config = WelchConfig {
+        window: Window::Rectangular,
+        overlap: 0,
+        segments: 1,
+    };
+    let mut welch = Welch::<256, 1>::new(config, SAMPLE_RATE);
+    // Bin 10, a whole number of periods per segment
+    let frequency = 10.0 * f64::from(SAMPLE_RATE) / 256.0;
+    let [_, _, alpha, _, _] = first_bands(&mut welch, 0, |n| [signal(&[(frequency, 2.0)], n)]);
+    assert!((alpha - 2.0).abs() < 1e-3, "{alpha}");
+}
+
+#[test]
+fn white_noise_power_is_its_variance() {
+    let config = WelchConfig {
+        segments: 64,
+        ..HANN
+    };
+    let mut welch = Welch::<256, 1>::new(config, SAMPLE_RATE);
+    let mut noise = noise(7);
+    let estimate = loop {
+        if let Some(estimate) = welch.push(&[100.0 * noise()]) {
+            break estimate;
+        }
+    };
+    let density = estimate.density(0);
+    assert_eq!(density.len(), 129);
+    let total: f32 = density.iter().sum::<f32>() * estimate.resolution();
+    // Uniform over [-100, 100)
+    let variance = 100.0 * 100.0 / 3.0;
+    assert!((total / variance - 1.0).abs() < 0.05, "{total}");
+    // Flat: every band gets its share
+    let mut power = [0.0; 2];
+    estimate.band_power(0, &[0.0, 62.5, 125.0], &mut power);
+    assert!((power[0] / power[1] - 1.0).abs() < 0.1, "{power:?}");
+}
+
+#[test]
+fn estimates_come_every_segments_times_hop() {
+    let mut welch = Welch::<256, 1>::new(HANN, SAMPLE_RATE);
+    assert_eq!(welch.span(), 256 + 3 * 128);
+    let ready: Vec<usize> = (1..=2_000)
+        .filter(|_| welch.push(&[0.0]).is_some())
+        .collect();
+    assert_eq!(ready, [640, 1_152, 1_664]);
+
+    welch.reset();
+    let ready = (1..=640).filter(|_| welch.push(&[0.0]).is_some()).count();
+    assert_eq!(ready, 1);
+}
+
+#[test]
+fn channels_have_their_own_spectrum() {
+    let mut welch = Welch::<256, 3>::new(HANN, SAMPLE_RATE);
+    let input = |n| [signal(&[(10.0, 100.0)], n), 3.0, signal(&[(20.0, 10.0)], n)];
+    let alpha = first_bands(&mut welch, 0, input)[2];
+    assert!((alpha / 5_000.0 - 1.0).abs() < 0.01, "{alpha}");
+    welch.reset();
+    assert_eq!(first_bands(&mut welch, 1, input), [0.0; 5]);
+    welch.reset();
+    let beta = first_bands(&mut welch, 2, input)[3];
+    assert!((beta / 50.0 - 1.0).abs() < 0.02, "{beta}");
+}
+
+#[test]
+fn band_includes_its_lower_edge_only() {
+    let density = [1.0, 2.0, 4.0, 8.0];
+    let mut power = [0.0; 2];
+    band_power(&density, 0.5, &[0.5, 1.0, 1.5], &mut power);
+    assert_eq!(power, [1.0, 2.0]);
+}
+
+#[test]
+fn windows_are_periodic() {
+    for window in [Window::Hann, Window::Hamming, Window::Blackman] {
+        for n in 1..32 {
+            assert!((window.weight(n, 64) - window.weight(64 - n, 64)).abs() < 1e-6);
+        }
+        assert!((window.weight(32, 64) - 1.0).abs() < 1e-6, "{window:?}");
+    }
+    assert_eq!(Window::Hann.weight(0, 64), 0.0);
+    assert!(Window::Blackman.weight(0, 64).abs() < 1e-6);
+    assert_eq!(Window::Rectangular.weight(5, 64), 1.0);
+}
@@ -9,7 +9,11 @@ MEMORY
 }
 SECTIONS {
     .shared_ram (NOLOAD) : {
+        /* Checked by both cores at boot, see `common::shared_ram` */
+        KEEP(*(.shared_ram.header))
         *(.shared_ram)
         *(.shared_ram.*)
     } > SHARED_RAM
 }
+
+ASSERT(SHARED_RAM_HEADER == ORIGIN(SHARED_RAM), "the shared RAM header has to be at the start of SHARED_RAM")
@@ -1,13 +1,20 @@
 #![no_std]
 #![no_main]
 
-use common::ring_buffer::RingBufferProducer;
+use common::block_buffer::BlockReader;
+use common::clock::Clock;
+use common::ipc::{Message, Payload, SampleBlock, SAMPLE_BLOCK_COUNT};
+use common::log::{Core, LogSink};
+use common::ring_buffer::{RingBufferConsumer, RingBufferProducer};
+use common::rpc::{self, Endpoint, QueueTransport, RemoteError, Reply, Request, RpcError};
+use core::cell::Cell;
 use core::{panic::PanicInfo, sync::atomic::compiler_fence};
 use defmt::println;
 use defmt_rtt as _;
 use embassy_executor::task;
 use embassy_executor::Spawner;
 use embassy_futures::join::join;
+use embassy_futures::select::{select, select4, Either, Either4};
 use embassy_nrf::bind_interrupts;
 use embassy_nrf::config::Config;
 use embassy_nrf::gpio::Output;
@@ -20,8 +27,10 @@ use embassy_nrf::peripherals::RNG;
 use embassy_nrf::rng::InterruptHandler as RngInterruptHandler;
 use embassy_nrf::rng::Rng;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use embassy_sync::watch;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_time::Duration;
+use embassy_time::Instant;
+use embassy_time::Ticker;
 use embassy_time::Timer;
 use nrf_sdc as sdc;
 use nrf_sdc::mpsl::{
@@ -30,19 +39,55 @@ use nrf_sdc::mpsl::{
 use nrf_sdc::mpsl::{MultiprotocolServiceLayer, Peripherals};
 use nrf_sdc::Builder;
 use nrf_sdc::SoftdeviceController;
+use proto::framing::{self, MAX_FRAME_LEN};
+use session::{ForwardError, Response, Session, MAX_COMMAND_LEN, MAX_REPLY_LEN};
+use signal::IpcSignal;
 use static_cell::StaticCell;
 use trouble_host::advertise;
 use trouble_host::prelude::AdStructure;
 use trouble_host::prelude::Advertisement;
 use trouble_host::prelude::AdvertisementParameters;
+use trouble_host::prelude::Connection;
 use trouble_host::prelude::DefaultPacketPool;
+use trouble_host::prelude::L2capChannel;
 use trouble_host::prelude::BR_EDR_NOT_SUPPORTED;
 use trouble_host::prelude::LE_GENERAL_DISCOVERABLE;
 use trouble_host::Address;
+use trouble_host::BleHostError;
+use trouble_host::Controller;
 use trouble_host::Host;
 use trouble_host::HostResources;
-
-static IPC_0_WATCH: watch::Watch<CriticalSectionRawMutex, (), 1> = watch::Watch::new();
+use trouble_host::Stack;
+
+mod session;
+mod signal;
+
+/// State of the link with the host, as reported to the application core
+static LINK: Mutex<CriticalSectionRawMutex, Cell<rpc::Link>> = Mutex::new(Cell::new(rpc::Link {
+    connected: false,
+    rssi: None,
+}));
+/// Charge of the battery when the application core was last asked
+static BATTERY: Mutex<CriticalSectionRawMutex, Cell<Option<rpc::Battery>>> =
+    Mutex::new(Cell::new(None));
+
+/// How often the signal strength of the connection is measured, while the host talks to us
+const RSSI_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the application core is asked for the charge of the battery
+const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// How long the application core has to answer a request
+const RPC_TIMEOUT: Duration = Duration::from_millis(100);
+
+type RpcHandler = fn(Request) -> Result<Reply, RemoteError>;
+type Rpc = Endpoint<QueueTransport<'static, IpcSignal, IpcSignal>, RpcHandler>;
+
+/// Sample blocks from the application core
+type Blocks = BlockReader<'static, SampleBlock, SAMPLE_BLOCK_COUNT, IpcSignal>;
+
+/// Log records waiting to be forwarded to the host
+static LOG: LogSink<16> = LogSink::new(Core::Net);
+/// Device time, set by the host
+static CLOCK: Clock = Clock::new();
 
 #[embassy_executor::task]
 async fn led_blinker(ipc: ipc::Event<'static>) {
@@ -65,10 +110,27 @@ async fn main(spawner: Spawner) {
 
     let Ipc {
         event0: mut start_ipc,
+        event1: mut from_app_ipc,
+        event2: mut to_app_ipc,
+        event3: mut blocks_ipc,
+        event4: mut rpc_from_app_ipc,
+        event5: mut rpc_to_app_ipc,
         ..
     } = Ipc::new(p.IPC, Irqs);
 
     start_ipc.configure_trigger([IpcChannel::Channel0]);
+    from_app_ipc.configure_wait([IpcChannel::Channel1]);
+    to_app_ipc.configure_trigger([IpcChannel::Channel2]);
+    blocks_ipc.configure_wait([IpcChannel::Channel3]);
+    rpc_from_app_ipc.configure_wait([IpcChannel::Channel4]);
+    rpc_to_app_ipc.configure_trigger([IpcChannel::Channel5]);
+
+    // Has to happen before signalling that we started, which is when the application core looks
+    // at the result
+    let layout = common::SHARED_RAM_HEADER.check_on_net_core();
+    if let Err(error) = layout {
+        defmt::error!("Not using the queues to the application core: {}", error);
+    }
 
     defmt::info!("Triggering start no app core");
     defmt::unwrap!(spawner.spawn(led_blinker(start_ipc)));
@@ -114,14 +176,34 @@ async fn main(spawner: Spawner) {
         .and_then(Builder::support_ext_adv)
         .and_then(|b| b.build(sdc_p, rng, mpsl, sdc_mem)));
 
-    defmt::info!("Getting sender");
-    // Safety: This is the only place in the codebase where this is called
-    let producer = unsafe { common::BLE_QUEUE.get_sender_with_signal(IPC_0_WATCH.sender()) };
+    let (to_app, from_app, blocks) = if layout.is_ok() {
+        defmt::info!("Getting inter-core queues");
+        let to_app = defmt::unwrap!(common::IPC_CHANNELS
+            .net_to_app
+            .take_sender_with_signal(Core::Net, IpcSignal(to_app_ipc)));
+        let from_app = defmt::unwrap!(common::IPC_CHANNELS
+            .app_to_net
+            .take_receiver_with_signal(Core::Net, IpcSignal(from_app_ipc)));
+        let blocks =
+            defmt::unwrap!(common::SAMPLE_BLOCKS.take_reader(Core::Net, IpcSignal(blocks_ipc)));
+        let transport = QueueTransport {
+            sender: defmt::unwrap!(common::IPC_CHANNELS
+                .rpc_net_to_app
+                .take_sender_with_signal(Core::Net, IpcSignal(rpc_to_app_ipc))),
+            receiver: defmt::unwrap!(common::IPC_CHANNELS
+                .rpc_app_to_net
+                .take_receiver_with_signal(Core::Net, IpcSignal(rpc_from_app_ipc))),
+        };
+        spawner.must_spawn(rpc_task(Endpoint::new(transport, answer_rpc as RpcHandler)));
+        (Some(to_app), Some(from_app), Some(blocks))
+    } else {
+        (None, None, None)
+    };
 
     defmt::info!("Spawning tasks");
     // Spawn the MPSL and SDC tasks
     spawner.must_spawn(mpsl_task(mpsl));
-    spawner.must_spawn(sdc_task(sdc, producer));
+    spawner.must_spawn(sdc_task(sdc, to_app, from_app, blocks));
 }
 
 #[embassy_executor::task]
@@ -132,7 +214,9 @@ async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) -> ! {
 #[embassy_executor::task]
 async fn sdc_task(
     sdc: SoftdeviceController<'static>,
-    _producer: RingBufferProducer<'static, u64, 1>,
+    mut to_app: Option<RingBufferProducer<'static, Message, IpcSignal>>,
+    mut from_app: Option<RingBufferConsumer<'static, Message, IpcSignal>>,
+    mut blocks: Option<Blocks>,
 ) -> ! {
     defmt::info!("In SDC task");
 
@@ -195,7 +279,7 @@ async fn sdc_task(
             let channel = trouble_host::l2cap::L2capChannel::accept(
                 &stack,
                 &connection,
-                &[0, 1],
+                &[framing::PSM],
                 &l2cap_config,
             );
 
@@ -213,11 +297,141 @@ async fn sdc_task(
                 }
             };
 
-            let mut packet_buffer: [u8; 512] = [0; 512];
-            while let Ok(count) = channel.receive(&stack, &mut packet_buffer).await {
-                let data = &packet_buffer[..count];
-                defmt::info!("Got data {:?}", data);
+            set_link(Some(&connection), &stack).await;
+            let mut rssi_measured_at = Instant::now();
+
+            let mut session = Session::default();
+            let mut decoder = framing::Decoder::<MAX_COMMAND_LEN>::new();
+            let mut encoder = framing::Encoder::new();
+            let mut packet_buffer = [0; MAX_FRAME_LEN];
+            let mut reply_buffer = [0; MAX_REPLY_LEN];
+
+            'connection: loop {
+                let received = select4(
+                    channel.receive(&stack, &mut packet_buffer),
+                    LOG.receive(),
+                    async {
+                        match from_app.as_mut() {
+                            Some(from_app) => from_app.recv().await,
+                            None => core::future::pending().await,
+                        }
+                    },
+                    async {
+                        match blocks.as_mut() {
+                            Some(blocks) => blocks.ready().await,
+                            None => core::future::pending().await,
+                        }
+                    },
+                )
+                .await;
+                let count = match received {
+                    Either4::First(Ok(count)) => count,
+                    Either4::First(Err(_)) => break 'connection,
+                    Either4::Second(record) => {
+                        let dropped = LOG.take_dropped();
+                        if dropped > 0 {
+                            common::log!(LOG, Warn, "Dropped {} log records", dropped);
+                        }
+                        let Some(len) = session.log_record(&record, &mut reply_buffer) else {
+                            continue;
+                        };
+                        if send_message(&mut channel, &stack, &mut encoder, &reply_buffer[..len])
+                            .await
+                            .is_err()
+                        {
+                            break 'connection;
+                        }
+                        continue;
+                    }
+                    Either4::Third(Message::ToHost(payload)) => {
+                        if send_message(&mut channel, &stack, &mut encoder, payload.as_bytes())
+                            .await
+                            .is_err()
+                        {
+                            break 'connection;
+                        }
+                        continue;
+                    }
+                    Either4::Third(message) => {
+                        defmt::warn!("Unexpected message from the application core: {}", message);
+                        continue;
+                    }
+                    Either4::Fourth(()) => {
+                        let Some(block) = blocks.as_mut().and_then(|blocks| blocks.try_acquire())
+                        else {
+                            continue;
+                        };
+                        let len = session.sample_frame(&block, &mut reply_buffer);
+                        // Handed back before sending, which can take a few connection intervals
+                        drop(block);
+                        let Some(len) = len else {
+                            continue;
+                        };
+                        if send_message(&mut channel, &stack, &mut encoder, &reply_buffer[..len])
+                            .await
+                            .is_err()
+                        {
+                            break 'connection;
+                        }
+                        continue;
+                    }
+                };
+
+                let received_at = CLOCK.now();
+                let mut data = &packet_buffer[..count];
+                while let Some(message) = decoder.decode(&mut data) {
+                    let len = match session.handle(message, received_at, &mut reply_buffer) {
+                        Response::Reply(len) => len,
+                        Response::None => continue,
+                        Response::Forward => {
+                            // The queue is left on its default policy, so that a full queue
+                            // gets the host a busy error rather than stalling the link
+                            let error = match (to_app.as_mut(), Payload::new(message)) {
+                                (None, _) => ForwardError::Unavailable,
+                                (Some(_), None) => ForwardError::TooLong,
+                                (Some(to_app), Some(payload)) => {
+                                    match to_app.send(Message::Command(payload)).await {
+                                        Ok(()) => continue,
+                                        Err(_) => ForwardError::Full,
+                                    }
+                                }
+                            };
+                            common::log!(LOG, Warn, "Couldn't forward a command");
+                            match session.forward_failed(message, error, &mut reply_buffer) {
+                                Some(len) => len,
+                                None => continue,
+                            }
+                        }
+                        Response::Close => {
+                            channel.disconnect();
+                            break 'connection;
+                        }
+                    };
+                    if send_message(&mut channel, &stack, &mut encoder, &reply_buffer[..len])
+                        .await
+                        .is_err()
+                    {
+                        break 'connection;
+                    }
+                }
+                // After replying, measuring takes a round trip to the controller
+                if rssi_measured_at.elapsed() >= RSSI_INTERVAL {
+                    set_link(Some(&connection), &stack).await;
+                    rssi_measured_at = Instant::now();
+                }
             }
+            set_link(None, &stack).await;
+            // The next host has to ask for log records again
+            LOG.set_level(None);
+            let stats = decoder.stats();
+            common::log!(
+                LOG,
+                Info,
+                "Skipped {} bytes, {} corrupted frames and {} dropped messages",
+                stats.skipped_bytes,
+                stats.corrupted_frames,
+                stats.dropped_messages
+            );
             defmt::info!("Connection closed");
         }
     })
@@ -230,14 +444,63 @@ async fn sdc_task(
     }
 }
 
-#[task]
-async fn ipc_handler_task(
-    mut event: embassy_nrf::ipc::Event<'static>,
-    sender: watch::Sender<'static, CriticalSectionRawMutex, (), 1>,
+/// Splits `message` into frames and sends them over `channel`
+async fn send_message<C: Controller>(
+    channel: &mut L2capChannel<'_, DefaultPacketPool>,
+    stack: &Stack<'_, C, DefaultPacketPool>,
+    encoder: &mut framing::Encoder,
+    message: &[u8],
+) -> Result<(), BleHostError<C::Error>> {
+    let mut frame_buffer = [0; MAX_FRAME_LEN];
+    let mut frames = encoder.frames(message, MAX_FRAME_LEN);
+    while let Some(frame_len) = frames.next_into(&mut frame_buffer) {
+        channel.send(stack, &frame_buffer[..frame_len]).await?;
+    }
+    Ok(())
+}
+
+/// Records whether `connection` is up and its signal strength, for the application core
+async fn set_link<C: Controller>(
+    connection: Option<&Connection<'_, DefaultPacketPool>>,
+    stack: &Stack<'_, C, DefaultPacketPool>,
 ) {
+    let link = match connection {
+        Some(connection) => rpc::Link {
+            connected: true,
+            rssi: connection.rssi(stack).await.ok(),
+        },
+        None => rpc::Link::default(),
+    };
+    LINK.lock(|cell| cell.set(link));
+}
+
+/// Answers the requests of the application core
+fn answer_rpc(request: Request) -> Result<Reply, RemoteError> {
+    match request {
+        Request::Link => Ok(Reply::Link(LINK.lock(Cell::get))),
+        Request::Time => Ok(Reply::Time(CLOCK.now())),
+        Request::Battery => Err(RemoteError::Unsupported),
+    }
+}
+
+/// Answers the requests of the application core, and asks it for the charge of the battery every
+/// [`BATTERY_POLL_INTERVAL`]
+#[task]
+async fn rpc_task(mut rpc: Rpc) -> ! {
+    let mut ticker = Ticker::every(BATTERY_POLL_INTERVAL);
     loop {
-        event.wait().await;
-        sender.send(());
+        if let Either::First(()) = select(rpc.serve(), ticker.next()).await {
+            continue;
+        }
+        let battery = match rpc.battery(RPC_TIMEOUT).await {
+            Ok(battery) => Some(battery),
+            Err(RpcError::Remote(RemoteError::Unavailable)) => None,
+            Err(error) => {
+                common::log!(LOG, Warn, "Couldn't get the battery charge: {:?}", error);
+                None
+            }
+        };
+        BATTERY.lock(|cell| cell.set(battery));
     }
 }
 
@@ -0,0 +1,314 @@
+use common::ipc::{build_ack, build_error, SampleBlock};
+use common::log::Record;
+use common::ring_buffer::QueueStats;
+use common::{CHANNEL_COUNT, HARDWARE_REV, IPC_CHANNELS, SAMPLE_RATES, SHARED_RAM_HEADER};
+use core::cell::Cell;
+use embassy_time::Instant;
+use proto::capnp;
+use proto::from_edge_capnp::{from_edge, queue_stats, ErrorCode};
+use proto::no_alloc::{self, ScratchBuffer};
+use proto::to_edge_capnp::to_edge;
+use proto::{Compatibility, ProtocolVersion};
+
+/// Largest command we accept from the host
+pub const MAX_COMMAND_LEN: usize = 1024;
+/// Largest reply we send back to the host
+pub const MAX_REPLY_LEN: usize = 1024;
+
+/// What to do after handling a message from the host
+pub enum Response {
+    /// Send back the first `len` bytes of the output buffer
+    Reply(usize),
+    /// There is nothing to send back
+    None,
+    /// The command is handled by the application core, the message should be forwarded to it
+    Forward,
+    /// The host broke the protocol, the connection should be closed
+    Close,
+}
+
+/// Why a command couldn't be forwarded to the application core
+pub enum ForwardError {
+    /// The queue to the application core is full
+    Full,
+    /// The cores disagree on the layout of shared RAM, so there is no queue
+    Unavailable,
+    /// Longer than a message between the cores can be, see [`common::ipc::MAX_MESSAGE_LEN`]
+    TooLong,
+}
+
+/// Protocol state of a single connection with the host
+#[derive(Default)]
+pub struct Session {
+    /// Set once the host sent its hello, which has to be the first message on the connection. An
+    /// incompatible host gets nothing but errors afterwards, unless it says hello again.
+    host_version: Option<ProtocolVersion>,
+    /// Replies are built in here before being serialized
+    scratch: ScratchBuffer<64>,
+    /// Sample frames are built in here, a full [`SampleBlock`] takes 71 words
+    frames: ScratchBuffer<80>,
+}
+
+impl Session {
+    /// Handles a message received from the host at device time `received_at`, and encodes the
+    /// reply to it into `out`
+    pub fn handle(&mut self, message: &[u8], received_at: u64, out: &mut [u8]) -> Response {
+        let message = no_alloc::read(message).ok();
+        let command = message
+            .as_ref()
+            .and_then(|message| message.get_root::<to_edge::Reader>().ok());
+        let Some(command) = command else {
+            common::log!(crate::LOG, Warn, "Couldn't decode a command");
+            return Response::None;
+        };
+        match self.reply(command, received_at, out) {
+            Ok(response) => response,
+            // The rest of the command is malformed, but the host can still be told which one
+            Err(_) => {
+                common::log!(crate::LOG, Warn, "Couldn't decode or reply to a command");
+                let request_id = command.get_request_id();
+                let encoded = self.scratch.encode::<from_edge::Owned>(out, |reply| {
+                    build_error(
+                        reply,
+                        request_id,
+                        ErrorCode::InvalidArgument,
+                        "Malformed command",
+                    )
+                });
+                encoded.map_or(Response::None, Response::Reply)
+            }
+        }
+    }
+
+    /// Encodes a log record to forward into `out`. Returns `None` if the host hasn't said hello
+    /// yet, or speaks an incompatible version.
+    pub fn log_record(&mut self, record: &Record, out: &mut [u8]) -> Option<usize> {
+        self.compatible_host()?;
+        let encoded = self
+            .scratch
+            .encode::<from_edge::Owned>(out, |message| build_log_record(message, record));
+        encoded.ok()
+    }
+
+    /// Encodes the samples of `block` into `out`. Returns `None` if the host hasn't said hello yet,
+    /// or speaks an incompatible version.
+    pub fn sample_frame(&mut self, block: &SampleBlock, out: &mut [u8]) -> Option<usize> {
+        self.compatible_host()?;
+        let encoded = self
+            .frames
+            .encode::<from_edge::Owned>(out, |message| build_sample_frame(message, block));
+        encoded.ok()
+    }
+
+    /// Encodes the reply to a command that couldn't be forwarded to the application core into
+    /// `out`
+    pub fn forward_failed(
+        &mut self,
+        message: &[u8],
+        error: ForwardError,
+        out: &mut [u8],
+    ) -> Option<usize> {
+        let command = no_alloc::read(message).ok()?;
+        let request_id = command.get_root::<to_edge::Reader>().ok()?.get_request_id();
+        let (code, text) = match error {
+            ForwardError::Full => (ErrorCode::Busy, "Application core isn't keeping up"),
+            ForwardError::Unavailable => (
+                ErrorCode::Unknown,
+                "Application and network core firmware don't match",
+            ),
+            ForwardError::TooLong => (
+                ErrorCode::InvalidArgument,
+                "Command too long for the application core",
+            ),
+        };
+        let encoded = self
+            .scratch
+            .encode::<from_edge::Owned>(out, |reply| build_error(reply, request_id, code, text));
+        encoded.ok()
+    }
+
+    /// Version of the host, if it said hello with one we can talk to
+    fn compatible_host(&self) -> Option<ProtocolVersion> {
+        self.host_version.filter(|host_version| {
+            ProtocolVersion::CURRENT.compatibility(host_version) != Compatibility::Incompatible
+        })
+    }
+
+    fn reply(
+        &mut self,
+        command: to_edge::Reader,
+        received_at: u64,
+        out: &mut [u8],
+    ) -> capnp::Result<Response> {
+        let request_id = command.get_request_id();
+        let which = command.which();
+        if self.host_version.is_none() && !matches!(which, Ok(to_edge::Hello(_))) {
+            defmt::warn!("First message on the connection wasn't a hello");
+            return Ok(Response::Close);
+        }
+        if self.compatible_host().is_none() && !matches!(which, Ok(to_edge::Hello(_))) {
+            let len = self.scratch.encode::<from_edge::Owned>(out, |reply| {
+                build_error(
+                    reply,
+                    request_id,
+                    ErrorCode::Unsupported,
+                    "Incompatible protocol version",
+                )
+            })?;
+            return Ok(Response::Reply(len));
+        }
+
+        let len = match which {
+            Ok(to_edge::Hello(hello)) => {
+                let hello = hello?;
+                let host_version = ProtocolVersion {
+                    major: hello.get_protocol_major(),
+                    minor: hello.get_protocol_minor(),
+                };
+                defmt::info!(
+                    "Host speaks protocol version {}.{}",
+                    host_version.major,
+                    host_version.minor
+                );
+                // We still reply, so that the host can tell the user why it can't talk to us, but
+                // nothing else it sends is handled
+                if ProtocolVersion::CURRENT.compatibility(&host_version)
+                    == Compatibility::Incompatible
+                {
+                    defmt::warn!(
+                        "Host protocol version {}.{} is incompatible with ours ({}.{})",
+                        host_version.major,
+                        host_version.minor,
+                        ProtocolVersion::CURRENT.major,
+                        ProtocolVersion::CURRENT.minor
+                    );
+                }
+                self.host_version = Some(host_version);
+                self.scratch.encode::<from_edge::Owned>(out, |reply| {
+                    build_capabilities(reply, request_id)
+                })?
+            }
+            Ok(to_edge::GetStatus(())) => self
+                .scratch
+                .encode::<from_edge::Owned>(out, |reply| build_status(reply, request_id))?,
+            Ok(to_edge::SetTime(time)) => {
+                crate::CLOCK.set(time);
+                common::log!(crate::LOG, Info, "Clock set to {} µs", time);
+                self.scratch
+                    .encode::<from_edge::Owned>(out, |reply| build_ack(reply, request_id))?
+            }
+            Ok(to_edge::Ping(ping)) => {
+                let host_time = ping?.get_host_time();
+                self.scratch.encode::<from_edge::Owned>(out, |mut reply| {
+                    reply.set_request_id(request_id);
+                    let mut pong = reply.init_pong();
+                    pong.set_host_time(host_time);
+                    pong.set_receive_time(received_at);
+                    // As late as we can, the reply is sent right after being encoded
+                    pong.set_transmit_time(crate::CLOCK.now());
+                    Ok(())
+                })?
+            }
+            Ok(to_edge::SetLogLevel(Ok(level))) => {
+                crate::LOG.set_level(Some(level.into()));
+                self.scratch
+                    .encode::<from_edge::Owned>(out, |reply| build_ack(reply, request_id))?
+            }
+            Ok(to_edge::SetLogLevel(Err(_))) => {
+                self.scratch.encode::<from_edge::Owned>(out, |reply| {
+                    build_error(
+                        reply,
+                        request_id,
+                        ErrorCode::InvalidArgument,
+                        "Unknown log level",
+                    )
+                })?
+            }
+            // Streaming, recording and everything else the front-end is involved in
+            Ok(_) => return Ok(Response::Forward),
+            // Sent by a host with a newer protocol version
+            Err(_) => self.scratch.encode::<from_edge::Owned>(out, |reply| {
+                build_error(reply, request_id, ErrorCode::Unsupported, "Unknown command")
+            })?,
+        };
+        Ok(Response::Reply(len))
+    }
+}
+
+/// Fills in `reply` with the capabilities of this device
+fn build_capabilities(mut reply: from_edge::Builder, request_id: u32) -> capnp::Result<()> {
+    reply.set_request_id(request_id);
+    let mut capabilities = reply.init_capabilities();
+    capabilities.set_protocol_major(ProtocolVersion::CURRENT.major);
+    capabilities.set_protocol_minor(ProtocolVersion::CURRENT.minor);
+    capabilities.set_channel_count(CHANNEL_COUNT);
+    capabilities.set_sample_rates(&SAMPLE_RATES)?;
+    capabilities.set_has_storage(false);
+    capabilities.set_has_imu(false);
+    Ok(())
+}
+
+/// Fills in `reply` with the status of the device. The battery is as last reported by the
+/// application core, nothing reports the storage or recordings yet.
+fn build_status(mut reply: from_edge::Builder, request_id: u32) -> capnp::Result<()> {
+    reply.set_request_id(request_id);
+    let mut status = reply.init_status();
+    status.set_hardware_rev(HARDWARE_REV);
+    status.set_firmware_rev(env!("CARGO_PKG_VERSION"));
+    let mut battery = status.reborrow().init_battery();
+    match crate::BATTERY.lock(Cell::get) {
+        Some(charge) => {
+            let mut level = if charge.charging {
+                battery.init_charging()
+            } else {
+                battery.init_discharging()
+            };
+            level.set_percentage(charge.percentage);
+            level.set_estimated_time(charge.estimated_time);
+        }
+        None => battery.set_none(()),
+    }
+    status.set_uptime(Instant::now().as_secs() as u32);
+    status.set_current_time(crate::CLOCK.now() / 1_000_000);
+    // Left out when the queues aren't where this image expects them
+    if SHARED_RAM_HEADER.check().is_ok() {
+        set_queue_stats(
+            status.reborrow().init_app_to_net_queue(),
+            IPC_CHANNELS.app_to_net.stats(),
+        );
+        set_queue_stats(
+            status.init_net_to_app_queue(),
+            IPC_CHANNELS.net_to_app.stats(),
+        );
+    }
+    Ok(())
+}
+
+fn set_queue_stats(mut builder: queue_stats::Builder, stats: QueueStats) {
+    builder.set_sent(stats.sent);
+    builder.set_dropped(stats.dropped);
+    builder.set_high_water(stats.high_water);
+    builder.set_capacity(stats.capacity);
+}
+
+/// Fills in `message` with a log record, sent unprompted
+fn build_log_record(message: from_edge::Builder, record: &Record) -> capnp::Result<()> {
+    let mut log_record = message.init_log_record();
+    log_record.set_level(record.level.into());
+    log_record.set_core(record.core.into());
+    log_record.set_timestamp(record.timestamp);
+    log_record.set_text(record.text.as_str());
+    Ok(())
+}
+
+/// Fills in `message` with the samples of `block`, sent unprompted
+fn build_sample_frame(message: from_edge::Builder, block: &SampleBlock) -> capnp::Result<()> {
+    let mut frame = message.init_sample_frame();
+    frame.set_sample_counter(block.sample_counter);
+    frame.set_timestamp(block.timestamp);
+    frame.set_sample_rate(block.sample_rate);
+    frame.set_channel_count(block.channel_count);
+    frame.set_lead_off(block.lead_off);
+    frame.set_samples(block.samples())?;
+    Ok(())
+}
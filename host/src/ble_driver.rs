@@ -1,16 +1,30 @@
-use bluest::{Adapter, Device};
-use std::{str::FromStr, time::Duration};
+use anyhow::Context;
+use bluest::{Adapter, Device, L2capChannel};
+use futures_lite::StreamExt;
+use std::{future::Future, time::Duration};
 use tokio::{task, time::timeout};
 use uuid::Uuid;
 
-/// Manages an adapter, scans for devices and automatically connects to peripherals that matches
+/// Service the headband advertises, the device sends the bytes of the UUID in reverse
+const SERVICE_UUID: Uuid = Uuid::from_u128(u128::from_le_bytes(proto::SERVICE_UUID));
+/// Pause between losing a device and scanning for one again
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How long opening the data channel of a device may take
+const OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Manages an adapter, scans for headbands and connects to the first one found. The data channel
+/// of the device is handed to `on_connect`, and scanning starts again once that returns.
 pub struct BleDriver {
     discovery_handle: task::JoinHandle<()>,
 }
 
 impl BleDriver {
-    pub fn new(adapter: Adapter) -> Self {
-        let handle = tokio::spawn(discovery_task(adapter));
+    pub fn new<F, Fut>(adapter: Adapter, on_connect: F) -> Self
+    where
+        F: Fn(L2capChannel) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let handle = tokio::spawn(discovery_task(adapter, on_connect));
 
         Self {
             discovery_handle: handle,
@@ -18,31 +32,58 @@ impl BleDriver {
     }
 }
 
-async fn discovery_task(adapter: Adapter) -> () {
-    //let service_uuid = Uuid::from_bytes(common::EEG_DATA_SERVICE_UUID);
-    let service_uuid = Uuid::from_str("00001105-0000-1000-8000-00805f9b34fb").unwrap();
-    let service_uuids = &[service_uuid.clone()];
-    let stream = adapter.scan(service_uuids).await.unwrap();
+impl Drop for BleDriver {
+    fn drop(&mut self) {
+        self.discovery_handle.abort();
+    }
+}
 
+async fn discovery_task<F, Fut>(adapter: Adapter, on_connect: F)
+where
+    F: Fn(L2capChannel) -> Fut,
+    Fut: Future<Output = ()>,
+{
     loop {
-        for device in adapter.connected_devices().await.unwrap() {
-            let name = device.name().unwrap_or_else(|_| "<Unknown>".to_string());
-
-            tracing::info!(?name, "Found device");
-
-            if !device.is_paired().await.unwrap() {
-                tracing::info!("Waiting for device to be paired");
+        let device = match find_device(&adapter).await {
+            Ok(device) => device,
+            Err(error) => {
+                tracing::warn!(%error, "Couldn't scan for devices");
+                tokio::time::sleep(RECONNECT_DELAY).await;
                 continue;
             }
+        };
+        let name = device.name().unwrap_or_else(|_| "<Unknown>".to_string());
+        tracing::info!(?name, "Found device");
 
-            let output = timeout(
-                Duration::from_secs(100),
-                device.discover_services_with_uuid(service_uuid.clone()),
-            )
-            .await;
-            tracing::info!(?output, "Discovering services");
+        match open_channel(&adapter, &device).await {
+            Ok(channel) => {
+                tracing::info!(?name, "Connected to device");
+                on_connect(channel).await;
+            }
+            Err(error) => tracing::warn!(?name, %error, "Couldn't connect to device"),
         }
-
-        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        if let Err(error) = adapter.disconnect_device(&device).await {
+            tracing::debug!(?name, %error, "Couldn't disconnect from device");
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
     }
 }
+
+/// Waits for a headband to advertise itself
+async fn find_device(adapter: &Adapter) -> anyhow::Result<Device> {
+    adapter.wait_available().await?;
+    let mut scan = adapter.scan(&[SERVICE_UUID]).await?;
+    let found = scan.next().await.context("scan stopped")?;
+    Ok(found.device)
+}
+
+async fn open_channel(adapter: &Adapter, device: &Device) -> anyhow::Result<L2capChannel> {
+    adapter.connect_device(device).await?;
+    let channel = timeout(
+        OPEN_TIMEOUT,
+        device.open_l2cap_channel(proto::framing::PSM, false),
+    )
+    .await
+    .context("timed out opening the data channel")??;
+    Ok(channel)
+}
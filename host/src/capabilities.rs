@@ -0,0 +1,36 @@
+use proto::from_edge_capnp::capabilities;
+use proto::{Compatibility, ProtocolVersion};
+
+/// What the connected device told us it supports during the handshake
+#[derive(Debug, Clone)]
+pub struct DeviceCapabilities {
+    /// Protocol version the device speaks
+    pub version: ProtocolVersion,
+    /// How well we can talk to the device, given its protocol version
+    pub compatibility: Compatibility,
+    /// Number of EEG channels on the device
+    pub channel_count: u8,
+    /// Sample rates the device can be configured to (Hz)
+    pub sample_rates: Vec<u32>,
+    pub has_storage: bool,
+    pub has_imu: bool,
+}
+
+impl TryFrom<capabilities::Reader<'_>> for DeviceCapabilities {
+    type Error = capnp::Error;
+
+    fn try_from(capabilities: capabilities::Reader<'_>) -> Result<Self, Self::Error> {
+        let version = ProtocolVersion {
+            major: capabilities.get_protocol_major(),
+            minor: capabilities.get_protocol_minor(),
+        };
+        Ok(Self {
+            version,
+            compatibility: ProtocolVersion::CURRENT.compatibility(&version),
+            channel_count: capabilities.get_channel_count(),
+            sample_rates: capabilities.get_sample_rates()?.iter().collect(),
+            has_storage: capabilities.get_has_storage(),
+            has_imu: capabilities.get_has_imu(),
+        })
+    }
+}
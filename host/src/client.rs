@@ -0,0 +1,160 @@
+use crate::command::Command;
+use capnp::message::{ReaderOptions, TypedReader};
+use capnp::serialize::{self, OwnedSegments};
+use parking_lot::Mutex;
+use proto::from_edge_capnp::{from_edge, ErrorCode};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// A message received from the device
+pub type Message = TypedReader<OwnedSegments, from_edge::Owned>;
+
+/// Why a command didn't get a successful reply
+#[derive(Debug)]
+pub enum CommandError {
+    /// The device didn't reply in time
+    Timeout,
+    /// The connection to the device was closed before it replied
+    Disconnected,
+    /// The device replied with an error
+    Device { code: ErrorCode, message: String },
+    /// The reply couldn't be decoded
+    Decode(capnp::Error),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Timeout => write!(f, "timed out waiting for a reply"),
+            CommandError::Disconnected => write!(f, "device disconnected"),
+            CommandError::Device { code, message } => write!(f, "device error {code:?}: {message}"),
+            CommandError::Decode(error) => write!(f, "couldn't decode reply: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+type ReplySender = oneshot::Sender<Result<Message, CommandError>>;
+
+/// Sends commands to the device, and hands each of them the reply the device sent back to it
+pub struct DeviceClient {
+    outgoing: mpsc::Sender<Vec<u8>>,
+    pending: Mutex<HashMap<u32, ReplySender>>,
+    next_request_id: AtomicU32,
+    timeout: Duration,
+}
+
+impl DeviceClient {
+    /// How long to wait for a reply, unless changed with [`DeviceClient::with_timeout`]
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Creates a client that writes encoded commands to `outgoing`. Every message received from
+    /// the device has to be passed to [`DeviceClient::handle_message`].
+    pub fn new(outgoing: mpsc::Sender<Vec<u8>>) -> Self {
+        Self {
+            outgoing,
+            pending: Default::default(),
+            next_request_id: AtomicU32::new(1),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends `command`, and waits for the device to reply to it
+    pub async fn send(&self, command: Command) -> Result<Message, CommandError> {
+        let request_id = self.next_request_id();
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().insert(request_id, sender);
+        // Stops waiting for the reply when we time out, or when this future is dropped
+        let _pending = PendingGuard {
+            client: self,
+            request_id,
+        };
+
+        tracing::debug!(?command, request_id, "Sending command");
+        self.outgoing
+            .send(command.encode(request_id))
+            .await
+            .map_err(|_| CommandError::Disconnected)?;
+
+        match tokio::time::timeout(self.timeout, receiver).await {
+            Ok(Ok(reply)) => reply,
+            // The sender got dropped by `disconnect`
+            Ok(Err(_)) => Err(CommandError::Disconnected),
+            Err(_) => Err(CommandError::Timeout),
+        }
+    }
+
+    /// Decodes a message received from the device, and hands it to the command it's a reply to.
+    /// Returns the message back if it isn't a reply to any pending command, e.g. a sample frame.
+    pub fn handle_message(&self, bytes: &[u8]) -> capnp::Result<Option<Message>> {
+        let message: Message =
+            serialize::read_message(&mut &bytes[..], ReaderOptions::new())?.into_typed();
+        let request_id = message.get()?.get_request_id();
+
+        let sender = match request_id {
+            0 => None,
+            request_id => self.pending.lock().remove(&request_id),
+        };
+        let Some(sender) = sender else {
+            return Ok(Some(message));
+        };
+
+        let reply = match reply_error(&message) {
+            Ok(None) => Ok(message),
+            Ok(Some(error)) => Err(error),
+            Err(error) => Err(CommandError::Decode(error)),
+        };
+        // The command may have stopped waiting in the meantime
+        let _ = sender.send(reply);
+        Ok(None)
+    }
+
+    /// Fails every command that is still waiting for a reply
+    pub fn disconnect(&self) {
+        self.pending.lock().clear();
+    }
+
+    fn next_request_id(&self) -> u32 {
+        loop {
+            // 0 is reserved for messages that aren't replies
+            let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+            if request_id != 0 {
+                return request_id;
+            }
+        }
+    }
+}
+
+struct PendingGuard<'a> {
+    client: &'a DeviceClient,
+    request_id: u32,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.client.pending.lock().remove(&self.request_id);
+    }
+}
+
+/// Returns the error the device replied with, if it replied with one
+fn reply_error(message: &Message) -> capnp::Result<Option<CommandError>> {
+    let from_edge::Error(error) = message.get()?.which()? else {
+        return Ok(None);
+    };
+    let error = error?;
+    Ok(Some(CommandError::Device {
+        code: error.get_code().unwrap_or(ErrorCode::Unknown),
+        message: error.get_message()?.to_string()?,
+    }))
+}
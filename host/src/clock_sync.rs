@@ -0,0 +1,136 @@
+use crate::client::{CommandError, DeviceClient};
+use crate::command::Command;
+use proto::from_edge_capnp::from_edge;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Exchanges the estimate is based on, older ones are forgotten as the clocks drift apart
+const MAX_EXCHANGES: usize = 16;
+/// Pings sent by [`synchronise`]
+const PINGS: usize = 8;
+
+/// Current wall-clock time of the host (unix timestamp, microseconds)
+pub fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+/// Timestamps of one ping exchange (microseconds)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exchange {
+    /// Host time the ping was sent at (t0)
+    pub host_sent: u64,
+    /// Device time the ping was received at (t1)
+    pub device_received: u64,
+    /// Device time the pong was sent at (t2)
+    pub device_sent: u64,
+    /// Host time the pong was received at (t3)
+    pub host_received: u64,
+}
+
+impl Exchange {
+    /// How far ahead the device clock is of the host clock, assuming the link is as fast in both
+    /// directions
+    pub fn offset(&self) -> i64 {
+        let there = self.device_received as i64 - self.host_sent as i64;
+        let back = self.device_sent as i64 - self.host_received as i64;
+        (there + back) / 2
+    }
+
+    /// Time spent on the link, without the time the device took to reply
+    pub fn round_trip(&self) -> u64 {
+        let total = self.host_received.saturating_sub(self.host_sent);
+        total.saturating_sub(self.device_sent.saturating_sub(self.device_received))
+    }
+}
+
+/// Offset between the device and host clocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockEstimate {
+    /// How far ahead the device clock is of the host clock (microseconds)
+    pub offset: i64,
+    /// The true offset is within this many microseconds of `offset`
+    pub uncertainty: u64,
+}
+
+impl ClockEstimate {
+    /// Host wall-clock time (unix timestamp, microseconds) of a device timestamp, within
+    /// [`ClockEstimate::uncertainty`]
+    pub fn to_host_time(&self, device_time: u64) -> u64 {
+        device_time.saturating_add_signed(-self.offset)
+    }
+}
+
+/// Estimates the device clock offset from ping exchanges, as NTP does
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    exchanges: VecDeque<Exchange>,
+}
+
+impl ClockSync {
+    pub fn add(&mut self, exchange: Exchange) {
+        if self.exchanges.len() == MAX_EXCHANGES {
+            self.exchanges.pop_front();
+        }
+        self.exchanges.push_back(exchange);
+    }
+
+    /// Forgets every exchange, e.g. after the device clock was set
+    pub fn reset(&mut self) {
+        self.exchanges.clear();
+    }
+
+    /// Based on the exchange with the shortest round trip, as it's the one the least delayed in
+    /// either direction. However asymmetric its delays were, the error on the offset is at most
+    /// half of its round trip.
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        let best = self.exchanges.iter().min_by_key(|e| e.round_trip())?;
+        Some(ClockEstimate {
+            offset: best.offset(),
+            uncertainty: best.round_trip().div_ceil(2),
+        })
+    }
+}
+
+/// Sends a single ping, and returns the timestamps of the exchange
+pub async fn ping(client: &DeviceClient) -> Result<Exchange, CommandError> {
+    let host_sent = unix_micros();
+    let reply = client.send(Command::Ping(host_sent)).await?;
+    let host_received = unix_micros();
+
+    let reply = reply.get().map_err(CommandError::Decode)?;
+    match reply.which().map_err(|e| CommandError::Decode(e.into()))? {
+        from_edge::Pong(pong) => {
+            let pong = pong.map_err(CommandError::Decode)?;
+            Ok(Exchange {
+                host_sent: pong.get_host_time(),
+                device_received: pong.get_receive_time(),
+                device_sent: pong.get_transmit_time(),
+                host_received,
+            })
+        }
+        _ => Err(CommandError::Decode(capnp::Error::failed(
+            "expected a pong".to_string(),
+        ))),
+    }
+}
+
+/// Sets the device clock to the host's, then measures how far apart they still are
+pub async fn synchronise(
+    client: &DeviceClient,
+    sync: &mut ClockSync,
+) -> Result<ClockEstimate, CommandError> {
+    client.send(Command::SetTime(unix_micros())).await?;
+    sync.reset();
+    for _ in 0..PINGS {
+        sync.add(ping(client).await?);
+    }
+    let estimate = sync.estimate().expect("at least one exchange");
+    tracing::info!(
+        offset = estimate.offset,
+        uncertainty = estimate.uncertainty,
+        "Synchronised with the device clock"
+    );
+    Ok(estimate)
+}
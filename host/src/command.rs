@@ -0,0 +1,75 @@
+use proto::from_edge_capnp::LogLevel;
+use proto::to_edge_capnp::{to_edge, Gain};
+use proto::ProtocolVersion;
+
+/// A command that can be sent to the headband
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    GetStatus,
+    StartStreaming,
+    StopStreaming,
+    /// Sample rate for every channel (Hz)
+    SetSampleRate(u32),
+    SetChannelGain {
+        channel: u8,
+        gain: Gain,
+    },
+    /// Bit `n` enables channel `n`
+    SetChannelMask(u32),
+    /// Unix timestamp (microseconds)
+    SetTime(u64),
+    StartRecording,
+    StopRecording,
+    Reboot,
+    /// First message on every connection, tells the device which protocol version we speak
+    Hello,
+    /// Forward device log records of this level and above
+    SetLogLevel(LogLevel),
+    /// Host wall-clock time the ping is sent at (unix timestamp, microseconds)
+    Ping(u64),
+    /// Drive a test current into the electrodes to measure their contact instead of streaming
+    SetImpedanceMode(bool),
+    /// Stream at a fraction of the sample rate (Hz), 0 for every sample
+    SetStreamRate(u32),
+}
+
+impl Command {
+    /// Fills in `builder` with this command
+    pub fn build(&self, mut builder: to_edge::Builder) {
+        match *self {
+            Command::GetStatus => builder.set_get_status(()),
+            Command::StartStreaming => builder.set_start_streaming(()),
+            Command::StopStreaming => builder.set_stop_streaming(()),
+            Command::SetSampleRate(rate) => builder.set_set_sample_rate(rate),
+            Command::SetChannelGain { channel, gain } => {
+                let mut channel_gain = builder.init_set_channel_gain();
+                channel_gain.set_channel(channel);
+                channel_gain.set_gain(gain);
+            }
+            Command::SetChannelMask(mask) => builder.set_set_channel_mask(mask),
+            Command::SetTime(time) => builder.set_set_time(time),
+            Command::StartRecording => builder.set_start_recording(()),
+            Command::StopRecording => builder.set_stop_recording(()),
+            Command::Reboot => builder.set_reboot(()),
+            Command::Hello => {
+                let mut hello = builder.init_hello();
+                hello.set_protocol_major(ProtocolVersion::CURRENT.major);
+                hello.set_protocol_minor(ProtocolVersion::CURRENT.minor);
+            }
+            Command::SetLogLevel(level) => builder.set_set_log_level(level),
+            Command::Ping(host_time) => builder.init_ping().set_host_time(host_time),
+            Command::SetImpedanceMode(enabled) => builder.set_set_impedance_mode(enabled),
+            Command::SetStreamRate(rate) => builder.set_set_stream_rate(rate),
+        }
+    }
+
+    /// Encodes this command as a standalone message. The device echoes `request_id` back in its
+    /// reply.
+    pub fn encode(&self, request_id: u32) -> Vec<u8> {
+        let mut message = capnp::message::Builder::new_default();
+        let mut root = message.init_root::<to_edge::Builder>();
+        root.set_request_id(request_id);
+        self.build(root);
+        capnp::serialize::write_message_to_words(&message)
+    }
+}
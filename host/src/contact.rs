@@ -0,0 +1,75 @@
+use proto::from_edge_capnp::contact_quality;
+
+/// Electrodes under this impedance make good contact with the skin (kΩ)
+pub const GOOD_BELOW: f32 = 10.0;
+/// Electrodes under this impedance, and not under [`GOOD_BELOW`], make usable contact (kΩ)
+pub const MARGINAL_BELOW: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    Good,
+    /// The signal is usable, but noisier and more prone to artifacts
+    Marginal,
+    /// Loose or dry electrode, the signal is mostly noise
+    Bad,
+}
+
+impl Quality {
+    pub fn of(impedance: f32) -> Self {
+        if impedance < GOOD_BELOW {
+            Quality::Good
+        } else if impedance < MARGINAL_BELOW {
+            Quality::Marginal
+        } else {
+            Quality::Bad
+        }
+    }
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Quality::Good => "good",
+            Quality::Marginal => "marginal",
+            Quality::Bad => "bad",
+        })
+    }
+}
+
+/// Contact of an electrode with the skin, measured in the impedance mode of the device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactQuality {
+    pub channel: u8,
+    /// Device time at which the measurement ended (microseconds)
+    pub timestamp: u64,
+    /// Between the electrode and the skin, at the frequency of the test current (kΩ)
+    pub impedance: f32,
+    pub quality: Quality,
+}
+
+impl TryFrom<contact_quality::Reader<'_>> for ContactQuality {
+    type Error = capnp::Error;
+
+    fn try_from(contact: contact_quality::Reader<'_>) -> Result<Self, Self::Error> {
+        let current = contact.get_test_current();
+        if current <= 0.0 || !current.is_finite() {
+            return Err(capnp::Error::failed(format!(
+                "contact quality measured with a test current of {current} nA"
+            )));
+        }
+        // µV / nA = kΩ
+        let impedance = contact.get_amplitude() / current;
+        Ok(Self {
+            channel: contact.get_channel(),
+            timestamp: contact.get_timestamp(),
+            impedance,
+            quality: Quality::of(impedance),
+        })
+    }
+}
+
+impl std::fmt::Display for ContactQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.1} kΩ ({})", self.impedance, self.quality)
+    }
+}
@@ -0,0 +1,101 @@
+use proto::from_edge_capnp::{log_record, Core, LogLevel};
+use std::collections::VecDeque;
+
+/// Records kept around, older ones are dropped
+const MAX_ENTRIES: usize = 2000;
+
+/// Every level, from least to most severe
+pub const LEVELS: [LogLevel; 5] = [
+    LogLevel::Trace,
+    LogLevel::Debug,
+    LogLevel::Info,
+    LogLevel::Warn,
+    LogLevel::Error,
+];
+
+pub fn level_name(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Trace => "TRACE",
+        LogLevel::Debug => "DEBUG",
+        LogLevel::Info => "INFO",
+        LogLevel::Warn => "WARN",
+        LogLevel::Error => "ERROR",
+    }
+}
+
+/// A log record forwarded by the device
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub level: LogLevel,
+    pub core: Core,
+    /// Time since the core booted (microseconds)
+    pub timestamp: u64,
+    pub text: String,
+}
+
+impl TryFrom<log_record::Reader<'_>> for LogEntry {
+    type Error = capnp::Error;
+
+    fn try_from(record: log_record::Reader<'_>) -> Result<Self, Self::Error> {
+        let text = match record.which()? {
+            log_record::Text(text) => text?.to_string()?,
+            // Resolving these needs the defmt table from the firmware's ELF file
+            log_record::FormatId(id) => format!("<defmt format string #{id}>"),
+        };
+        Ok(Self {
+            level: record.get_level()?,
+            core: record.get_core()?,
+            timestamp: record.get_timestamp(),
+            text,
+        })
+    }
+}
+
+impl std::fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let core = match self.core {
+            Core::App => "app",
+            Core::Net => "net",
+        };
+        write!(
+            f,
+            "{:>12.6} {core} {:<5} {}",
+            self.timestamp as f64 / 1e6,
+            level_name(self.level),
+            self.text
+        )
+    }
+}
+
+/// Log records received from the device, and the level below which they're hidden
+pub struct DeviceLog {
+    entries: VecDeque<LogEntry>,
+    /// Also the level the device is asked to forward records from
+    pub filter: LogLevel,
+}
+
+impl Default for DeviceLog {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            filter: LogLevel::Info,
+        }
+    }
+}
+
+impl DeviceLog {
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Entries at or above the filter level, oldest first
+    pub fn visible(&self) -> impl Iterator<Item = &LogEntry> {
+        // Generated enums are numbered from the least severe level
+        self.entries
+            .iter()
+            .filter(|entry| entry.level as u16 >= self.filter as u16)
+    }
+}
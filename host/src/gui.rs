@@ -14,11 +14,11 @@ use parking_lot::lock_api::GuardSend;
 use phosphor::PhosphorHeadless;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
-use std::{env, ops::DerefMut, path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, env, ops::DerefMut, path::PathBuf, sync::Arc};
 
 actions!(main, [Quit]);
 
-pub fn start_application(cx: &mut App) {
+pub fn start_application(cx: &mut App, main_window: MainWindow) {
     // Initialize gpui-component before using any components
     gpui_component::init(cx);
 
@@ -59,7 +59,7 @@ pub fn start_application(cx: &mut App) {
 
     cx.spawn(async move |cx| {
         let window = cx.open_window(window_options, |window, cx| {
-            let view = cx.new(|_cx| MainWindow::default());
+            let view = cx.new(|_cx| main_window);
 
             cx.new(|cx| Root::new(view, window, cx))
         })?;
@@ -110,6 +110,7 @@ impl<T> From<T> for Shared<T> {
 #[derive(Clone, Copy)]
 enum Tab {
     DeviceState,
+    DeviceLog,
     Streaming,
     Recordings,
     Firmware,
@@ -119,6 +120,7 @@ impl Tab {
     pub fn name(&self) -> &'static str {
         match self {
             Tab::DeviceState => "Device State",
+            Tab::DeviceLog => "Device Log",
             Tab::Streaming => "Streaming",
             Tab::Recordings => "Recordings",
             Tab::Firmware => "Firmware",
@@ -128,6 +130,7 @@ impl Tab {
     pub fn in_order() -> impl Iterator<Item = Self> {
         [
             Tab::DeviceState,
+            Tab::DeviceLog,
             Tab::Streaming,
             Tab::Recordings,
             Tab::Firmware,
@@ -136,7 +139,23 @@ impl Tab {
     }
 }
 
-use proto::{from_edge::FromEdge, to_edge::ToEdge};
+use crate::capabilities::DeviceCapabilities;
+use crate::client::{DeviceClient, Message};
+use crate::clock_sync::{self, ClockEstimate, ClockSync};
+use crate::command::Command;
+use crate::contact::ContactQuality;
+use crate::device_log::{DeviceLog, LogEntry};
+use crate::link;
+use crate::streaming::{BandPower, Stream};
+use proto::from_edge_capnp::{from_edge, LogLevel};
+use proto::Compatibility;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+
+/// Encoded commands waiting to be written to the device
+const OUTGOING_LEN: usize = 32;
+/// Messages from the device waiting to be applied to the state, mostly sample frames
+const UNSOLICITED_LEN: usize = 256;
 
 const fn pc(value: f32) -> DefiniteLength {
     let _: () = {
@@ -150,6 +169,18 @@ struct GuiState {
     // Tabs, plus static information for each tab
     selected_tab: Tab,
     device_state: Option<device_state::DeviceState>,
+    /// Received during the handshake with the device
+    capabilities: Option<DeviceCapabilities>,
+    /// Log records forwarded by the device
+    device_log: DeviceLog,
+    /// Offset of the device clock, once it has been synchronised
+    clock: Option<ClockEstimate>,
+    /// Samples streamed by the device, and what is computed from them
+    stream: Stream,
+    /// Set while the device is asked to measure the contact of the electrodes
+    impedance_mode: bool,
+    /// Latest measurement of each channel
+    contact: BTreeMap<u8, ContactQuality>,
 }
 
 impl Default for GuiState {
@@ -157,20 +188,222 @@ impl Default for GuiState {
         Self {
             selected_tab: Tab::DeviceState,
             device_state: Default::default(),
+            capabilities: Default::default(),
+            device_log: Default::default(),
+            clock: Default::default(),
+            stream: Default::default(),
+            impedance_mode: Default::default(),
+            contact: Default::default(),
         }
     }
 }
 
-#[derive(Default)]
-struct MainWindow {
+impl GuiState {
+    /// Updates the state with a message received from the device
+    fn apply(&mut self, message: from_edge::Reader) -> capnp::Result<()> {
+        match message.which()? {
+            from_edge::Status(report) => {
+                self.device_state = Some(device_state::DeviceState::try_from(report?)?);
+            }
+            from_edge::Capabilities(capabilities) => {
+                let capabilities = DeviceCapabilities::try_from(capabilities?)?;
+                match capabilities.compatibility {
+                    Compatibility::Full => {}
+                    Compatibility::Degraded => tracing::warn!(
+                        version = ?capabilities.version,
+                        "Device firmware is older than this host, newer features won't work"
+                    ),
+                    Compatibility::Incompatible => tracing::error!(
+                        version = ?capabilities.version,
+                        "Device speaks an incompatible protocol version, refusing to talk to it"
+                    ),
+                }
+                self.capabilities = Some(capabilities);
+            }
+            from_edge::LogRecord(record) => {
+                self.device_log.push(LogEntry::try_from(record?)?);
+            }
+            from_edge::SampleFrame(frame) => self.stream.push_frame(frame?)?,
+            from_edge::BandPower(band_power) => {
+                self.stream
+                    .set_band_power(BandPower::try_from(band_power?)?);
+            }
+            from_edge::ContactQuality(contact) => {
+                let contact = ContactQuality::try_from(contact?)?;
+                self.contact.insert(contact.channel, contact);
+            }
+            // Errors are handed to the command that caused them by the client, and pongs are
+            // only of use to the clock synchronisation that sent the ping
+            from_edge::Ack(()) | from_edge::Error(_) | from_edge::Pong(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Forgets what was only true of the device that just disconnected
+    fn disconnected(&mut self) {
+        self.capabilities = None;
+        self.clock = None;
+        self.stream.reset();
+        self.impedance_mode = false;
+        self.contact.clear();
+    }
+}
+
+/// Cloned into the tasks talking to the device, which share the state with the window
+#[derive(Clone, Default)]
+pub struct MainWindow {
     state: Shared<GuiState>,
+    /// Set while a device is connected, once it answered our hello
+    client: Shared<Option<Arc<DeviceClient>>>,
 }
 
 impl MainWindow {
-    pub fn send_command(&mut self, command: ToEdge) {
-        panic!("Unhandled command {:?}", command);
+    /// Talks to the device at the other end of `stream` until either side closes the connection.
+    ///
+    /// Hello is sent before any other command, and the connection is closed if the device doesn't
+    /// answer it or speaks an incompatible protocol version.
+    pub async fn run_connection<S>(&self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (outgoing, commands) = mpsc::channel(OUTGOING_LEN);
+        let (unsolicited, messages) = mpsc::channel(UNSOLICITED_LEN);
+        let client = Arc::new(DeviceClient::new(outgoing));
+        let receiver = tokio::spawn(self.clone().receive(messages));
+
+        let handshake = async {
+            if self.handshake(&client).await {
+                std::future::pending::<()>().await;
+            }
+        };
+        let result = tokio::select! {
+            result = link::run(stream, &client, commands, unsolicited) => result,
+            () = handshake => {
+                tracing::warn!("Closing the connection to a device we can't talk to");
+                Ok(())
+            }
+        };
+        if let Err(error) = result {
+            tracing::warn!(%error, "Connection to device failed");
+        }
+
+        self.client.update(|client| *client = None);
+        client.disconnect();
+        // Ends once the link dropped its sender, after the last message from the device
+        let _ = receiver.await;
+        self.state.update(GuiState::disconnected);
+    }
+
+    /// Says hello, and sets the device up once it's known that we can talk to it. Returns whether
+    /// we can.
+    async fn handshake(&self, client: &Arc<DeviceClient>) -> bool {
+        let reply = match client.send(Command::Hello).await {
+            Ok(reply) => reply,
+            Err(error) => {
+                tracing::error!(%error, "Device didn't answer our hello");
+                return false;
+            }
+        };
+        if let Err(error) = reply.get().and_then(|message| self.handle_message(message)) {
+            tracing::error!(%error, "Couldn't handle the capabilities of the device");
+            return false;
+        }
+        let compatible = self.state.update(|state| {
+            state
+                .capabilities
+                .as_ref()
+                .is_some_and(|c| c.compatibility != Compatibility::Incompatible)
+        });
+        if !compatible {
+            return false;
+        }
+        self.client.update(|slot| *slot = Some(client.clone()));
+
+        // The device doesn't forward any log records until it's told which ones we want
+        let level = self.state.update(|state| state.device_log.filter);
+        if let Err(error) = client.send(Command::SetLogLevel(level)).await {
+            tracing::warn!(%error, "Couldn't enable log forwarding");
+        }
+        sync_clock(client, &self.state).await;
+        true
+    }
+
+    /// Applies the messages the device sends without being asked to, until the link closes
+    async fn receive(self, mut messages: mpsc::Receiver<Message>) {
+        while let Some(message) = messages.recv().await {
+            if let Err(error) = message
+                .get()
+                .and_then(|message| self.handle_message(message))
+            {
+                tracing::warn!(%error, "Couldn't handle message from device");
+            }
+        }
+    }
+
+    /// Handles a message the device sent without being asked to
+    pub fn handle_message(&self, message: from_edge::Reader) -> capnp::Result<()> {
+        self.state.update(|state| state.apply(message))
+    }
+
+    pub fn send_command(&mut self, command: Command) {
+        let Some(client) = self.client.update(|client| client.clone()) else {
+            tracing::warn!(?command, "No device connected, dropping command");
+            return;
+        };
+
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let reply = match client.send(command).await {
+                Ok(reply) => reply,
+                Err(error) => {
+                    tracing::error!(?command, %error, "Command failed");
+                    return;
+                }
+            };
+            let applied = reply
+                .get()
+                .and_then(|message| state.update(|state| state.apply(message)));
+            if let Err(error) = applied {
+                tracing::error!(?command, %error, "Couldn't handle reply");
+            }
+        });
+    }
+
+    /// Sets the device clock to ours, and measures the offset left between them
+    pub fn sync_clock(&mut self) {
+        let Some(client) = self.client.update(|client| client.clone()) else {
+            tracing::warn!("No device connected, not synchronising clocks");
+            return;
+        };
+        let state = self.state.clone();
+        tokio::spawn(async move { sync_clock(&client, &state).await });
+    }
+
+    /// Asks the device to start or stop measuring the contact of the electrodes
+    pub fn toggle_impedance_mode(&mut self) {
+        let enabled = self.state.update(|state| {
+            state.impedance_mode = !state.impedance_mode;
+            state.impedance_mode
+        });
+        self.send_command(Command::SetImpedanceMode(enabled));
+    }
+
+    /// Hides log records below `level`, and asks the device to only forward those from now on
+    pub fn set_log_filter(&mut self, level: LogLevel) {
+        self.state.update(|state| state.device_log.filter = level);
+        if self.client.update(|client| client.is_some()) {
+            self.send_command(Command::SetLogLevel(level));
+        }
     }
 }
+
+async fn sync_clock(client: &DeviceClient, state: &Shared<GuiState>) {
+    match clock_sync::synchronise(client, &mut ClockSync::default()).await {
+        Ok(estimate) => state.update(|state| state.clock = Some(estimate)),
+        Err(error) => tracing::error!(%error, "Couldn't synchronise clocks"),
+    }
+}
+
 impl Render for MainWindow {
     fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         div()
@@ -199,10 +432,11 @@ pub fn navigation_bar(cx: &mut Context<MainWindow>, shared: Shared<GuiState>) ->
 
 pub fn content_pane(cx: &mut Context<MainWindow>, shared: Shared<GuiState>) -> impl IntoElement {
     let selected_tab = match shared.update(|shared| shared.selected_tab) {
-        Tab::DeviceState => device_state::device_state(cx, shared),
+        Tab::DeviceState => device_state::device_state(cx, shared).into_any_element(),
+        Tab::DeviceLog => log_console::log_console(cx, shared).into_any_element(),
         Tab::Firmware => unimplemented!(),
         Tab::Recordings => unimplemented!(),
-        Tab::Streaming => unimplemented!(),
+        Tab::Streaming => streaming_view::streaming(cx, shared).into_any_element(),
     };
     div()
         .p(px(16.0))
@@ -213,6 +447,8 @@ pub fn content_pane(cx: &mut Context<MainWindow>, shared: Shared<GuiState>) -> i
 }
 
 mod device_state {
+    use crate::command::Command;
+    use crate::contact::Quality;
     use crate::gui::{GuiState, MainWindow, Shared};
     use gpui::*;
     use gpui_component::{
@@ -220,6 +456,7 @@ mod device_state {
         description_list::{DescriptionItem, DescriptionList},
         label::Label,
     };
+    use proto::from_edge_capnp::{battery_status, queue_stats, status_report};
 
     /// Status of the battery we've received from the device
     enum BatteryStatus {
@@ -245,6 +482,34 @@ mod device_state {
         }
     }
 
+    /// Counters of a queue between the two cores of the device
+    struct QueueStats {
+        sent: u32,
+        dropped: u32,
+        high_water: u32,
+        capacity: u32,
+    }
+
+    impl ToString for QueueStats {
+        fn to_string(&self) -> String {
+            format!(
+                "{} sent, {} dropped, at most {} of {} waiting",
+                self.sent, self.dropped, self.high_water, self.capacity
+            )
+        }
+    }
+
+    impl From<queue_stats::Reader<'_>> for QueueStats {
+        fn from(stats: queue_stats::Reader<'_>) -> Self {
+            QueueStats {
+                sent: stats.get_sent(),
+                dropped: stats.get_dropped(),
+                high_water: stats.get_high_water(),
+                capacity: stats.get_capacity(),
+            }
+        }
+    }
+
     /// Stores the current state of the connected device
     pub struct DeviceState {
         /// String identifying the hardware revision of the board
@@ -265,6 +530,48 @@ mod device_state {
         storage_size_used: u32,
         /// Amount of storage that is currently free
         storage_size_free: u32,
+        /// Queue of the messages from the application core to the network core
+        app_to_net_queue: QueueStats,
+        /// Queue of the commands forwarded to the application core
+        net_to_app_queue: QueueStats,
+    }
+
+    impl TryFrom<battery_status::Reader<'_>> for BatteryStatus {
+        type Error = capnp::Error;
+
+        fn try_from(battery: battery_status::Reader<'_>) -> Result<Self, Self::Error> {
+            Ok(match battery.which()? {
+                battery_status::None(()) => BatteryStatus::None,
+                battery_status::Charging(level) => {
+                    let level = level?;
+                    BatteryStatus::Charging(level.get_percentage(), level.get_estimated_time())
+                }
+                battery_status::Discharging(level) => {
+                    let level = level?;
+                    BatteryStatus::Discharging(level.get_percentage(), level.get_estimated_time())
+                }
+            })
+        }
+    }
+
+    impl TryFrom<status_report::Reader<'_>> for DeviceState {
+        type Error = capnp::Error;
+
+        fn try_from(report: status_report::Reader<'_>) -> Result<Self, Self::Error> {
+            Ok(DeviceState {
+                hardware_rev: report.get_hardware_rev()?.to_string()?,
+                firmware_rev: report.get_firmware_rev()?.to_string()?,
+                battery_status: report.get_battery()?.try_into()?,
+                uptime: report.get_uptime(),
+                current_time_reconding: report.get_recording_time(),
+                current_time: report.get_current_time(),
+                storage_size_total: report.get_storage_total(),
+                storage_size_used: report.get_storage_used(),
+                storage_size_free: report.get_storage_free(),
+                app_to_net_queue: report.get_app_to_net_queue()?.into(),
+                net_to_app_queue: report.get_net_to_app_queue()?.into(),
+            })
+        }
     }
 
     trait Formatter<T> {
@@ -291,30 +598,51 @@ mod device_state {
     }
 
     pub fn device_state(
-        _cx: &mut Context<MainWindow>,
+        cx: &mut Context<MainWindow>,
         shared: Shared<GuiState>,
     ) -> impl IntoElement {
-        let shared_inner = shared.clone();
-        let update_button =
-            Button::new("update_button")
-                .label("Fetch Status")
-                .on_click(move |_, _, _| {
-                    shared_inner.update(|status| {
-                        status.device_state = Some(DeviceState {
-                            hardware_rev: "092hns234".into(),
-                            firmware_rev: "ht94hr0298349".into(),
-                            battery_status: BatteryStatus::Charging(100.0, 0),
-                            uptime: 234,
-                            current_time_reconding: 0,
-                            current_time: 234123523,
-                            storage_size_total: 16,
-                            storage_size_used: 0,
-                            storage_size_free: 16,
-                        })
-                    });
-                });
-
-        let root = div().flex_1().flex_col().child(update_button);
+        // The device state gets filled in once the device replies with a status report
+        let update_button = Button::new("update_button")
+            .label("Fetch Status")
+            .on_click(cx.listener(|window, _, _, _| window.send_command(Command::GetStatus)));
+
+        let sync_button = Button::new("sync_button")
+            .label("Sync Clock")
+            .on_click(cx.listener(|window, _, _, _| window.sync_clock()));
+
+        let impedance_mode = shared.update(|state| state.impedance_mode);
+        let contact_button = Button::new("contact_button")
+            .label(if impedance_mode {
+                "Stop Contact Check"
+            } else {
+                "Check Contact"
+            })
+            .on_click(cx.listener(|window, _, _, _| window.toggle_impedance_mode()));
+
+        let root = div().flex_1().flex_col().child(
+            div()
+                .flex()
+                .gap(px(8.0))
+                .child(update_button)
+                .child(sync_button)
+                .child(contact_button),
+        );
+        let mut root = shared.update(|state| match &state.clock {
+            Some(clock) => root.child(Label::new(format!(
+                "Device clock offset: {} µs ± {} µs",
+                clock.offset, clock.uncertainty
+            ))),
+            None => root.child(Label::new("Clocks not synchronised")),
+        });
+        let contact: Vec<_> = shared.update(|state| state.contact.values().copied().collect());
+        for contact in contact {
+            let line = div().child(format!("Channel {} contact: {contact}", contact.channel));
+            root = root.child(match contact.quality {
+                Quality::Good => line,
+                Quality::Marginal => line.text_color(yellow()),
+                Quality::Bad => line.text_color(red()),
+            });
+        }
         let root = shared.update(move |state| {
             if let Some(device_state) = &state.device_state {
                 let mut state_list = DescriptionList::horizontal().bordered(true).columns(1);
@@ -336,18 +664,38 @@ mod device_state {
                     ),
                     text_with_formatter("Uptime", &device_state.uptime, StringFormatter),
                     text_with_formatter(
-                        "Hardware Revision",
-                        &device_state.hardware_rev,
+                        "Recording Time",
+                        &device_state.current_time_reconding,
                         StringFormatter,
                     ),
                     text_with_formatter(
-                        "Hardware Revision",
-                        &device_state.hardware_rev,
+                        "Current Time",
+                        &device_state.current_time,
                         StringFormatter,
                     ),
                     text_with_formatter(
-                        "Hardware Revision",
-                        &device_state.hardware_rev,
+                        "Storage Total",
+                        &device_state.storage_size_total,
+                        StringFormatter,
+                    ),
+                    text_with_formatter(
+                        "Storage Used",
+                        &device_state.storage_size_used,
+                        StringFormatter,
+                    ),
+                    text_with_formatter(
+                        "Storage Free",
+                        &device_state.storage_size_free,
+                        StringFormatter,
+                    ),
+                    text_with_formatter(
+                        "App → Net Queue",
+                        &device_state.app_to_net_queue,
+                        StringFormatter,
+                    ),
+                    text_with_formatter(
+                        "Net → App Queue",
+                        &device_state.net_to_app_queue,
                         StringFormatter,
                     ),
                 ]);
@@ -359,3 +707,147 @@ mod device_state {
         root
     }
 }
+
+mod log_console {
+    use crate::device_log::{level_name, LEVELS};
+    use crate::gui::{GuiState, MainWindow, Shared};
+    use gpui::*;
+    use gpui_component::{button::Button, label::Label};
+    use proto::from_edge_capnp::LogLevel;
+
+    /// Most records shown at once, the oldest ones are left out
+    const MAX_SHOWN: usize = 500;
+
+    pub fn log_console(cx: &mut Context<MainWindow>, shared: Shared<GuiState>) -> impl IntoElement {
+        let (filter, lines) = shared.update(|state| {
+            let visible: Vec<_> = state.device_log.visible().collect();
+            let skip = visible.len().saturating_sub(MAX_SHOWN);
+            let lines: Vec<_> = visible[skip..]
+                .iter()
+                .map(|entry| (entry.level, entry.to_string()))
+                .collect();
+            (state.device_log.filter, lines)
+        });
+
+        let mut filter_bar = div().flex().gap(px(8.0)).child(Label::new("Show from"));
+        for level in LEVELS {
+            // The selected level is marked, as the buttons have no selected state of their own
+            let label = if level == filter {
+                format!("[{}]", level_name(level))
+            } else {
+                level_name(level).to_string()
+            };
+            filter_bar = filter_bar.child(
+                Button::new(level_name(level))
+                    .label(label)
+                    .on_click(cx.listener(move |window, _, _, _| window.set_log_filter(level))),
+            );
+        }
+
+        let mut log = div()
+            .id("device_log")
+            .flex()
+            .flex_col()
+            .flex_1()
+            .overflow_y_scroll();
+        if lines.is_empty() {
+            log = log.child(Label::new("No log records received"));
+        }
+        for (level, line) in lines {
+            let line = div().child(line);
+            log = log.child(match level {
+                LogLevel::Error => line.text_color(red()),
+                LogLevel::Warn => line.text_color(yellow()),
+                _ => line,
+            });
+        }
+
+        div()
+            .flex_1()
+            .flex_col()
+            .gap(px(12.0))
+            .child(filter_bar)
+            .child(log)
+    }
+}
+
+mod streaming_view {
+    use crate::gui::{GuiState, MainWindow, Shared};
+    use crate::streaming::COUNTS_PER_MICROVOLT;
+    use dsp::welch::{EEG_BANDS, EEG_BAND_NAMES};
+    use gpui::*;
+    use gpui_component::label::Label;
+
+    /// Name of band `n` between `edges`, the EEG bands being known by name
+    fn band_name(edges: &[f32], n: usize) -> String {
+        if edges == EEG_BANDS {
+            EEG_BAND_NAMES[n].to_string()
+        } else {
+            format!("{}-{} Hz", edges[n], edges[n + 1])
+        }
+    }
+
+    pub fn streaming(_cx: &mut Context<MainWindow>, shared: Shared<GuiState>) -> impl IntoElement {
+        let (band_power, flags, lost_samples, plot_rate, ranges) = shared.update(|state| {
+            let flags = state
+                .stream
+                .segments()
+                .back()
+                .map(|segment| segment.flags.clone());
+            // Peak to peak of the plotted samples of each channel
+            let ranges: Vec<_> = (0..)
+                .map_while(|channel| state.stream.plot(channel))
+                .map(|plot| {
+                    let max = plot.iter().copied().fold(f32::MIN, f32::max);
+                    let min = plot.iter().copied().fold(f32::MAX, f32::min);
+                    (max - min).max(0.0) / COUNTS_PER_MICROVOLT
+                })
+                .collect();
+            (
+                state.stream.band_power().cloned(),
+                flags,
+                state.stream.lost_samples(),
+                state.stream.plot_rate(),
+                ranges,
+            )
+        });
+
+        let mut root = div().flex_1().flex_col().gap(px(8.0));
+        if lost_samples > 0 {
+            root = root.child(Label::new(format!("{lost_samples} samples lost")));
+        }
+        let Some(band_power) = band_power else {
+            return root.child(Label::new("No samples received"));
+        };
+        if plot_rate > 0 {
+            root = root.child(Label::new(format!("Plotted at {plot_rate} Hz")));
+        }
+
+        for (channel, power) in band_power.power.iter().enumerate() {
+            let mut row = div()
+                .flex()
+                .gap(px(16.0))
+                .child(format!("Channel {channel}"));
+            for (n, power) in power.iter().enumerate() {
+                row = row.child(format!(
+                    "{}: {power:.0}",
+                    band_name(&band_power.band_edges, n)
+                ));
+            }
+            if let Some(range) = ranges.get(channel) {
+                row = row.child(format!("{range:.1} µV peak to peak"));
+            }
+            // Only computed here, not sent by the device along with its band power
+            if let Some(&artifacts) = flags.as_ref().and_then(|flags| flags.get(channel)) {
+                let label = div().child(artifacts.to_string());
+                row = row.child(if artifacts.is_empty() {
+                    label
+                } else {
+                    label.text_color(red())
+                });
+            }
+            root = root.child(row);
+        }
+        root
+    }
+}
@@ -0,0 +1,69 @@
+use crate::client::{DeviceClient, Message};
+use proto::framing::{self, MAX_FRAME_LEN};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+/// Largest message we accept from the device
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Shuttles messages between `client` and the byte stream of a connected device, until either
+/// the stream or `outgoing` is closed.
+///
+/// `outgoing` receives the encoded commands of the client. Messages from the device that aren't
+/// replies to a command are sent to `unsolicited`.
+pub async fn run<S>(
+    stream: S,
+    client: &DeviceClient,
+    mut outgoing: mpsc::Receiver<Vec<u8>>,
+    unsolicited: mpsc::Sender<Message>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut decoder = Box::new(framing::Decoder::<MAX_MESSAGE_LEN>::new());
+    let mut encoder = framing::Encoder::new();
+    let mut chunk = [0; MAX_FRAME_LEN];
+    let mut frame = [0; MAX_FRAME_LEN];
+
+    let result = 'link: loop {
+        tokio::select! {
+            read = reader.read(&mut chunk) => {
+                let count = match read {
+                    Ok(0) => break Ok(()),
+                    Ok(count) => count,
+                    Err(error) => break Err(error),
+                };
+                let mut data = &chunk[..count];
+                while let Some(message) = decoder.decode(&mut data) {
+                    match client.handle_message(message) {
+                        Ok(Some(message)) => {
+                            let _ = unsolicited.send(message).await;
+                        }
+                        Ok(None) => {}
+                        Err(error) => tracing::warn!(%error, "Couldn't decode message from device"),
+                    }
+                }
+            }
+            message = outgoing.recv() => {
+                let Some(message) = message else {
+                    break Ok(());
+                };
+                // Every frame is written on its own, so that it maps onto a single SDU
+                let mut frames = encoder.frames(&message, MAX_FRAME_LEN);
+                while let Some(len) = frames.next_into(&mut frame) {
+                    if let Err(error) = writer.write_all(&frame[..len]).await {
+                        break 'link Err(error);
+                    }
+                }
+            }
+        }
+    };
+
+    let stats = decoder.stats();
+    tracing::info!(?stats, "Connection to device closed");
+    client.disconnect();
+    result
+}
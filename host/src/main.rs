@@ -1,18 +1,24 @@
 // See the "macOS permissions note" in README.md before running this on macOS
 // Big Sur or later.
 
+use bluest::Adapter;
 use gpui::Application;
 use tokio::runtime::Builder;
 
 mod ble_driver;
+mod capabilities;
+mod client;
+mod clock_sync;
+mod command;
+mod contact;
+mod device_log;
 mod gui;
+mod link;
+mod streaming;
 
 fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt().init();
 
-    //let adapter = Adapter::default().await.unwrap();
-    //let driver = ble_driver::BleDriver::new(adapter);
-
     // 1. Build the runtime manually
     let rt = Builder::new_multi_thread()
         .worker_threads(4)
@@ -20,12 +26,30 @@ fn main() -> anyhow::Result<()> {
         .build()
         .unwrap();
 
+    let adapter = rt.block_on(Adapter::default());
+
     // 2. Enter the runtime in this scope
     let _enter = rt.enter(); // runtime is now "current" on this thread
 
+    // 3. Connect to the headband in the background, for as long as the application runs
+    let main_window = gui::MainWindow::default();
+    let _driver = match adapter {
+        Some(adapter) => {
+            let main_window = main_window.clone();
+            Some(ble_driver::BleDriver::new(adapter, move |channel| {
+                let main_window = main_window.clone();
+                async move { main_window.run_connection(channel).await }
+            }))
+        }
+        None => {
+            tracing::error!("No Bluetooth adapter found, can't connect to a device");
+            None
+        }
+    };
+
     Application::new()
         .with_assets(gpui_component_assets::Assets)
-        .run(gui::start_application);
+        .run(move |cx| gui::start_application(cx, main_window));
 
     Ok(())
 }
@@ -0,0 +1,302 @@
+//! Samples streamed by the device, filtered and turned into band power as they arrive, with the
+//! artifacts of every channel flagged along the way. The filtered samples are kept at a reduced
+//! rate for plotting, recordings take the frames at the full rate.
+//!
+//! When the link doesn't have the bandwidth for the samples, the device sends the band power
+//! instead, which takes the place of the one computed here.
+
+use dsp::artifact::MUSCLE_CUTOFF;
+use dsp::welch::{Welch, WelchConfig, Window, EEG_BANDS};
+use dsp::{
+    ArtifactConfig, ArtifactDetector, Artifacts, Cascade, Design, FixedCoefficients, Mains,
+    Resampler,
+};
+use proto::from_edge_capnp::{band_power, sample_frame};
+use std::collections::VecDeque;
+
+/// Samples of each channel in a segment of the band power estimate, as on the device
+pub const SEGMENT_LEN: usize = 256;
+/// Half overlapping Hann windows, an estimate every 512 samples covering 640
+pub const BAND_POWER_CONFIG: WelchConfig = WelchConfig {
+    window: Window::Hann,
+    overlap: SEGMENT_LEN / 2,
+    segments: 4,
+};
+/// ADC counts per microvolt at the electrodes, for the 4.5 V reference and the gain of 24 the
+/// front-end starts with
+pub const COUNTS_PER_MICROVOLT: f32 = (1 << 23) as f32 / (4.5e6 / 24.0);
+/// Flagged segments kept around, an hour's worth, older ones are dropped
+const MAX_SEGMENTS: usize = 2 * 60 * 60;
+/// Samples are plotted at up to this rate, the lowest sample rate of the device (Hz)
+pub const PLOT_RATE: u32 = 250;
+/// Plotted samples kept of each channel, older ones are dropped
+pub const PLOT_LEN: usize = 10 * PLOT_RATE as usize;
+/// Taps of the filter of each halving of the sample rate on the way down to [`PLOT_RATE`]
+const HALVING_TAPS: usize = 48;
+
+/// Power of every channel in frequency bands, over consecutive samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandPower {
+    /// Index of the first sample the estimate covers since streaming started
+    pub sample_counter: u64,
+    /// Device time at which that sample was taken (microseconds)
+    pub timestamp: u64,
+    /// Consecutive samples of each channel the estimate covers
+    pub sample_count: u32,
+    pub sample_rate: u32,
+    /// Band `n` goes from edge `n` up to edge `n + 1` (Hz)
+    pub band_edges: Vec<f32>,
+    /// Power of each band (squared ADC counts), by channel
+    pub power: Vec<Vec<f32>>,
+}
+
+impl TryFrom<band_power::Reader<'_>> for BandPower {
+    type Error = capnp::Error;
+
+    fn try_from(band_power: band_power::Reader<'_>) -> Result<Self, Self::Error> {
+        let band_edges: Vec<f32> = band_power.get_band_edges()?.iter().collect();
+        let bands = band_edges.len().saturating_sub(1);
+        let channels = usize::from(band_power.get_channel_count());
+        let power: Vec<f32> = band_power.get_power()?.iter().collect();
+        if bands == 0 || power.len() != bands * channels {
+            return Err(capnp::Error::failed(format!(
+                "{} band powers for {channels} channels and {} band edges",
+                power.len(),
+                band_edges.len()
+            )));
+        }
+        Ok(Self {
+            sample_counter: band_power.get_sample_counter(),
+            timestamp: band_power.get_timestamp(),
+            sample_count: band_power.get_sample_count(),
+            sample_rate: band_power.get_sample_rate(),
+            band_edges,
+            power: power.chunks(bands).map(<[f32]>::to_vec).collect(),
+        })
+    }
+}
+
+/// Artifacts of every channel over consecutive samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlaggedSegment {
+    /// Index of the first sample of the segment since streaming started
+    pub sample_counter: u64,
+    /// Device time at which that sample was taken (microseconds)
+    pub timestamp: u64,
+    pub sample_count: u32,
+    /// By channel
+    pub flags: Vec<Artifacts>,
+}
+
+/// Filters of a single channel
+struct Channel {
+    /// Removes drift and mains hum. In fixed point, the drift cut-off is too low for `f32` at the
+    /// higher sample rates.
+    preprocessing: Cascade<FixedCoefficients, 2, 1>,
+    band_power: Welch<SEGMENT_LEN, 1>,
+    /// Looks at the raw samples, as clipping doesn't survive the filters. In `f64`, the blink band
+    /// is too low for `f32` at the higher sample rates.
+    artifacts: ArtifactDetector<1, Design>,
+    /// Halve the sample rate one after the other, a single filter would need thousands of taps
+    /// to go from the highest sample rates down to the plot rate
+    halvings: Vec<Resampler<HALVING_TAPS, 1>>,
+    /// Filtered samples at the plot rate, oldest first
+    plot: VecDeque<f32>,
+}
+
+impl Channel {
+    fn new(mains: Mains, sample_rate: f32) -> Self {
+        let artifacts = ArtifactConfig::new(proto::SAMPLE_MAX as f32, COUNTS_PER_MICROVOLT);
+        Self {
+            preprocessing: Cascade::new(Design::preprocessing(mains, sample_rate).map(Into::into)),
+            band_power: Welch::new(BAND_POWER_CONFIG, sample_rate),
+            artifacts: ArtifactDetector::new(artifacts, mains, sample_rate),
+            halvings: (0..plot_halvings(sample_rate as u32))
+                .map(|_| Resampler::decimator(2))
+                .collect(),
+            plot: VecDeque::with_capacity(PLOT_LEN),
+        }
+    }
+
+    /// Adds a filtered sample to the plot, once it made it through the halvings
+    fn plot(&mut self, x: f32) {
+        fn halve(halvings: &mut [Resampler<HALVING_TAPS, 1>], x: f32, plot: &mut VecDeque<f32>) {
+            let Some((halving, rest)) = halvings.split_first_mut() else {
+                if plot.len() == PLOT_LEN {
+                    plot.pop_front();
+                }
+                plot.push_back(x);
+                return;
+            };
+            halving.push(&[x], |&[y]| halve(rest, y, plot));
+        }
+        halve(&mut self.halvings, x, &mut self.plot);
+    }
+}
+
+/// Times the sample rate is halved on the way down to the plot rate
+fn plot_halvings(sample_rate: u32) -> u32 {
+    let mut halvings = 0;
+    while sample_rate >> halvings > PLOT_RATE && (sample_rate >> halvings).is_multiple_of(2) {
+        halvings += 1;
+    }
+    halvings
+}
+
+/// Processing of the sample frames of a stream
+pub struct Stream {
+    mains: Mains,
+    channels: Vec<Channel>,
+    /// Layout of the frames the filters are set up for, a change starts them over
+    sample_rate: u32,
+    /// `sampleCounter` the next frame should start at, `None` before the first one
+    next_counter: Option<u64>,
+    /// Samples lost in gaps between frames since streaming started
+    lost_samples: u64,
+    /// Latest estimate, computed here or sent by the device
+    band_power: Option<BandPower>,
+    /// Oldest first, for recordings to store along with the samples
+    segments: VecDeque<FlaggedSegment>,
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Self::new(Mains::Hz50)
+    }
+}
+
+impl Stream {
+    pub fn new(mains: Mains) -> Self {
+        Self {
+            mains,
+            channels: Vec::new(),
+            sample_rate: 0,
+            next_counter: None,
+            lost_samples: 0,
+            band_power: None,
+            segments: VecDeque::new(),
+        }
+    }
+
+    /// Filters the samples of `frame`, updating the band power once there is a new estimate and
+    /// flagging the artifacts of every segment that ends
+    pub fn push_frame(&mut self, frame: sample_frame::Reader) -> capnp::Result<()> {
+        let sample_rate = frame.get_sample_rate();
+        let channel_count = usize::from(frame.get_channel_count());
+        let samples = frame.get_samples()?;
+        // Well under the lowest sample rate of the device
+        if sample_rate as f32 <= 2.0 * MUSCLE_CUTOFF || channel_count == 0 {
+            return Err(capnp::Error::failed(format!(
+                "sample frame of {channel_count} channels at {sample_rate} Hz"
+            )));
+        }
+        let frames = samples.len() as usize / channel_count;
+
+        let counter = frame.get_sample_counter();
+        let gap = self.next_counter.filter(|&next| next != counter);
+        if let Some(next) = gap {
+            tracing::warn!(next, counter, "Samples were lost, processing starts over");
+            self.lost_samples += counter.saturating_sub(next);
+        }
+        if gap.is_some() || sample_rate != self.sample_rate || channel_count != self.channels.len()
+        {
+            self.restart(sample_rate, channel_count);
+        }
+        self.next_counter = Some(counter + frames as u64);
+
+        let span = self.channels[0].band_power.span();
+        let segment_len = self.channels[0].artifacts.segment_len();
+        // Counter and timestamp of the first of the `len` samples up to sample `index` of the frame
+        let first = |index: usize, len: usize| {
+            let offset = (index as i64 - (len as i64 - 1)) * 1_000_000 / i64::from(sample_rate);
+            (
+                (counter + index as u64).saturating_sub(len as u64 - 1),
+                frame.get_timestamp().saturating_add_signed(offset),
+            )
+        };
+
+        let samples: Vec<i32> = samples.iter().collect();
+        for (index, frame_samples) in samples.chunks_exact(channel_count).enumerate() {
+            let mut power = Vec::new();
+            let mut flags = Vec::new();
+            for (channel, &sample) in self.channels.iter_mut().zip(frame_samples) {
+                if let Some(artifacts) = channel.artifacts.push(&[sample as f32]) {
+                    flags.push(artifacts[0]);
+                }
+                let filtered = channel.preprocessing.process(0, sample) as f32;
+                channel.plot(filtered);
+                if let Some(estimate) = channel.band_power.push(&[filtered]) {
+                    let mut bands = vec![0.0; EEG_BANDS.len() - 1];
+                    estimate.band_power(0, &EEG_BANDS, &mut bands);
+                    power.push(bands);
+                }
+            }
+            if flags.len() == channel_count {
+                let (sample_counter, timestamp) = first(index, segment_len);
+                if self.segments.len() == MAX_SEGMENTS {
+                    self.segments.pop_front();
+                }
+                self.segments.push_back(FlaggedSegment {
+                    sample_counter,
+                    timestamp,
+                    sample_count: segment_len as u32,
+                    flags,
+                });
+            }
+            if power.len() == channel_count {
+                let (sample_counter, timestamp) = first(index, span);
+                self.band_power = Some(BandPower {
+                    sample_counter,
+                    timestamp,
+                    sample_count: span as u32,
+                    sample_rate,
+                    band_edges: EEG_BANDS.to_vec(),
+                    power,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the band power the device estimated in place of the one computed from the samples
+    pub fn set_band_power(&mut self, band_power: BandPower) {
+        self.band_power = Some(band_power);
+    }
+
+    pub fn band_power(&self) -> Option<&BandPower> {
+        self.band_power.as_ref()
+    }
+
+    /// Artifacts of the segments streamed so far, oldest first
+    pub fn segments(&self) -> &VecDeque<FlaggedSegment> {
+        &self.segments
+    }
+
+    pub fn lost_samples(&self) -> u64 {
+        self.lost_samples
+    }
+
+    /// Rate of the plotted samples, 0 before the first frame (Hz)
+    pub fn plot_rate(&self) -> u32 {
+        self.sample_rate >> plot_halvings(self.sample_rate)
+    }
+
+    /// Filtered samples of `channel` at the plot rate, oldest first. The last [`PLOT_LEN`] of them
+    /// since processing last started over.
+    pub fn plot(&self, channel: usize) -> Option<&VecDeque<f32>> {
+        self.channels.get(channel).map(|channel| &channel.plot)
+    }
+
+    /// Starts the filters over, e.g. when streaming stops
+    pub fn reset(&mut self) {
+        self.channels.clear();
+        self.next_counter = None;
+    }
+
+    fn restart(&mut self, sample_rate: u32, channel_count: usize) {
+        self.sample_rate = sample_rate;
+        self.channels = (0..channel_count)
+            .map(|_| Channel::new(self.mains, sample_rate as f32))
+            .collect();
+    }
+}
@@ -0,0 +1,99 @@
+//! Compression ratio and speed of the sample codec on synthetic EEG.
+//!
+//! Run with `cargo bench --bench compression`.
+
+use proto::compression::{self, max_encoded_len};
+use proto::{SAMPLE_MAX, SAMPLE_MIN};
+use std::f64::consts::TAU;
+use std::hint::black_box;
+use std::time::Instant;
+
+const CHANNELS: usize = 8;
+/// Length of the recording compressed at every sample rate
+const SECONDS: usize = 60;
+/// Sample sets per block, about what fits in one BLE frame after compression
+const BLOCK_SETS: usize = 32;
+/// Input referred voltage of one LSB, for a 4.5 V reference and a gain of 24
+const LSB_MICROVOLTS: f64 = 4.5e6 / 24.0 / (1 << 23) as f64;
+
+/// xorshift64*, so that the benchmark doesn't depend on a random number crate
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Roughly normally distributed, with a standard deviation of 1
+    fn next_gaussian(&mut self) -> f64 {
+        (0..12).map(|_| self.next_f64()).sum::<f64>() - 6.0
+    }
+}
+
+/// Interleaved samples of a few rhythms, drifting electrode offsets, mains interference and
+/// amplifier noise
+fn synthetic_eeg(sample_rate: usize) -> Vec<i32> {
+    let mut rng = Rng(0x5EED);
+    let mut drift = [0.0; CHANNELS];
+    let mut pink = [0.0; CHANNELS];
+    let mut samples = Vec::with_capacity(sample_rate * SECONDS * CHANNELS);
+    for n in 0..sample_rate * SECONDS {
+        let t = n as f64 / sample_rate as f64;
+        for channel in 0..CHANNELS {
+            let phase = channel as f64 * 0.7;
+            drift[channel] += rng.next_gaussian() * 0.5;
+            pink[channel] = 0.98 * pink[channel] + rng.next_gaussian() * 2.0;
+            let microvolts = 15_000.0 * (channel as f64 - 3.5)
+                + drift[channel]
+                + 20.0 * (TAU * 10.0 * t + phase).sin()
+                + 6.0 * (TAU * 21.0 * t + 2.0 * phase).sin()
+                + 4.0 * (TAU * 50.0 * t).sin()
+                + pink[channel]
+                + rng.next_gaussian() * 0.5;
+            let sample = (microvolts / LSB_MICROVOLTS).round() as i32;
+            samples.push(sample.clamp(SAMPLE_MIN, SAMPLE_MAX));
+        }
+    }
+    samples
+}
+
+fn main() {
+    println!(
+        "{CHANNELS} channels, {SECONDS} s, blocks of {BLOCK_SETS} sample sets, raw size 3 bytes per sample"
+    );
+    for sample_rate in [250, 500, 1000] {
+        let samples = synthetic_eeg(sample_rate);
+        let block_len = BLOCK_SETS * CHANNELS;
+        let mut encoded = vec![0; max_encoded_len(CHANNELS, block_len)];
+        let mut blocks = Vec::new();
+
+        let start = Instant::now();
+        for block in samples.chunks(block_len) {
+            let len = compression::encode(black_box(block), CHANNELS as u8, &mut encoded).unwrap();
+            blocks.push(encoded[..len].to_vec());
+        }
+        let encode_time = start.elapsed();
+
+        let mut decoded = vec![0; block_len];
+        let start = Instant::now();
+        for (block, original) in blocks.iter().zip(samples.chunks(block_len)) {
+            let info = compression::decode(black_box(block), &mut decoded).unwrap();
+            assert_eq!(&decoded[..info.len()], original);
+        }
+        let decode_time = start.elapsed();
+
+        let raw_len = samples.len() * 3;
+        let compressed_len: usize = blocks.iter().map(Vec::len).sum();
+        let million_samples = samples.len() as f64 / 1e6;
+        println!(
+            "{sample_rate:>5} Hz: {raw_len:>8} -> {compressed_len:>8} bytes, ratio {:.2}, {:.1} kB/s, encode {:.1} Msamples/s, decode {:.1} Msamples/s",
+            raw_len as f64 / compressed_len as f64,
+            compressed_len as f64 / SECONDS as f64 / 1000.0,
+            million_samples / encode_time.as_secs_f64(),
+            million_samples / decode_time.as_secs_f64(),
+        );
+    }
+}
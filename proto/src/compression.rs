@@ -0,0 +1,334 @@
+//! Lossless compression of blocks of 24-bit samples.
+//!
+//! Every channel is delta encoded, and the residuals are Rice coded with a parameter that adapts
+//! to the recent magnitude of the residuals of that channel (as in LOCO-I). A block stands on its
+//! own, so losing one doesn't prevent decoding the next:
+//!
+//! ```text
+//! | channel count (1) | sample sets (2, LE) | first sample of every channel (3 each, LE) | residuals |
+//! ```
+//!
+//! The residuals are a bit stream, MSB first, of the samples in the same interleaved order as
+//! [`crate::from_edge_capnp::sample_frame`]. A residual `r`, zigzag encoded to `u`, takes
+//! `u >> k` one bits, a zero bit, then the `k` low bits of `u`. Residuals that would need
+//! [`ESCAPE`] or more one bits are written as [`ESCAPE`] one bits followed by `u` in full.
+
+use crate::{SAMPLE_MAX, SAMPLE_MIN};
+use core::fmt;
+
+/// Most channels a block can hold
+pub const MAX_CHANNELS: usize = 32;
+
+const HEADER_LEN: usize = 3;
+const FIRST_SAMPLE_LEN: usize = 3;
+
+/// Length of the unary prefix that marks an escaped residual
+const ESCAPE: u32 = 16;
+/// Bits of an escaped residual, enough for the difference of any two 24-bit samples
+const RESIDUAL_BITS: u32 = 25;
+/// Largest Rice parameter
+const MAX_K: u32 = 24;
+/// The magnitude statistics are halved after this many residuals, so that they follow the signal
+const RESET: u32 = 64;
+/// Magnitude assumed for the first residuals of a block
+const INITIAL_MAGNITUDE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The output buffer is too small
+    BufferTooSmall,
+    /// The number of samples isn't a multiple of the channel count, or the channel count is 0 or
+    /// above [`MAX_CHANNELS`]
+    InvalidChannelCount,
+    /// More sample sets than fit in a block
+    TooManySamples,
+    /// A sample doesn't fit in 24 bits
+    SampleOutOfRange,
+    /// The block ends early, or decodes to samples outside of the 24-bit range
+    Corrupted,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BufferTooSmall => write!(f, "Output buffer too small"),
+            Self::InvalidChannelCount => write!(f, "Invalid channel count"),
+            Self::TooManySamples => write!(f, "Too many samples for a single block"),
+            Self::SampleOutOfRange => write!(f, "Sample out of the 24-bit range"),
+            Self::Corrupted => write!(f, "Corrupted block"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Layout of an encoded block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub channel_count: u8,
+    /// Number of samples of every channel
+    pub sample_sets: u16,
+}
+
+impl BlockInfo {
+    /// Total number of samples in the block
+    pub const fn len(&self) -> usize {
+        self.channel_count as usize * self.sample_sets as usize
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Largest possible size of an encoded block of `samples` samples over `channel_count` channels
+pub const fn max_encoded_len(channel_count: usize, samples: usize) -> usize {
+    let residual_bits = samples.saturating_sub(channel_count) * (ESCAPE + RESIDUAL_BITS) as usize;
+    HEADER_LEN + channel_count * FIRST_SAMPLE_LEN + residual_bits.div_ceil(8)
+}
+
+/// Magnitude statistics of the residuals of one channel, from which the Rice parameter is chosen
+#[derive(Clone, Copy)]
+struct Adaptation {
+    sum: u32,
+    count: u32,
+}
+
+impl Adaptation {
+    const fn new() -> Self {
+        Self {
+            sum: INITIAL_MAGNITUDE,
+            count: 1,
+        }
+    }
+
+    /// Smallest `k` for which `2^k` is at least the mean magnitude
+    fn k(&self) -> u32 {
+        let mut k = 0;
+        while k < MAX_K && (self.count << k) < self.sum {
+            k += 1;
+        }
+        k
+    }
+
+    fn update(&mut self, magnitude: u32) {
+        self.sum += magnitude;
+        self.count += 1;
+        if self.count == RESET {
+            self.sum >>= 1;
+            self.count >>= 1;
+        }
+    }
+}
+
+/// Per channel state, shared by the encoder and the decoder
+#[derive(Clone, Copy)]
+struct Channel {
+    previous: i32,
+    adaptation: Adaptation,
+}
+
+const fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+const fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+struct BitWriter<'a> {
+    out: &'a mut [u8],
+    len: usize,
+    buffer: u64,
+    buffered: u32,
+}
+
+impl BitWriter<'_> {
+    /// Writes the `count` low bits of `bits`, `count` being at most 32
+    fn write(&mut self, bits: u32, count: u32) -> Result<(), Error> {
+        let bits = bits as u64 & ((1 << count) - 1);
+        self.buffer = (self.buffer << count) | bits;
+        self.buffered += count;
+        while self.buffered >= 8 {
+            self.buffered -= 8;
+            self.push((self.buffer >> self.buffered) as u8)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), Error> {
+        *self.out.get_mut(self.len).ok_or(Error::BufferTooSmall)? = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Pads the last byte with zeros, and returns the number of bytes written
+    fn finish(mut self) -> Result<usize, Error> {
+        if self.buffered > 0 {
+            self.push((self.buffer << (8 - self.buffered)) as u8)?;
+        }
+        Ok(self.len)
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    buffer: u64,
+    buffered: u32,
+}
+
+impl BitReader<'_> {
+    /// Reads `count` bits, `count` being at most 32
+    fn read(&mut self, count: u32) -> Result<u32, Error> {
+        while self.buffered < count {
+            let (byte, rest) = self.data.split_first().ok_or(Error::Corrupted)?;
+            self.data = rest;
+            self.buffer = (self.buffer << 8) | *byte as u64;
+            self.buffered += 8;
+        }
+        self.buffered -= count;
+        Ok(((self.buffer >> self.buffered) & ((1 << count) - 1)) as u32)
+    }
+
+    /// Counts one bits up to the next zero bit, stopping at `max`
+    fn read_unary(&mut self, max: u32) -> Result<u32, Error> {
+        let mut count = 0;
+        while count < max && self.read(1)? == 1 {
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Encodes the interleaved `samples` of `channel_count` channels into `out`, returning the length
+/// of the block. `out` never needs more than [`max_encoded_len`] bytes.
+pub fn encode(samples: &[i32], channel_count: u8, out: &mut [u8]) -> Result<usize, Error> {
+    let channels = channel_count as usize;
+    if channels == 0 || channels > MAX_CHANNELS || !samples.len().is_multiple_of(channels) {
+        return Err(Error::InvalidChannelCount);
+    }
+    let sample_sets = u16::try_from(samples.len() / channels).map_err(|_| Error::TooManySamples)?;
+    if samples
+        .iter()
+        .any(|sample| !(SAMPLE_MIN..=SAMPLE_MAX).contains(sample))
+    {
+        return Err(Error::SampleOutOfRange);
+    }
+
+    let header_len = HEADER_LEN + channels * FIRST_SAMPLE_LEN;
+    if out.len() < header_len {
+        return Err(Error::BufferTooSmall);
+    }
+    let (header, residuals) = out.split_at_mut(header_len);
+    header[0] = channel_count;
+    header[1..HEADER_LEN].copy_from_slice(&sample_sets.to_le_bytes());
+    if sample_sets == 0 {
+        // There are no first samples to write
+        return Ok(HEADER_LEN);
+    }
+
+    let mut state = [Channel {
+        previous: 0,
+        adaptation: Adaptation::new(),
+    }; MAX_CHANNELS];
+    let (first, rest) = samples.split_at(channels);
+    for ((channel, sample), bytes) in state
+        .iter_mut()
+        .zip(first)
+        .zip(header[HEADER_LEN..].chunks_exact_mut(FIRST_SAMPLE_LEN))
+    {
+        channel.previous = *sample;
+        bytes.copy_from_slice(&sample.to_le_bytes()[..FIRST_SAMPLE_LEN]);
+    }
+
+    let mut writer = BitWriter {
+        out: residuals,
+        len: 0,
+        buffer: 0,
+        buffered: 0,
+    };
+    for set in rest.chunks_exact(channels) {
+        for (channel, sample) in state.iter_mut().zip(set) {
+            let value = zigzag(sample - channel.previous);
+            let k = channel.adaptation.k();
+            let quotient = value >> k;
+            if quotient < ESCAPE {
+                writer.write((1 << quotient) - 1, quotient)?;
+                writer.write(0, 1)?;
+                writer.write(value, k)?;
+            } else {
+                writer.write((1 << ESCAPE) - 1, ESCAPE)?;
+                writer.write(value, RESIDUAL_BITS)?;
+            }
+            channel.previous = *sample;
+            channel.adaptation.update(value);
+        }
+    }
+    Ok(header_len + writer.finish()?)
+}
+
+/// Reads the header of an encoded block
+pub fn block_info(data: &[u8]) -> Result<BlockInfo, Error> {
+    let header = data.get(..HEADER_LEN).ok_or(Error::Corrupted)?;
+    let info = BlockInfo {
+        channel_count: header[0],
+        sample_sets: u16::from_le_bytes([header[1], header[2]]),
+    };
+    if info.channel_count == 0 || info.channel_count as usize > MAX_CHANNELS {
+        return Err(Error::Corrupted);
+    }
+    Ok(info)
+}
+
+/// Decodes a block into `out`, which has to hold at least [`BlockInfo::len`] samples. The samples
+/// are written interleaved, as they were given to [`encode`].
+pub fn decode(data: &[u8], out: &mut [i32]) -> Result<BlockInfo, Error> {
+    let info = block_info(data)?;
+    let out = out.get_mut(..info.len()).ok_or(Error::BufferTooSmall)?;
+    if info.is_empty() {
+        return Ok(info);
+    }
+
+    let channels = info.channel_count as usize;
+    let header_len = HEADER_LEN + channels * FIRST_SAMPLE_LEN;
+    let first_samples = data.get(HEADER_LEN..header_len).ok_or(Error::Corrupted)?;
+
+    let mut state = [Channel {
+        previous: 0,
+        adaptation: Adaptation::new(),
+    }; MAX_CHANNELS];
+    let (first, rest) = out.split_at_mut(channels);
+    for ((channel, sample), bytes) in state
+        .iter_mut()
+        .zip(first)
+        .zip(first_samples.chunks_exact(FIRST_SAMPLE_LEN))
+    {
+        *sample = crate::sign_extend_24(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]));
+        channel.previous = *sample;
+    }
+
+    let mut reader = BitReader {
+        data: &data[header_len..],
+        buffer: 0,
+        buffered: 0,
+    };
+    for set in rest.chunks_exact_mut(channels) {
+        for (channel, sample) in state.iter_mut().zip(set) {
+            let k = channel.adaptation.k();
+            let quotient = reader.read_unary(ESCAPE)?;
+            let value = if quotient < ESCAPE {
+                (quotient << k) | reader.read(k)?
+            } else {
+                reader.read(RESIDUAL_BITS)?
+            };
+            *sample = channel
+                .previous
+                .checked_add(unzigzag(value))
+                .filter(|sample| (SAMPLE_MIN..=SAMPLE_MAX).contains(sample))
+                .ok_or(Error::Corrupted)?;
+            channel.previous = *sample;
+            channel.adaptation.update(value);
+        }
+    }
+    Ok(info)
+}
@@ -0,0 +1,312 @@
+//! Framing of messages sent over the L2CAP channel.
+//!
+//! Every message is split into one or more frames, each of which fits in a single L2CAP SDU:
+//!
+//! ```text
+//! | magic (2) | flags (1) | sequence (1) | payload length (2, LE) | payload | CRC-32 (4, LE) |
+//! ```
+//!
+//! The CRC covers the header and the payload. `flags` marks the first and last frame of a
+//! message, and `sequence` is incremented for every frame sent, so that the receiver notices when
+//! a fragment of a message goes missing. The decoder accepts the byte stream in chunks of any
+//! size, and resynchronises on the next magic after a corrupted frame.
+
+/// L2CAP channel the device listens on, the first of the dynamically allocated LE PSMs
+pub const PSM: u16 = 0x0080;
+/// Largest frame that fits in a single L2CAP SDU
+pub const MAX_FRAME_LEN: usize = 512;
+/// Bytes added to every frame on top of its payload
+pub const FRAME_OVERHEAD: usize = HEADER_LEN + CRC_LEN;
+/// Largest payload a single frame can carry
+pub const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - FRAME_OVERHEAD;
+
+const MAGIC: [u8; 2] = [0xEE, 0x6A];
+const HEADER_LEN: usize = 6;
+const CRC_LEN: usize = 4;
+
+/// Set on the first frame of a message
+const FLAG_FIRST: u8 = 1 << 0;
+/// Set on the last frame of a message
+const FLAG_LAST: u8 = 1 << 1;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (as used by zlib and ethernet) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Splits messages into frames
+#[derive(Debug, Default)]
+pub struct Encoder {
+    sequence: u8,
+}
+
+impl Encoder {
+    pub const fn new() -> Self {
+        Self { sequence: 0 }
+    }
+
+    /// Splits `message` into frames of at most `max_frame_len` bytes, which is clamped to
+    /// [`MAX_FRAME_LEN`].
+    ///
+    /// Panics if `max_frame_len` can't fit a frame carrying at least one byte of payload.
+    pub fn frames<'a>(&'a mut self, message: &'a [u8], max_frame_len: usize) -> Frames<'a> {
+        let max_frame_len = max_frame_len.min(MAX_FRAME_LEN);
+        assert!(
+            max_frame_len > FRAME_OVERHEAD,
+            "frames can't carry any payload"
+        );
+        Frames {
+            encoder: self,
+            remaining: message,
+            max_payload_len: max_frame_len - FRAME_OVERHEAD,
+            first: true,
+        }
+    }
+}
+
+/// Frames of a single message, see [`Encoder::frames`]
+pub struct Frames<'a> {
+    encoder: &'a mut Encoder,
+    remaining: &'a [u8],
+    max_payload_len: usize,
+    first: bool,
+}
+
+impl Frames<'_> {
+    /// Writes the next frame into `out`, returning its length, or `None` once the whole message
+    /// has been written.
+    ///
+    /// Panics if `out` is too small to hold the frame.
+    pub fn next_into(&mut self, out: &mut [u8]) -> Option<usize> {
+        // An empty message still takes up one frame
+        if self.remaining.is_empty() && !self.first {
+            return None;
+        }
+
+        let payload_len = self.remaining.len().min(self.max_payload_len);
+        let (payload, remaining) = self.remaining.split_at(payload_len);
+        self.remaining = remaining;
+
+        let mut flags = 0;
+        if self.first {
+            flags |= FLAG_FIRST;
+            self.first = false;
+        }
+        if self.remaining.is_empty() {
+            flags |= FLAG_LAST;
+        }
+
+        let frame_len = payload_len + FRAME_OVERHEAD;
+        let frame = &mut out[..frame_len];
+        frame[..2].copy_from_slice(&MAGIC);
+        frame[2] = flags;
+        frame[3] = self.encoder.sequence;
+        frame[4..HEADER_LEN].copy_from_slice(&(payload_len as u16).to_le_bytes());
+        frame[HEADER_LEN..HEADER_LEN + payload_len].copy_from_slice(payload);
+        let crc = crc32(&frame[..HEADER_LEN + payload_len]);
+        frame[HEADER_LEN + payload_len..].copy_from_slice(&crc.to_le_bytes());
+
+        self.encoder.sequence = self.encoder.sequence.wrapping_add(1);
+        Some(frame_len)
+    }
+}
+
+/// Counters of everything the decoder had to throw away
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderStats {
+    /// Bytes skipped while looking for the start of a frame
+    pub skipped_bytes: u32,
+    /// Frames with a valid magic, but an invalid length or checksum
+    pub corrupted_frames: u32,
+    /// Messages dropped because a fragment went missing, or because they didn't fit in the
+    /// decoder's buffer
+    pub dropped_messages: u32,
+}
+
+enum Parse {
+    /// A valid frame of this length sits at the start of the buffer
+    Frame(usize),
+    /// This many more bytes are needed before we know whether the buffer starts with a frame
+    Incomplete(usize),
+    /// The buffer doesn't start with a valid frame
+    Invalid,
+}
+
+#[repr(C, align(8))]
+struct Aligned<const N: usize>([u8; N]);
+
+/// Reassembles messages of up to `N` bytes from a stream of frames
+pub struct Decoder<const N: usize> {
+    frame: [u8; MAX_FRAME_LEN],
+    frame_len: usize,
+    message: Aligned<N>,
+    message_len: usize,
+    /// Set while we're in the middle of a message
+    in_message: bool,
+    /// Sequence number the next fragment of the current message has to have
+    next_sequence: u8,
+    stats: DecoderStats,
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            frame: [0; MAX_FRAME_LEN],
+            frame_len: 0,
+            message: Aligned([0; N]),
+            message_len: 0,
+            in_message: false,
+            next_sequence: 0,
+            stats: DecoderStats {
+                skipped_bytes: 0,
+                corrupted_frames: 0,
+                dropped_messages: 0,
+            },
+        }
+    }
+
+    pub fn stats(&self) -> DecoderStats {
+        self.stats
+    }
+
+    /// Consumes bytes from `data` until a whole message has been reassembled, and returns it.
+    /// Returns `None` once `data` has been used up without completing a message, the partial
+    /// message is kept until the next call.
+    ///
+    /// The returned message is 8-byte aligned, so that it can be read in place by capnp.
+    pub fn decode(&mut self, data: &mut &[u8]) -> Option<&[u8]> {
+        loop {
+            match self.parse() {
+                Parse::Frame(frame_len) => {
+                    let complete = self.accept_frame(frame_len);
+                    self.consume(frame_len);
+                    if complete {
+                        return Some(&self.message.0[..self.message_len]);
+                    }
+                }
+                Parse::Invalid => self.resync(),
+                Parse::Incomplete(needed) => {
+                    if data.is_empty() {
+                        return None;
+                    }
+                    let (taken, rest) = data.split_at(needed.min(data.len()));
+                    self.frame[self.frame_len..self.frame_len + taken.len()].copy_from_slice(taken);
+                    self.frame_len += taken.len();
+                    *data = rest;
+                }
+            }
+        }
+    }
+
+    fn parse(&mut self) -> Parse {
+        let buffer = &self.frame[..self.frame_len];
+        if buffer.iter().zip(MAGIC).any(|(byte, magic)| *byte != magic) {
+            return Parse::Invalid;
+        }
+        if buffer.len() < HEADER_LEN {
+            return Parse::Incomplete(HEADER_LEN - buffer.len());
+        }
+
+        let payload_len = u16::from_le_bytes([buffer[4], buffer[5]]) as usize;
+        if payload_len > MAX_PAYLOAD_LEN {
+            self.stats.corrupted_frames += 1;
+            return Parse::Invalid;
+        }
+        let frame_len = payload_len + FRAME_OVERHEAD;
+        if buffer.len() < frame_len {
+            return Parse::Incomplete(frame_len - buffer.len());
+        }
+
+        let (content, crc) = buffer[..frame_len].split_at(HEADER_LEN + payload_len);
+        if crc32(content).to_le_bytes() != crc {
+            self.stats.corrupted_frames += 1;
+            return Parse::Invalid;
+        }
+        Parse::Frame(frame_len)
+    }
+
+    /// Adds the payload of the frame at the start of the buffer to the current message. Returns
+    /// `true` if that completed the message.
+    fn accept_frame(&mut self, frame_len: usize) -> bool {
+        let flags = self.frame[2];
+        let sequence = self.frame[3];
+        let payload = &self.frame[HEADER_LEN..frame_len - CRC_LEN];
+
+        if flags & FLAG_FIRST != 0 {
+            if self.in_message {
+                // The previous message never got its last fragment
+                self.stats.dropped_messages += 1;
+            }
+            self.in_message = true;
+            self.message_len = 0;
+        } else if !self.in_message {
+            // The rest of a message whose start we didn't see
+            return false;
+        } else if sequence != self.next_sequence {
+            self.stats.dropped_messages += 1;
+            self.in_message = false;
+            return false;
+        }
+
+        if self.message_len + payload.len() > N {
+            self.stats.dropped_messages += 1;
+            self.in_message = false;
+            return false;
+        }
+        self.message.0[self.message_len..self.message_len + payload.len()].copy_from_slice(payload);
+        self.message_len += payload.len();
+        self.next_sequence = sequence.wrapping_add(1);
+
+        if flags & FLAG_LAST != 0 {
+            self.in_message = false;
+            return true;
+        }
+        false
+    }
+
+    /// Removes the first `len` bytes of the buffer
+    fn consume(&mut self, len: usize) {
+        self.frame.copy_within(len..self.frame_len, 0);
+        self.frame_len -= len;
+    }
+
+    /// Skips to the next possible start of a frame in the buffer
+    fn resync(&mut self) {
+        let skip = self.frame[1..self.frame_len]
+            .iter()
+            .position(|byte| *byte == MAGIC[0])
+            .map_or(self.frame_len, |position| position + 1);
+        self.stats.skipped_bytes += skip as u32;
+        self.consume(skip);
+    }
+}
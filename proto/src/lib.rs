@@ -1,4 +1,63 @@
 #![cfg_attr(feature = "no_std", no_std)]
 
-capnp::generated_code!(pub mod to_edge, "proto/to_edge_capnp.rs");
-capnp::generated_code!(pub mod from_edge, "proto/from_edge_capnp.rs");
+pub use capnp;
+
+pub mod compression;
+pub mod framing;
+pub mod no_alloc;
+
+// The generated code refers to itself through `crate::<file>_capnp`, so the modules have to keep
+// the names capnpc gives them.
+capnp::generated_code!(pub mod to_edge_capnp, "proto/to_edge_capnp.rs");
+capnp::generated_code!(pub mod from_edge_capnp, "proto/from_edge_capnp.rs");
+
+/// UUID of the service the device advertises, in the little-endian byte order it is sent in
+pub const SERVICE_UUID: [u8; 16] = [
+    255, 77, 189, 23, 34, 96, 77, 13, 167, 102, 45, 228, 119, 88, 43, 141,
+];
+
+/// Largest value a 24-bit sample can take
+pub const SAMPLE_MAX: i32 = (1 << 23) - 1;
+/// Smallest value a 24-bit sample can take
+pub const SAMPLE_MIN: i32 = -(1 << 23);
+
+/// Sign-extends a raw 24-bit two's complement reading from the ADC. The top byte is ignored.
+pub const fn sign_extend_24(raw: u32) -> i32 {
+    ((raw << 8) as i32) >> 8
+}
+
+/// Version of the protocol described by the schemas in `proto/`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    /// Bumped for changes that peers on an older version can't understand
+    pub major: u16,
+    /// Bumped when fields or messages are added
+    pub minor: u16,
+}
+
+/// How well we can talk to a peer, based on the protocol version it reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    /// The peer understands everything we can send
+    Full,
+    /// The peer is on an older minor version, so it won't understand anything added since
+    Degraded,
+    /// The major versions differ, nothing sent by either side can be trusted
+    Incompatible,
+}
+
+impl ProtocolVersion {
+    /// Version implemented by this crate
+    pub const CURRENT: Self = Self { major: 1, minor: 7 };
+
+    /// How well a peer running `peer` can be talked to from this version
+    pub const fn compatibility(&self, peer: &Self) -> Compatibility {
+        if self.major != peer.major {
+            Compatibility::Incompatible
+        } else if peer.minor < self.minor {
+            Compatibility::Degraded
+        } else {
+            Compatibility::Full
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Encoding and decoding without a heap, as done on the firmware.
+//!
+//! Messages are built in a fixed-size scratch buffer, which has to be large enough to hold the
+//! whole message in a single segment. The bytes produced are the same as those of
+//! `capnp::serialize::write_message_to_words` on the host.
+//!
+//! Without a heap, capnp can't report running out of scratch space, it panics. Everything but text
+//! has a size known up front, so text is checked with [`check_text`] before it's set, and scratch
+//! buffers are sized for the largest message with text up to [`MAX_TEXT_LEN`] long.
+
+use capnp::message::{self, ReaderOptions, SingleSegmentAllocator};
+use capnp::serialize::{self, NoAllocSliceSegments};
+use capnp::traits::Owned;
+use capnp::{Error, ErrorKind, Word};
+
+/// Longest text put in a message built in a scratch buffer
+pub const MAX_TEXT_LEN: usize = 96;
+
+/// Scratch space to build messages of up to `WORDS` words (8 bytes each) in
+pub struct ScratchBuffer<const WORDS: usize> {
+    words: [Word; WORDS],
+}
+
+impl<const WORDS: usize> Default for ScratchBuffer<WORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const WORDS: usize> ScratchBuffer<WORDS> {
+    pub const fn new() -> Self {
+        Self {
+            words: [capnp::word(0, 0, 0, 0, 0, 0, 0, 0); WORDS],
+        }
+    }
+
+    /// Builds a message with a root of type `T` using `build`, and serializes it into `out`.
+    /// Returns the length of the serialized message.
+    ///
+    /// Fails if `out` is too small for the serialized message, or `build` fails, e.g. because
+    /// [`check_text`] turned down the text of a field.
+    pub fn encode<T: Owned>(
+        &mut self,
+        out: &mut [u8],
+        build: impl FnOnce(T::Builder<'_>) -> capnp::Result<()>,
+    ) -> capnp::Result<usize> {
+        let mut message = message::Builder::new(SingleSegmentAllocator::new(
+            Word::words_to_bytes_mut(&mut self.words),
+        ));
+        build(message.init_root())?;
+
+        let capacity = out.len();
+        let mut writer = out;
+        serialize::write_message(&mut writer, &message)?;
+        Ok(capacity - writer.len())
+    }
+}
+
+/// `text`, if it is at most [`MAX_TEXT_LEN`] bytes long. Fails with
+/// [`ErrorKind::BufferNotLargeEnough`] otherwise, before the text can overflow the scratch buffer.
+pub fn check_text(text: &str) -> capnp::Result<&str> {
+    if text.len() <= MAX_TEXT_LEN {
+        Ok(text)
+    } else {
+        Err(Error::from_kind(ErrorKind::BufferNotLargeEnough))
+    }
+}
+
+/// Reads a serialized message in place. `bytes` has to be 8-byte aligned, which is the case for
+/// messages returned by [`crate::framing::Decoder`].
+pub fn read(mut bytes: &[u8]) -> capnp::Result<message::Reader<NoAllocSliceSegments<'_>>> {
+    serialize::read_message_from_flat_slice_no_alloc(&mut bytes, ReaderOptions::new())
+}
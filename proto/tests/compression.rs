@@ -0,0 +1,147 @@
+use proptest::prelude::*;
+use proto::compression::{self, max_encoded_len, BlockInfo, Error, MAX_CHANNELS};
+use proto::{SAMPLE_MAX, SAMPLE_MIN};
+
+fn round_trip(samples: &[i32], channel_count: u8) -> usize {
+    let mut encoded = vec![0; max_encoded_len(channel_count as usize, samples.len())];
+    let len = compression::encode(samples, channel_count, &mut encoded).unwrap();
+
+    let info = compression::block_info(&encoded[..len]).unwrap();
+    assert_eq!(info.len(), samples.len());
+    let mut decoded = vec![0; info.len()];
+    let decoded_info = compression::decode(&encoded[..len], &mut decoded).unwrap();
+    assert_eq!(decoded_info, info);
+    assert_eq!(decoded, samples);
+    len
+}
+
+/// Interleaved samples of `channels` random walks with steps of up to `step`
+fn random_walks(channels: usize, step: i32) -> impl Strategy<Value = (u8, Vec<i32>)> {
+    (
+        prop::collection::vec(SAMPLE_MIN..=SAMPLE_MAX, channels),
+        prop::collection::vec(-step..=step, 0..300 * channels),
+    )
+        .prop_map(move |(start, steps)| {
+            let mut current = start;
+            let samples = steps
+                .chunks_exact(channels)
+                .flat_map(|set| {
+                    for (sample, step) in current.iter_mut().zip(set) {
+                        *sample = (*sample + step).clamp(SAMPLE_MIN, SAMPLE_MAX);
+                    }
+                    current.clone()
+                })
+                .collect();
+            (channels as u8, samples)
+        })
+}
+
+proptest! {
+    #[test]
+    fn uncorrelated_samples_round_trip(
+        (channel_count, samples) in (1..=MAX_CHANNELS).prop_flat_map(|channels| {
+            (
+                Just(channels as u8),
+                prop::collection::vec(SAMPLE_MIN..=SAMPLE_MAX, 0..200)
+                    .prop_map(move |mut samples| {
+                        samples.truncate(samples.len() / channels * channels);
+                        samples
+                    }),
+            )
+        })
+    ) {
+        round_trip(&samples, channel_count);
+    }
+
+    #[test]
+    fn small_steps_round_trip((channel_count, samples) in (1..=8usize).prop_flat_map(|channels| random_walks(channels, 50))) {
+        round_trip(&samples, channel_count);
+    }
+
+    #[test]
+    fn large_steps_round_trip((channel_count, samples) in (1..=8usize).prop_flat_map(|channels| random_walks(channels, 1 << 20))) {
+        round_trip(&samples, channel_count);
+    }
+
+    #[test]
+    fn garbage_never_panics(data in prop::collection::vec(any::<u8>(), 0..512)) {
+        let mut decoded = vec![0; MAX_CHANNELS * u16::MAX as usize];
+        let _ = compression::decode(&data, &mut decoded);
+    }
+
+    #[test]
+    fn truncated_block_is_corrupted(
+        (channel_count, samples) in random_walks(4, 1000),
+        cut in any::<prop::sample::Index>(),
+    ) {
+        let mut encoded = vec![0; max_encoded_len(channel_count as usize, samples.len())];
+        let len = compression::encode(&samples, channel_count, &mut encoded).unwrap();
+        let cut = cut.index(len);
+        let mut decoded = vec![0; samples.len()];
+        prop_assert_eq!(
+            compression::decode(&encoded[..cut], &mut decoded),
+            Err(Error::Corrupted)
+        );
+    }
+}
+
+#[test]
+fn extremes_round_trip() {
+    let samples = [
+        SAMPLE_MIN, SAMPLE_MAX, SAMPLE_MIN, SAMPLE_MAX, 0, -1, 1, SAMPLE_MAX,
+    ];
+    round_trip(&samples, 1);
+    round_trip(&samples, 2);
+    round_trip(&samples, 8);
+}
+
+#[test]
+fn constant_signal_compresses_well() {
+    let samples = vec![12_345; 8 * 250];
+    let len = round_trip(&samples, 8);
+    assert!(len * 16 < samples.len() * 3, "{len} bytes");
+}
+
+#[test]
+fn empty_block() {
+    let mut encoded = [0; 64];
+    let len = compression::encode(&[], 8, &mut encoded).unwrap();
+    assert_eq!(
+        compression::decode(&encoded[..len], &mut []),
+        Ok(BlockInfo {
+            channel_count: 8,
+            sample_sets: 0
+        })
+    );
+}
+
+#[test]
+fn invalid_input_is_rejected() {
+    let mut encoded = [0; 1024];
+    assert_eq!(
+        compression::encode(&[1, 2, 3], 2, &mut encoded),
+        Err(Error::InvalidChannelCount)
+    );
+    assert_eq!(
+        compression::encode(&[], 0, &mut encoded),
+        Err(Error::InvalidChannelCount)
+    );
+    assert_eq!(
+        compression::encode(&[SAMPLE_MAX + 1], 1, &mut encoded),
+        Err(Error::SampleOutOfRange)
+    );
+    assert_eq!(
+        compression::encode(&[0; 1 << 16], 1, &mut encoded),
+        Err(Error::TooManySamples)
+    );
+    assert_eq!(
+        compression::encode(&[SAMPLE_MIN, SAMPLE_MAX], 1, &mut encoded[..5]),
+        Err(Error::BufferTooSmall)
+    );
+
+    let len = compression::encode(&[1, 2, 3, 4], 2, &mut encoded).unwrap();
+    assert_eq!(
+        compression::decode(&encoded[..len], &mut [0; 3]),
+        Err(Error::BufferTooSmall)
+    );
+}
@@ -0,0 +1,263 @@
+//! Decodes every message in `corpus/`, encoded with each released version of the schema, with the
+//! current code. Renumbering a field or changing its type breaks these instead of old recordings.
+//!
+//! When releasing a new protocol version, add its messages to the tables of [`messages`] and
+//! snapshot them with `cargo test -p proto --test corpus -- --ignored`. Never change the files of a
+//! released version.
+
+mod messages;
+
+use capnp::message::{Builder, ReaderOptions};
+use capnp::serialize;
+use messages::{
+    build_from_edge, build_to_edge, APP_TO_NET_QUEUE, BAND_EDGES, BAND_POWER, FROM_EDGE, HOST_TIME,
+    NET_TO_APP_QUEUE, SAMPLES, SAMPLE_RATES, TO_EDGE,
+};
+use proto::from_edge_capnp::{
+    battery_status, from_edge, log_record, queue_stats, Core, ErrorCode, LogLevel,
+};
+use proto::to_edge_capnp::{to_edge, Gain};
+use proto::ProtocolVersion;
+use std::path::{Path, PathBuf};
+
+/// Request ids were added in 1.1, they read as 0 in older messages
+const REQUEST_IDS: ProtocolVersion = ProtocolVersion { major: 1, minor: 1 };
+/// Queue stats were added to the status in 1.4, they read as 0 in older messages
+const QUEUE_STATS: ProtocolVersion = ProtocolVersion { major: 1, minor: 4 };
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus")
+}
+
+/// Parses the name of a version directory, e.g. `v1.2`
+fn parse_version(name: &str) -> Option<ProtocolVersion> {
+    let (major, minor) = name.strip_prefix('v')?.split_once('.')?;
+    Some(ProtocolVersion {
+        major: major.parse().ok()?,
+        minor: minor.parse().ok()?,
+    })
+}
+
+/// Every message of the corpus, with the version it was encoded with
+fn corpus() -> Vec<(ProtocolVersion, PathBuf)> {
+    let mut messages = Vec::new();
+    for dir in std::fs::read_dir(corpus_dir()).unwrap() {
+        let dir = dir.unwrap().path();
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        let version = parse_version(name).unwrap_or_else(|| panic!("unexpected {dir:?}"));
+        for file in std::fs::read_dir(&dir).unwrap() {
+            messages.push((version, file.unwrap().path()));
+        }
+    }
+    messages.sort_by(|a, b| a.1.cmp(&b.1));
+    messages
+}
+
+fn expected_request_id(version: ProtocolVersion, table: &[(&str, u32)], name: &str) -> u32 {
+    let (_, id) = table
+        .iter()
+        .find(|(known, _)| *known == name)
+        .unwrap_or_else(|| panic!("no expected values for {name}"));
+    if is_before(version, REQUEST_IDS) {
+        0
+    } else {
+        *id
+    }
+}
+
+fn is_before(version: ProtocolVersion, added: ProtocolVersion) -> bool {
+    version.major == added.major && version.minor < added.minor
+}
+
+fn read_queue_stats(stats: queue_stats::Reader) -> [u32; 4] {
+    [
+        stats.get_sent(),
+        stats.get_dropped(),
+        stats.get_high_water(),
+        stats.get_capacity(),
+    ]
+}
+
+fn check_to_edge(version: ProtocolVersion, name: &str, root: to_edge::Reader) {
+    root.total_size().unwrap();
+    assert_eq!(
+        root.get_request_id(),
+        expected_request_id(version, TO_EDGE, name)
+    );
+    match (name, root.which().unwrap()) {
+        ("hello", to_edge::Hello(hello)) => {
+            let hello = hello.unwrap();
+            assert_eq!(hello.get_protocol_major(), version.major);
+            assert_eq!(hello.get_protocol_minor(), version.minor);
+        }
+        ("get_status", to_edge::GetStatus(())) => {}
+        ("set_sample_rate", to_edge::SetSampleRate(rate)) => assert_eq!(rate, 500),
+        ("set_channel_gain", to_edge::SetChannelGain(gain)) => {
+            let gain = gain.unwrap();
+            assert_eq!(gain.get_channel(), 5);
+            assert_eq!(gain.get_gain().unwrap(), Gain::X12);
+        }
+        ("set_channel_mask", to_edge::SetChannelMask(mask)) => assert_eq!(mask, 0b1010_0101),
+        ("set_time", to_edge::SetTime(time)) => assert_eq!(time, HOST_TIME),
+        ("set_log_level", to_edge::SetLogLevel(level)) => {
+            assert_eq!(level.unwrap(), LogLevel::Warn);
+        }
+        ("ping", to_edge::Ping(ping)) => assert_eq!(ping.unwrap().get_host_time(), HOST_TIME),
+        ("set_impedance_mode", to_edge::SetImpedanceMode(enabled)) => assert!(enabled),
+        ("set_stream_rate", to_edge::SetStreamRate(rate)) => assert_eq!(rate, 500),
+        _ => panic!("{name} decoded as the wrong variant"),
+    }
+}
+
+fn check_from_edge(version: ProtocolVersion, name: &str, root: from_edge::Reader) {
+    root.total_size().unwrap();
+    assert_eq!(
+        root.get_request_id(),
+        expected_request_id(version, FROM_EDGE, name)
+    );
+    match (name, root.which().unwrap()) {
+        ("status", from_edge::Status(status)) => {
+            let status = status.unwrap();
+            assert_eq!(status.get_hardware_rev().unwrap(), "rev-b");
+            assert_eq!(status.get_firmware_rev().unwrap(), "v0.3.1-4-g1a2b3c4");
+            let battery = status.get_battery().unwrap();
+            let Ok(battery_status::Discharging(level)) = battery.which() else {
+                panic!("expected a discharging battery");
+            };
+            let level = level.unwrap();
+            assert_eq!(level.get_percentage(), 72.5);
+            assert_eq!(level.get_estimated_time(), 14_400);
+            assert_eq!(status.get_uptime(), 3_600);
+            assert_eq!(status.get_recording_time(), 120);
+            assert_eq!(status.get_current_time(), 1_700_000_000);
+            assert_eq!(status.get_storage_total(), 1 << 30);
+            assert_eq!(status.get_storage_used(), 1 << 20);
+            assert_eq!(status.get_storage_free(), (1 << 30) - (1 << 20));
+            let queues = [
+                read_queue_stats(status.get_app_to_net_queue().unwrap()),
+                read_queue_stats(status.get_net_to_app_queue().unwrap()),
+            ];
+            if is_before(version, QUEUE_STATS) {
+                assert_eq!(queues, [[0; 4]; 2]);
+            } else {
+                assert_eq!(queues, [APP_TO_NET_QUEUE, NET_TO_APP_QUEUE]);
+            }
+        }
+        ("sample_frame", from_edge::SampleFrame(frame)) => {
+            let frame = frame.unwrap();
+            assert_eq!(frame.get_sample_counter(), 1_024);
+            assert_eq!(frame.get_timestamp(), 1_700_000_000_250_000);
+            assert_eq!(frame.get_sample_rate(), 250);
+            assert_eq!(frame.get_channel_count(), 2);
+            assert_eq!(frame.get_lead_off(), 0b10);
+            let samples: Vec<i32> = frame.get_samples().unwrap().iter().collect();
+            assert_eq!(samples, SAMPLES);
+        }
+        ("capabilities", from_edge::Capabilities(capabilities)) => {
+            let capabilities = capabilities.unwrap();
+            assert_eq!(capabilities.get_protocol_major(), version.major);
+            assert_eq!(capabilities.get_protocol_minor(), version.minor);
+            assert_eq!(capabilities.get_channel_count(), 8);
+            let rates: Vec<u32> = capabilities.get_sample_rates().unwrap().iter().collect();
+            assert_eq!(rates, SAMPLE_RATES);
+            assert!(capabilities.get_has_storage());
+            assert!(!capabilities.get_has_imu());
+        }
+        ("ack", from_edge::Ack(())) => {}
+        ("error", from_edge::Error(error)) => {
+            let error = error.unwrap();
+            assert_eq!(error.get_code().unwrap(), ErrorCode::Busy);
+            assert_eq!(error.get_message().unwrap(), "recording in progress");
+        }
+        ("log_record", from_edge::LogRecord(record)) => {
+            let record = record.unwrap();
+            assert_eq!(record.get_level().unwrap(), LogLevel::Warn);
+            assert_eq!(record.get_core().unwrap(), Core::Net);
+            assert_eq!(record.get_timestamp(), 5_000_000);
+            let Ok(log_record::Text(text)) = record.which() else {
+                panic!("expected a formatted record");
+            };
+            assert_eq!(text.unwrap(), "Lost 3 frames");
+        }
+        ("pong", from_edge::Pong(pong)) => {
+            let pong = pong.unwrap();
+            assert_eq!(pong.get_host_time(), HOST_TIME);
+            assert_eq!(pong.get_receive_time(), 1_700_000_000_130_000);
+            assert_eq!(pong.get_transmit_time(), 1_700_000_000_130_250);
+        }
+        ("band_power", from_edge::BandPower(band_power)) => {
+            let band_power = band_power.unwrap();
+            assert_eq!(band_power.get_sample_counter(), 2_048);
+            assert_eq!(band_power.get_timestamp(), 1_700_000_008_192_000);
+            assert_eq!(band_power.get_sample_count(), 640);
+            assert_eq!(band_power.get_sample_rate(), 250);
+            assert_eq!(band_power.get_channel_count(), 2);
+            let edges: Vec<f32> = band_power.get_band_edges().unwrap().iter().collect();
+            assert_eq!(edges, BAND_EDGES);
+            let power: Vec<f32> = band_power.get_power().unwrap().iter().collect();
+            assert_eq!(power, BAND_POWER);
+        }
+        ("contact_quality", from_edge::ContactQuality(contact)) => {
+            let contact = contact.unwrap();
+            assert_eq!(contact.get_channel(), 3);
+            assert_eq!(contact.get_timestamp(), 1_700_000_010_000_000);
+            assert_eq!(contact.get_test_current(), 6.0);
+            assert_eq!(contact.get_test_frequency(), 62.5);
+            assert_eq!(contact.get_amplitude(), 120.5);
+        }
+        _ => panic!("{name} decoded as the wrong variant"),
+    }
+}
+
+#[test]
+fn every_historical_message_decodes() {
+    let messages = corpus();
+    assert!(!messages.is_empty());
+    for (version, path) in messages {
+        let bytes = std::fs::read(&path).unwrap();
+        let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new())
+            .unwrap_or_else(|e| panic!("{path:?}: {e}"));
+        let stem = path.file_stem().unwrap().to_str().unwrap();
+        if let Some(name) = stem.strip_prefix("to_edge_") {
+            check_to_edge(version, name, message.get_root().unwrap());
+        } else if let Some(name) = stem.strip_prefix("from_edge_") {
+            check_from_edge(version, name, message.get_root().unwrap());
+        } else {
+            panic!("{path:?} isn't named after its root struct");
+        }
+    }
+}
+
+#[test]
+fn current_version_is_in_the_corpus() {
+    let ProtocolVersion { major, minor } = ProtocolVersion::CURRENT;
+    let dir = corpus_dir().join(format!("v{major}.{minor}"));
+    for (table, prefix) in [(TO_EDGE, "to_edge"), (FROM_EDGE, "from_edge")] {
+        for (name, _) in table {
+            let path = dir.join(format!("{prefix}_{name}.bin"));
+            assert!(path.exists(), "missing {path:?}, regenerate the corpus");
+        }
+    }
+}
+
+/// Snapshots the messages of the current version, to be run once when releasing it
+#[test]
+#[ignore]
+fn regenerate_current_version() {
+    let ProtocolVersion { major, minor } = ProtocolVersion::CURRENT;
+    let dir = corpus_dir().join(format!("v{major}.{minor}"));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    for (name, request_id) in TO_EDGE {
+        let mut message = Builder::new_default();
+        build_to_edge(name, *request_id, message.init_root());
+        let bytes = serialize::write_message_to_words(&message);
+        std::fs::write(dir.join(format!("to_edge_{name}.bin")), bytes).unwrap();
+    }
+    for (name, request_id) in FROM_EDGE {
+        let mut message = Builder::new_default();
+        build_from_edge(name, *request_id, message.init_root());
+        let bytes = serialize::write_message_to_words(&message);
+        std::fs::write(dir.join(format!("from_edge_{name}.bin")), bytes).unwrap();
+    }
+}
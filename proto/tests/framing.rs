@@ -0,0 +1,201 @@
+use proto::framing::{crc32, Decoder, Encoder, FRAME_OVERHEAD, MAX_FRAME_LEN};
+
+/// Encodes every message into one contiguous stream of frames
+fn encode_all(encoder: &mut Encoder, messages: &[&[u8]], max_frame_len: usize) -> Vec<Vec<u8>> {
+    let mut frames = Vec::new();
+    for message in messages {
+        let mut out = [0; MAX_FRAME_LEN];
+        let mut message_frames = encoder.frames(message, max_frame_len);
+        while let Some(len) = message_frames.next_into(&mut out) {
+            frames.push(out[..len].to_vec());
+        }
+    }
+    frames
+}
+
+/// Feeds `stream` to the decoder in chunks of `chunk_len` bytes
+fn decode_all<const N: usize>(
+    decoder: &mut Decoder<N>,
+    stream: &[u8],
+    chunk_len: usize,
+) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    for chunk in stream.chunks(chunk_len) {
+        let mut data = chunk;
+        while let Some(message) = decoder.decode(&mut data) {
+            messages.push(message.to_vec());
+        }
+        assert!(data.is_empty());
+    }
+    messages
+}
+
+fn test_message(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+        .collect()
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn single_frame_round_trip() {
+    let message = test_message(100, 1);
+    let frames = encode_all(&mut Encoder::new(), &[&message], MAX_FRAME_LEN);
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].len(), message.len() + FRAME_OVERHEAD);
+
+    let mut decoder = Decoder::<1024>::new();
+    assert_eq!(
+        decode_all(&mut decoder, &frames[0], MAX_FRAME_LEN),
+        [message]
+    );
+}
+
+#[test]
+fn empty_message_round_trip() {
+    let frames = encode_all(&mut Encoder::new(), &[&[]], MAX_FRAME_LEN);
+    assert_eq!(frames.len(), 1);
+
+    let mut decoder = Decoder::<16>::new();
+    assert_eq!(
+        decode_all(&mut decoder, &frames[0], MAX_FRAME_LEN),
+        [Vec::<u8>::new()]
+    );
+}
+
+#[test]
+fn frames_split_across_chunks() {
+    let messages = [
+        test_message(300, 1),
+        test_message(7, 2),
+        test_message(480, 3),
+    ];
+    let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    let stream = encode_all(&mut Encoder::new(), &refs, MAX_FRAME_LEN).concat();
+
+    for chunk_len in [1, 2, 3, 5, 13, 64, 511] {
+        let mut decoder = Decoder::<1024>::new();
+        assert_eq!(
+            decode_all(&mut decoder, &stream, chunk_len),
+            messages,
+            "chunk length {chunk_len}"
+        );
+        assert_eq!(decoder.stats(), Default::default());
+    }
+}
+
+#[test]
+fn frames_merged_into_one_chunk() {
+    let messages: Vec<Vec<u8>> = (0..10).map(|i| test_message(20 + i, i as u8)).collect();
+    let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    let stream = encode_all(&mut Encoder::new(), &refs, MAX_FRAME_LEN).concat();
+
+    let mut decoder = Decoder::<1024>::new();
+    assert_eq!(decode_all(&mut decoder, &stream, stream.len()), messages);
+}
+
+#[test]
+fn large_message_is_fragmented_and_reassembled() {
+    let message = test_message(5000, 9);
+    let frames = encode_all(&mut Encoder::new(), &[&message], MAX_FRAME_LEN);
+    assert!(frames.len() > 1);
+    assert!(frames.iter().all(|frame| frame.len() <= MAX_FRAME_LEN));
+
+    let mut decoder = Decoder::<8192>::new();
+    let mut decoded = Vec::new();
+    for frame in &frames {
+        decoded.extend(decode_all(&mut decoder, frame, frame.len()));
+    }
+    assert_eq!(decoded, [message]);
+}
+
+#[test]
+fn smaller_frames_than_the_mtu() {
+    let message = test_message(1000, 4);
+    let frames = encode_all(&mut Encoder::new(), &[&message], 64);
+    assert!(frames.iter().all(|frame| frame.len() <= 64));
+
+    let mut decoder = Decoder::<1024>::new();
+    assert_eq!(decode_all(&mut decoder, &frames.concat(), 100), [message]);
+}
+
+#[test]
+fn corrupted_frame_is_dropped_and_stream_recovers() {
+    let messages = [
+        test_message(50, 1),
+        test_message(60, 2),
+        test_message(70, 3),
+    ];
+    let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    let frames = encode_all(&mut Encoder::new(), &refs, MAX_FRAME_LEN);
+
+    for corrupted_byte in 0..frames[1].len() {
+        let mut frames = frames.clone();
+        frames[1][corrupted_byte] ^= 0x10;
+
+        let mut decoder = Decoder::<1024>::new();
+        let decoded = decode_all(&mut decoder, &frames.concat(), 17);
+        assert_eq!(
+            decoded,
+            [messages[0].clone(), messages[2].clone()],
+            "corrupted byte {corrupted_byte}"
+        );
+        assert!(decoder.stats().skipped_bytes > 0);
+    }
+}
+
+#[test]
+fn garbage_between_frames_is_skipped() {
+    let messages = [test_message(30, 1), test_message(40, 2)];
+    let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    let frames = encode_all(&mut Encoder::new(), &refs, MAX_FRAME_LEN);
+
+    // Includes a fake start of a frame
+    let garbage = [0x00, 0xEE, 0x6A, 0x03, 0xFF, 0xEE, 0x12];
+    let stream = [&garbage[..], &frames[0], &garbage, &frames[1]].concat();
+
+    let mut decoder = Decoder::<1024>::new();
+    assert_eq!(decode_all(&mut decoder, &stream, 9), messages);
+    assert_eq!(decoder.stats().skipped_bytes, 2 * garbage.len() as u32);
+}
+
+#[test]
+fn lost_fragment_drops_the_message() {
+    let messages = [test_message(1200, 1), test_message(100, 2)];
+    let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    let mut frames = encode_all(&mut Encoder::new(), &refs, MAX_FRAME_LEN);
+    assert_eq!(frames.len(), 4);
+    frames.remove(1);
+
+    let mut decoder = Decoder::<2048>::new();
+    assert_eq!(
+        decode_all(&mut decoder, &frames.concat(), 100),
+        [messages[1].clone()]
+    );
+    assert_eq!(decoder.stats().dropped_messages, 1);
+}
+
+#[test]
+fn message_too_large_for_the_decoder_is_dropped() {
+    let messages = [test_message(600, 1), test_message(100, 2)];
+    let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    let stream = encode_all(&mut Encoder::new(), &refs, MAX_FRAME_LEN).concat();
+
+    let mut decoder = Decoder::<512>::new();
+    assert_eq!(decode_all(&mut decoder, &stream, 64), [messages[1].clone()]);
+    assert_eq!(decoder.stats().dropped_messages, 1);
+}
+
+#[test]
+fn decoded_messages_are_word_aligned() {
+    let stream = encode_all(&mut Encoder::new(), &[&test_message(10, 1)], MAX_FRAME_LEN).concat();
+    let mut decoder = Decoder::<64>::new();
+    let mut data = &stream[..];
+    let message = decoder.decode(&mut data).unwrap();
+    assert_eq!(message.as_ptr() as usize % 8, 0);
+}
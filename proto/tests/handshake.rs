@@ -0,0 +1,66 @@
+use capnp::message::{Builder, ReaderOptions};
+use capnp::serialize;
+use proto::from_edge_capnp::from_edge;
+use proto::to_edge_capnp::to_edge;
+use proto::{Compatibility, ProtocolVersion};
+
+#[test]
+fn hello_round_trip() {
+    let mut message = Builder::new_default();
+    let mut hello = message.init_root::<to_edge::Builder>().init_hello();
+    hello.set_protocol_major(ProtocolVersion::CURRENT.major);
+    hello.set_protocol_minor(ProtocolVersion::CURRENT.minor);
+    let bytes = serialize::write_message_to_words(&message);
+
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<to_edge::Reader>().unwrap();
+    let Ok(to_edge::Hello(hello)) = root.which() else {
+        panic!("expected a hello");
+    };
+    let hello = hello.unwrap();
+    assert_eq!(hello.get_protocol_major(), ProtocolVersion::CURRENT.major);
+    assert_eq!(hello.get_protocol_minor(), ProtocolVersion::CURRENT.minor);
+}
+
+#[test]
+fn capabilities_round_trip() {
+    let mut message = Builder::new_default();
+    {
+        let mut capabilities = message
+            .init_root::<from_edge::Builder>()
+            .init_capabilities();
+        capabilities.set_protocol_major(1);
+        capabilities.set_protocol_minor(3);
+        capabilities.set_channel_count(8);
+        capabilities.set_sample_rates(&[250, 500, 1000]).unwrap();
+        capabilities.set_has_storage(true);
+        capabilities.set_has_imu(false);
+    }
+    let bytes = serialize::write_message_to_words(&message);
+
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    let Ok(from_edge::Capabilities(capabilities)) = root.which() else {
+        panic!("expected capabilities");
+    };
+    let capabilities = capabilities.unwrap();
+    assert_eq!(capabilities.get_protocol_major(), 1);
+    assert_eq!(capabilities.get_protocol_minor(), 3);
+    assert_eq!(capabilities.get_channel_count(), 8);
+    let rates: Vec<u32> = capabilities.get_sample_rates().unwrap().iter().collect();
+    assert_eq!(rates, [250, 500, 1000]);
+    assert!(capabilities.get_has_storage());
+    assert!(!capabilities.get_has_imu());
+}
+
+#[test]
+fn compatibility_between_versions() {
+    let ours = ProtocolVersion { major: 2, minor: 3 };
+    let check = |major, minor| ours.compatibility(&ProtocolVersion { major, minor });
+
+    assert_eq!(check(2, 3), Compatibility::Full);
+    assert_eq!(check(2, 7), Compatibility::Full);
+    assert_eq!(check(2, 0), Compatibility::Degraded);
+    assert_eq!(check(1, 3), Compatibility::Incompatible);
+    assert_eq!(check(3, 3), Compatibility::Incompatible);
+}
@@ -0,0 +1,48 @@
+use capnp::message::{Builder, ReaderOptions};
+use capnp::serialize;
+use proto::from_edge_capnp::{from_edge, log_record, Core, LogLevel};
+use proto::to_edge_capnp::to_edge;
+
+#[test]
+fn log_record_round_trip() {
+    let mut message = Builder::new_default();
+    {
+        let mut record = message.init_root::<from_edge::Builder>().init_log_record();
+        record.set_level(LogLevel::Warn);
+        record.set_core(Core::Net);
+        record.set_timestamp(12_345_678);
+        record.set_text("Dropped 3 log records");
+    }
+    let bytes = serialize::write_message_to_words(&message);
+
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    assert_eq!(root.get_request_id(), 0);
+    let Ok(from_edge::LogRecord(record)) = root.which() else {
+        panic!("expected a log record");
+    };
+    let record = record.unwrap();
+    assert_eq!(record.get_level().unwrap(), LogLevel::Warn);
+    assert_eq!(record.get_core().unwrap(), Core::Net);
+    assert_eq!(record.get_timestamp(), 12_345_678);
+    let Ok(log_record::Text(text)) = record.which() else {
+        panic!("expected text");
+    };
+    assert_eq!(text.unwrap(), "Dropped 3 log records");
+}
+
+#[test]
+fn set_log_level_round_trip() {
+    let mut message = Builder::new_default();
+    message
+        .init_root::<to_edge::Builder>()
+        .set_set_log_level(LogLevel::Debug);
+    let bytes = serialize::write_message_to_words(&message);
+
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<to_edge::Reader>().unwrap();
+    let Ok(to_edge::SetLogLevel(level)) = root.which() else {
+        panic!("expected a log level");
+    };
+    assert_eq!(level.unwrap(), LogLevel::Debug);
+}
@@ -0,0 +1,159 @@
+//! Messages of the corpus, as built with the current schema. Shared with the firmware, which
+//! checks that it encodes them to the same bytes without a heap.
+
+use proto::from_edge_capnp::{from_edge, queue_stats, Core, ErrorCode, LogLevel};
+use proto::to_edge_capnp::{to_edge, Gain};
+use proto::ProtocolVersion;
+
+/// Messages sent to the device, with the request id they're sent with
+pub const TO_EDGE: &[(&str, u32)] = &[
+    ("hello", 1),
+    ("get_status", 2),
+    ("set_sample_rate", 3),
+    ("set_channel_gain", 4),
+    ("set_channel_mask", 5),
+    ("set_time", 6),
+    ("set_log_level", 7),
+    ("ping", 8),
+    ("set_impedance_mode", 9),
+    ("set_stream_rate", 10),
+];
+
+/// Messages sent by the device, with the request id they're sent with
+pub const FROM_EDGE: &[(&str, u32)] = &[
+    ("status", 2),
+    ("sample_frame", 0),
+    ("capabilities", 1),
+    ("ack", 3),
+    ("error", 4),
+    ("log_record", 0),
+    ("pong", 8),
+    ("band_power", 0),
+    ("contact_quality", 0),
+];
+
+pub const SAMPLES: [i32; 6] = [0, -1, proto::SAMPLE_MAX, proto::SAMPLE_MIN, 12_345, -54_321];
+pub const SAMPLE_RATES: [u32; 7] = [250, 500, 1000, 2000, 4000, 8000, 16000];
+pub const HOST_TIME: u64 = 1_700_000_000_123_456;
+/// Sent, dropped, high water and capacity of the application → network queue
+pub const APP_TO_NET_QUEUE: [u32; 4] = [120, 3, 16, 16];
+/// Sent, dropped, high water and capacity of the network → application queue
+pub const NET_TO_APP_QUEUE: [u32; 4] = [40, 0, 2, 16];
+pub const BAND_EDGES: [f32; 6] = [0.5, 4.0, 8.0, 13.0, 30.0, 45.0];
+/// Of two channels, five bands each
+pub const BAND_POWER: [f32; 10] = [
+    1_200.5, 800.25, 2_400.0, 300.75, 45.5, 0.0, 1e-3, 1e9, 12.0, 6.5,
+];
+
+fn set_queue_stats(
+    mut stats: queue_stats::Builder,
+    [sent, dropped, high_water, capacity]: [u32; 4],
+) {
+    stats.set_sent(sent);
+    stats.set_dropped(dropped);
+    stats.set_high_water(high_water);
+    stats.set_capacity(capacity);
+}
+
+pub fn build_to_edge(name: &str, request_id: u32, mut root: to_edge::Builder) {
+    root.set_request_id(request_id);
+    match name {
+        "hello" => {
+            let mut hello = root.init_hello();
+            hello.set_protocol_major(ProtocolVersion::CURRENT.major);
+            hello.set_protocol_minor(ProtocolVersion::CURRENT.minor);
+        }
+        "get_status" => root.set_get_status(()),
+        "set_sample_rate" => root.set_set_sample_rate(500),
+        "set_channel_gain" => {
+            let mut gain = root.init_set_channel_gain();
+            gain.set_channel(5);
+            gain.set_gain(Gain::X12);
+        }
+        "set_channel_mask" => root.set_set_channel_mask(0b1010_0101),
+        "set_time" => root.set_set_time(HOST_TIME),
+        "set_log_level" => root.set_set_log_level(LogLevel::Warn),
+        "ping" => root.init_ping().set_host_time(HOST_TIME),
+        "set_impedance_mode" => root.set_set_impedance_mode(true),
+        "set_stream_rate" => root.set_set_stream_rate(500),
+        _ => panic!("don't know how to build {name}"),
+    }
+}
+
+pub fn build_from_edge(name: &str, request_id: u32, mut root: from_edge::Builder) {
+    root.set_request_id(request_id);
+    match name {
+        "status" => {
+            let mut status = root.init_status();
+            status.set_hardware_rev("rev-b");
+            status.set_firmware_rev("v0.3.1-4-g1a2b3c4");
+            let mut level = status.reborrow().init_battery().init_discharging();
+            level.set_percentage(72.5);
+            level.set_estimated_time(14_400);
+            status.set_uptime(3_600);
+            status.set_recording_time(120);
+            status.set_current_time(1_700_000_000);
+            status.set_storage_total(1 << 30);
+            status.set_storage_used(1 << 20);
+            status.set_storage_free((1 << 30) - (1 << 20));
+            set_queue_stats(status.reborrow().init_app_to_net_queue(), APP_TO_NET_QUEUE);
+            set_queue_stats(status.init_net_to_app_queue(), NET_TO_APP_QUEUE);
+        }
+        "sample_frame" => {
+            let mut frame = root.init_sample_frame();
+            frame.set_sample_counter(1_024);
+            frame.set_timestamp(1_700_000_000_250_000);
+            frame.set_sample_rate(250);
+            frame.set_channel_count(2);
+            frame.set_lead_off(0b10);
+            frame.set_samples(&SAMPLES[..]).unwrap();
+        }
+        "capabilities" => {
+            let mut capabilities = root.init_capabilities();
+            capabilities.set_protocol_major(ProtocolVersion::CURRENT.major);
+            capabilities.set_protocol_minor(ProtocolVersion::CURRENT.minor);
+            capabilities.set_channel_count(8);
+            capabilities.set_sample_rates(&SAMPLE_RATES[..]).unwrap();
+            capabilities.set_has_storage(true);
+            capabilities.set_has_imu(false);
+        }
+        "ack" => root.set_ack(()),
+        "error" => {
+            let mut error = root.init_error();
+            error.set_code(ErrorCode::Busy);
+            error.set_message("recording in progress");
+        }
+        "log_record" => {
+            let mut record = root.init_log_record();
+            record.set_level(LogLevel::Warn);
+            record.set_core(Core::Net);
+            record.set_timestamp(5_000_000);
+            record.set_text("Lost 3 frames");
+        }
+        "pong" => {
+            let mut pong = root.init_pong();
+            pong.set_host_time(HOST_TIME);
+            pong.set_receive_time(1_700_000_000_130_000);
+            pong.set_transmit_time(1_700_000_000_130_250);
+        }
+        "band_power" => {
+            let mut band_power = root.init_band_power();
+            band_power.set_sample_counter(2_048);
+            band_power.set_timestamp(1_700_000_008_192_000);
+            band_power.set_sample_count(640);
+            band_power.set_sample_rate(250);
+            band_power.set_channel_count(2);
+            band_power.set_band_edges(&BAND_EDGES[..]).unwrap();
+            band_power.set_power(&BAND_POWER[..]).unwrap();
+        }
+        "contact_quality" => {
+            let mut contact = root.init_contact_quality();
+            contact.set_channel(3);
+            contact.set_timestamp(1_700_000_010_000_000);
+            contact.set_test_current(6.0);
+            contact.set_test_frequency(62.5);
+            contact.set_amplitude(120.5);
+        }
+        _ => panic!("don't know how to build {name}"),
+    }
+}
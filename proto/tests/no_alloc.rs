@@ -0,0 +1,163 @@
+//! The firmware builds messages in fixed scratch buffers, without an allocator. These check that
+//! it puts the exact same bytes on the wire as the host does.
+
+use capnp::message::{Builder, ReaderOptions};
+use capnp::serialize;
+use capnp::traits::Owned;
+use capnp::ErrorKind;
+use proto::framing::{Decoder, Encoder, MAX_FRAME_LEN};
+use proto::from_edge_capnp::{from_edge, ErrorCode};
+use proto::no_alloc::{self, ScratchBuffer};
+use proto::to_edge_capnp::{to_edge, Gain};
+use proto::ProtocolVersion;
+
+/// Encodes the message built by `build` both ways, and checks that the bytes are the same
+fn assert_identical<T: Owned>(build: impl Fn(T::Builder<'_>) -> capnp::Result<()>) -> Vec<u8> {
+    let mut message = Builder::new_default();
+    build(message.init_root::<T::Builder<'_>>()).unwrap();
+    let expected = serialize::write_message_to_words(&message);
+
+    let mut out = [0; 1024];
+    let len = ScratchBuffer::<128>::new()
+        .encode::<T>(&mut out, build)
+        .unwrap();
+    assert_eq!(&out[..len], &expected[..]);
+    expected
+}
+
+fn build_sample_frame(root: from_edge::Builder) -> capnp::Result<()> {
+    let samples: Vec<i32> = (0..64).map(|i| (i - 32) * 100_000).collect();
+    let mut frame = root.init_sample_frame();
+    frame.set_sample_counter(123_456);
+    frame.set_timestamp(1_700_000_000_000_000);
+    frame.set_sample_rate(500);
+    frame.set_channel_count(8);
+    frame.set_lead_off(0b0100_0001);
+    frame.set_samples(&samples[..])
+}
+
+#[test]
+fn sample_frame_is_identical() {
+    assert_identical::<from_edge::Owned>(build_sample_frame);
+}
+
+#[test]
+fn status_report_is_identical() {
+    assert_identical::<from_edge::Owned>(|mut root| {
+        root.set_request_id(7);
+        let mut status = root.init_status();
+        status.set_hardware_rev("rev-b");
+        status.set_firmware_rev("0.1.0");
+        let mut level = status.reborrow().init_battery().init_discharging();
+        level.set_percentage(81.5);
+        level.set_estimated_time(7200);
+        status.set_uptime(3600);
+        status.set_current_time(1_700_000_000_000_000);
+        status.set_storage_total(1 << 20);
+        status.set_storage_used(1 << 10);
+        status.set_storage_free((1 << 20) - (1 << 10));
+        Ok(())
+    });
+}
+
+#[test]
+fn capabilities_are_identical() {
+    assert_identical::<from_edge::Owned>(|mut root| {
+        root.set_request_id(1);
+        let mut capabilities = root.init_capabilities();
+        capabilities.set_protocol_major(ProtocolVersion::CURRENT.major);
+        capabilities.set_protocol_minor(ProtocolVersion::CURRENT.minor);
+        capabilities.set_channel_count(8);
+        capabilities.set_sample_rates(&[250, 500, 1000, 2000, 4000, 8000, 16000])?;
+        capabilities.set_has_storage(true);
+        Ok(())
+    });
+}
+
+#[test]
+fn error_is_identical() {
+    assert_identical::<from_edge::Owned>(|mut root| {
+        root.set_request_id(u32::MAX);
+        let mut error = root.init_error();
+        error.set_code(ErrorCode::Busy);
+        error.set_message("Already recording");
+        Ok(())
+    });
+}
+
+#[test]
+fn commands_are_identical() {
+    assert_identical::<to_edge::Owned>(|mut root| {
+        root.set_request_id(3);
+        let mut gain = root.init_set_channel_gain();
+        gain.set_channel(5);
+        gain.set_gain(Gain::X12);
+        Ok(())
+    });
+    assert_identical::<to_edge::Owned>(|mut root| {
+        root.set_request_id(4);
+        root.set_set_time(1_700_000_000_000_000);
+        Ok(())
+    });
+}
+
+#[test]
+fn output_too_small_is_an_error() {
+    let mut out = [0; 16];
+    let result =
+        ScratchBuffer::<128>::new().encode::<from_edge::Owned>(&mut out, build_sample_frame);
+    assert!(result.is_err());
+}
+
+/// Error reply as the firmware builds it, in a scratch buffer of the size it uses for replies
+fn encode_error(message: &str) -> capnp::Result<usize> {
+    let mut out = [0; 1024];
+    ScratchBuffer::<64>::new().encode::<from_edge::Owned>(&mut out, |mut root| {
+        root.set_request_id(u32::MAX);
+        let mut error = root.init_error();
+        error.set_code(ErrorCode::Unknown);
+        error.set_message(no_alloc::check_text(message)?);
+        Ok(())
+    })
+}
+
+#[test]
+fn longest_text_fits_in_a_reply() {
+    let message = "x".repeat(no_alloc::MAX_TEXT_LEN);
+    assert!(encode_error(&message).is_ok());
+}
+
+#[test]
+fn longer_text_is_an_error_instead_of_overflowing() {
+    let message = "x".repeat(64 * 8);
+    let error = encode_error(&message).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::BufferNotLargeEnough);
+}
+
+#[test]
+fn read_in_place_after_framing() {
+    let bytes = assert_identical::<from_edge::Owned>(build_sample_frame);
+
+    let mut encoder = Encoder::new();
+    let mut frames = encoder.frames(&bytes, MAX_FRAME_LEN);
+    let mut stream = Vec::new();
+    let mut frame = [0; MAX_FRAME_LEN];
+    while let Some(len) = frames.next_into(&mut frame) {
+        stream.extend_from_slice(&frame[..len]);
+    }
+
+    let mut decoder = Decoder::<1024>::new();
+    let message = decoder.decode(&mut &stream[..]).unwrap();
+    let message = no_alloc::read(message).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    let Ok(from_edge::SampleFrame(frame)) = root.which() else {
+        panic!("expected a sample frame");
+    };
+    let frame = frame.unwrap();
+    assert_eq!(frame.get_sample_counter(), 123_456);
+    assert_eq!(frame.get_samples().unwrap().len(), 64);
+
+    // The host reads the same bytes with an allocator
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    assert!(message.get_root::<from_edge::Reader>().is_ok());
+}
@@ -0,0 +1,68 @@
+use capnp::message::{Builder, ReaderOptions};
+use capnp::serialize;
+use proto::from_edge_capnp::{from_edge, sample_frame};
+
+const CHANNELS: u8 = 4;
+
+fn encode_frame(counter: u64, samples: &[i32]) -> Vec<u8> {
+    let mut message = Builder::new_default();
+    let mut frame = message
+        .init_root::<from_edge::Builder>()
+        .init_sample_frame();
+    frame.set_sample_counter(counter);
+    frame.set_timestamp(1_700_000_000_000_000);
+    frame.set_sample_rate(250);
+    frame.set_channel_count(CHANNELS);
+    frame.set_lead_off(0b1010);
+    frame.set_samples(samples).unwrap();
+    serialize::write_message_to_words(&message)
+}
+
+fn decode_frame(bytes: &[u8], check: impl FnOnce(sample_frame::Reader)) {
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    match root.which().unwrap() {
+        from_edge::SampleFrame(frame) => check(frame.unwrap()),
+        _ => panic!("expected a sample frame"),
+    }
+}
+
+#[test]
+fn sample_frame_round_trip() {
+    let samples: Vec<i32> = (0..CHANNELS as i32 * 10)
+        .map(|i| i * 1000 - 20_000)
+        .collect();
+    let bytes = encode_frame(42, &samples);
+
+    decode_frame(&bytes, |frame| {
+        assert_eq!(frame.get_sample_counter(), 42);
+        assert_eq!(frame.get_timestamp(), 1_700_000_000_000_000);
+        assert_eq!(frame.get_sample_rate(), 250);
+        assert_eq!(frame.get_channel_count(), CHANNELS);
+        assert_eq!(frame.get_lead_off(), 0b1010);
+        let decoded: Vec<i32> = frame.get_samples().unwrap().iter().collect();
+        assert_eq!(decoded, samples);
+    });
+}
+
+#[test]
+fn sample_frame_keeps_full_24_bit_range() {
+    let samples = [proto::SAMPLE_MIN, -1, 0, proto::SAMPLE_MAX];
+    let bytes = encode_frame(u64::MAX, &samples);
+
+    decode_frame(&bytes, |frame| {
+        assert_eq!(frame.get_sample_counter(), u64::MAX);
+        let decoded: Vec<i32> = frame.get_samples().unwrap().iter().collect();
+        assert_eq!(decoded, samples);
+    });
+}
+
+#[test]
+fn sign_extend_24_bit_samples() {
+    assert_eq!(proto::sign_extend_24(0x00_0000), 0);
+    assert_eq!(proto::sign_extend_24(0x7F_FFFF), proto::SAMPLE_MAX);
+    assert_eq!(proto::sign_extend_24(0x80_0000), proto::SAMPLE_MIN);
+    assert_eq!(proto::sign_extend_24(0xFF_FFFF), -1);
+    // The top byte is ignored
+    assert_eq!(proto::sign_extend_24(0xAB00_0000 | 0xFF_FFFF), -1);
+}
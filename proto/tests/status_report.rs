@@ -0,0 +1,48 @@
+use capnp::message::{Builder, ReaderOptions};
+use capnp::serialize;
+use proto::from_edge_capnp::{battery_status, from_edge};
+
+#[test]
+fn status_report_round_trip() {
+    let mut message = Builder::new_default();
+    {
+        let mut report = message.init_root::<from_edge::Builder>().init_status();
+        report.set_hardware_rev("rev-b");
+        report.set_firmware_rev("v0.1.0-3-g1234abc");
+        report.set_uptime(3600);
+        report.set_recording_time(120);
+        report.set_current_time(1_700_000_000);
+        report.set_storage_total(16 << 20);
+        report.set_storage_used(4 << 20);
+        report.set_storage_free(12 << 20);
+        let mut level = report.init_battery().init_discharging();
+        level.set_percentage(87.5);
+        level.set_estimated_time(7200);
+    }
+    let bytes = serialize::write_message_to_words(&message);
+
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    let Ok(from_edge::Status(report)) = root.which() else {
+        panic!("expected a status report");
+    };
+    let report = report.unwrap();
+
+    assert_eq!(report.get_hardware_rev().unwrap(), "rev-b");
+    assert_eq!(report.get_firmware_rev().unwrap(), "v0.1.0-3-g1234abc");
+    assert_eq!(report.get_uptime(), 3600);
+    assert_eq!(report.get_recording_time(), 120);
+    assert_eq!(report.get_current_time(), 1_700_000_000);
+    assert_eq!(report.get_storage_total(), 16 << 20);
+    assert_eq!(report.get_storage_used(), 4 << 20);
+    assert_eq!(report.get_storage_free(), 12 << 20);
+
+    match report.get_battery().unwrap().which().unwrap() {
+        battery_status::Discharging(level) => {
+            let level = level.unwrap();
+            assert_eq!(level.get_percentage(), 87.5);
+            assert_eq!(level.get_estimated_time(), 7200);
+        }
+        _ => panic!("expected a discharging battery"),
+    }
+}
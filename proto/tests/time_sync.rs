@@ -0,0 +1,46 @@
+use capnp::message::{Builder, ReaderOptions};
+use capnp::serialize;
+use proto::from_edge_capnp::from_edge;
+use proto::to_edge_capnp::to_edge;
+
+#[test]
+fn ping_round_trip() {
+    let mut message = Builder::new_default();
+    message
+        .init_root::<to_edge::Builder>()
+        .init_ping()
+        .set_host_time(1_700_000_000_123_456);
+    let bytes = serialize::write_message_to_words(&message);
+
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<to_edge::Reader>().unwrap();
+    let Ok(to_edge::Ping(ping)) = root.which() else {
+        panic!("expected a ping");
+    };
+    assert_eq!(ping.unwrap().get_host_time(), 1_700_000_000_123_456);
+}
+
+#[test]
+fn pong_round_trip() {
+    let mut message = Builder::new_default();
+    {
+        let mut root = message.init_root::<from_edge::Builder>();
+        root.set_request_id(9);
+        let mut pong = root.init_pong();
+        pong.set_host_time(1_700_000_000_123_456);
+        pong.set_receive_time(1_700_000_000_130_000);
+        pong.set_transmit_time(1_700_000_000_130_250);
+    }
+    let bytes = serialize::write_message_to_words(&message);
+
+    let message = serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap();
+    let root = message.get_root::<from_edge::Reader>().unwrap();
+    assert_eq!(root.get_request_id(), 9);
+    let Ok(from_edge::Pong(pong)) = root.which() else {
+        panic!("expected a pong");
+    };
+    let pong = pong.unwrap();
+    assert_eq!(pong.get_host_time(), 1_700_000_000_123_456);
+    assert_eq!(pong.get_receive_time(), 1_700_000_000_130_000);
+    assert_eq!(pong.get_transmit_time(), 1_700_000_000_130_250);
+}